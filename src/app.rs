@@ -1,8 +1,8 @@
-use dioxus::prelude::*;
+use dioxus::{desktop::{tao::event::{Event, WindowEvent}, use_wry_event_handler}, prelude::*};
 use directories::UserDirs;
-use tokio::sync::mpsc::channel;
+use tokio::sync::mpsc::{channel, unbounded_channel};
 
-use crate::{ICON, MAIN_CSS, app_context::{DatabaseContext, PlayerContext}, audio_controller::AudioController, audio_controller_command::AudioControllerCommand, file_browser::file_browser, player::player_sidebar, queue_bar::queue_bar};
+use crate::{ICON, MAIN_CSS, session::SESSION_SAVE_INTERVAL_MS, app_context::{DatabaseContext, PlayerContext}, audio_controller::AudioController, audio_controller_command::AudioControllerCommand, audio_controller_event::AudioControllerEvent, file_browser::file_browser, player::player_sidebar, queue_bar::queue_bar, toast::toast_list};
 
 #[component]
 pub fn App() -> Element {
@@ -16,26 +16,61 @@ pub fn App() -> Element {
         "/".to_string()
     };
 
+    let db_ctx = use_context_provider(|| DatabaseContext::new());
+
     let ctx = use_hook(|| -> PlayerContext {
     
         let (cmd_snd, cmd_rcv) = channel::<AudioControllerCommand>(10);
+        let (evt_snd, mut evt_rcv) = unbounded_channel::<AudioControllerEvent>();
         
-        let player_ctx = PlayerContext::new(cmd_snd.clone());
+        let player_ctx = PlayerContext::new(cmd_snd.clone(), db_ctx.arc());
         
+        let output_device = player_ctx.output_device.peek().clone();
         spawn(async move {
-            let mut audio = AudioController::new(cmd_rcv, player_ctx.playing_state.clone()); 
+            let mut audio = AudioController::new(cmd_rcv, evt_snd, output_device); 
             audio.run().await;
         });
 
+        let mut event_ctx = player_ctx.clone();
+        spawn(async move {
+            while let Some(event) = evt_rcv.recv().await {
+                event_ctx.handle_audio_event(event);
+            }
+        });
+
         player_ctx
     });
 
+    let session_ctx = ctx.clone();
+    use_wry_event_handler(move |event, _| {
+        if let Event::WindowEvent { event: WindowEvent::CloseRequested, .. } = event {
+            session_ctx.finish_play(false);
+            session_ctx.save_session();
+        }
+    });
+
+    let session_ctx = ctx.clone();
+    use_future(move || {
+        let session_ctx = session_ctx.clone();
+        async move {
+            let mut last_saved = None;
+            loop {
+                tokio::time::sleep(tokio::time::Duration::from_millis(SESSION_SAVE_INTERVAL_MS)).await;
+                let session = session_ctx.capture_session();
+                if last_saved.as_ref() != Some(&session) {
+                    if let Err(e) = session.save(session_ctx.db()) {
+                        eprintln!("Failed to save session: {:?}", e);
+                    }
+                    last_saved = Some(session);
+                }
+            }
+        }
+    });
+
     let _ = use_context_provider(|| {
         ctx
     });
 
-    let _ = use_context_provider(|| DatabaseContext::new());
-
     rsx! {
         style { "{MAIN_CSS}" }
         document::Link { rel: "icon", href: ICON }
@@ -47,6 +82,7 @@ pub fn App() -> Element {
                 queue_bar { }
             }
             player_sidebar { }    
+            toast_list { }
         }
     }
 }
@@ -1,14 +1,30 @@
+use std::collections::HashSet;
+use std::path::PathBuf;
 use std::sync::Arc;
 
 
 use dioxus::prelude::*;
-use tokio::sync::mpsc::{Sender};
+use serde::{Deserialize, Serialize};
+use tokio::sync::mpsc::{Sender, unbounded_channel};
 
 use crate::audio_controller_command::AudioControllerCommand;
-use crate::db::Db;
+use crate::audio_controller_event::AudioControllerEvent;
+use crate::custom_tags::write_rating;
+use crate::db::{Db, SongView};
+use crate::equalizer::{EqualizerPreset, EqualizerSettings};
+use crate::library_scanner::{LibraryScan, ScanProgress, index_files, load_library_roots, save_library_roots};
+use crate::library_watcher::LibraryWatcher;
 
 use crate::player_playing_state::PlayerPlayingState;
-use crate::queue_state::QueueState;
+use crate::play_history::PlayInProgress;
+use crate::loudness::LoudnessAnalysis;
+use crate::output_device::OUTPUT_DEVICE_SETTING;
+use crate::queue_state::{QueueFallbackMode, QueueSettings, QueueState, StopAfter};
+use crate::replay_gain::ReplayGainSettings;
+use crate::session::{SAVED_HISTORY_LEN, Session, SessionFallback};
+use crate::shuffle::{SHUFFLE_STRATEGY_SETTING, ShuffleStrategy};
+use crate::tempo::{MAX_SPEED, MIN_SPEED};
+use crate::toast::{Toast, TOAST_DURATION_MS};
 
 #[derive(Clone)]
 pub struct DatabaseContext(std::sync::Arc<Db>);
@@ -33,10 +49,10 @@ impl PartialEq for DatabaseContext {
     }
 }
 
-#[derive(Clone, Copy, Debug, Eq)]
+#[derive(Clone, Copy, Debug, Eq, Serialize, Deserialize)]
 pub enum PlaybackMode {
+    #[serde(alias = "Loop")] //sessions from before repeat was its own setting
     Normal,
-    Loop,
     Shuffle,
 }
 
@@ -44,7 +60,6 @@ impl PartialEq for PlaybackMode {
     fn eq(&self, other: &Self) -> bool {
         match (self, other) {
             (PlaybackMode::Normal, PlaybackMode::Normal) => true,
-            (PlaybackMode::Loop, PlaybackMode::Loop) => true,
             (PlaybackMode::Shuffle, PlaybackMode::Shuffle) => true,
             _ => false,
         }
@@ -54,38 +69,133 @@ impl PartialEq for PlaybackMode {
         !self.eq(other)
     }
 }
+/// Whether the current song or the whole folder, album or playlist starts over once it ends.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RepeatMode {
+    #[default]
+    Off,
+    One,
+    All,
+}
+
+impl RepeatMode {
+    pub fn cycle(&self) -> RepeatMode {
+        match self {
+            RepeatMode::Off => RepeatMode::All,
+            RepeatMode::All => RepeatMode::One,
+            RepeatMode::One => RepeatMode::Off,
+        }
+    }
+}
+
+pub const CROSSFADE_SETTING: &str = "crossfade_ms";
+pub const SPEED_SETTING: &str = "playback_speed";
+pub const PRESERVE_PITCH_SETTING: &str = "preserve_pitch";
+
 #[derive(Clone)]
 pub struct PlayerContext {
     pub playing_state: Signal<PlayerPlayingState>,
     volume: Signal<f32>,
     pub speed: Signal<f32>,
+    pub preserve_pitch: Signal<bool>,
+    pub crossfade_ms: Signal<u64>,
+    pub replay_gain: Signal<ReplayGainSettings>,
+    loudness_analysis: Signal<Option<LoudnessAnalysis>>,
+    pub equalizer: Signal<EqualizerSettings>,
+    pub album_eq_preset: Signal<Option<EqualizerPreset>>,
+    pub eq_presets: Signal<Vec<EqualizerPreset>>,
+    pub output_device: Signal<Option<String>>,
+    pub toasts: Signal<Vec<Toast>>,
+    pub library_roots: Signal<Vec<String>>,
+    library_scan: Signal<Option<LibraryScan>>,
+    pub scan_progress: Signal<Option<ScanProgress>>,
+    library_watcher: Signal<Option<LibraryWatcher>>,
+    indexed_paths: Signal<HashSet<PathBuf>>, //files already handed to `index_files`, so ones that fail to read aren't retried on every refresh
+    pub library_update_counter: Signal<u64>,
+    pub selected_songs: Signal<Vec<String>>, //paths picked with ctrl-click for batch tag editing
     pub mode: Signal<PlaybackMode>,
+    pub repeat: Signal<RepeatMode>,
+    pub stop_after: Signal<StopAfter>,
+    pub shuffle_strategy: Signal<ShuffleStrategy>,
     command_sender: Sender<AudioControllerCommand>,
     pub playlist_update_counter: Signal<u64>,
     pub queue: Signal<QueueState>,
+    current_play: Signal<Option<PlayInProgress>>,
+    pub play_history_counter: Signal<u64>,
+    db: Arc<Db>,
 }
 
 impl PlayerContext {
-    pub fn new(sender: Sender<AudioControllerCommand>) -> Self {
+    pub fn new(sender: Sender<AudioControllerCommand>, db: Arc<Db>) -> Self {
+        let session = Session::load(&db);
         let playing_state = Signal::new(PlayerPlayingState::NoSongSelected);
-        let mode = Signal::new(PlaybackMode::Normal);
-        PlayerContext {
+        let mode = Signal::new(session.mode);
+        let repeat = Signal::new(session.repeat);
+        let stop_after = Signal::new(StopAfter::Never);
+        let shuffle_strategy = Signal::new(ShuffleStrategy::load(&db));
+        let crossfade_ms = db.get_setting(CROSSFADE_SETTING).ok().flatten()
+            .and_then(|value| value.parse().ok())
+            .unwrap_or(0);
+        let speed = db.get_setting(SPEED_SETTING).ok().flatten()
+            .and_then(|value| value.parse().ok())
+            .unwrap_or(1.0);
+        let preserve_pitch = db.get_setting(PRESERVE_PITCH_SETTING).ok().flatten()
+            .is_none_or(|value| value == "true");
+        let replay_gain = ReplayGainSettings::load(&db);
+        let equalizer = EqualizerSettings::load(&db);
+        let eq_presets = db.get_eq_presets().unwrap_or_default();
+        let output_device = db.get_setting(OUTPUT_DEVICE_SETTING).ok().flatten()
+            .filter(|name| !name.is_empty());
+        let mut player_ctx = PlayerContext {
             playing_state: playing_state.clone(),
-            volume: Signal::new(1.0),
-            speed: Signal::new(1.0),
+            volume: Signal::new(session.volume),
+            speed: Signal::new(speed),
+            preserve_pitch: Signal::new(preserve_pitch),
+            crossfade_ms: Signal::new(crossfade_ms),
+            replay_gain: Signal::new(replay_gain),
+            loudness_analysis: Signal::new(replay_gain.analyze_untagged.then(LoudnessAnalysis::start)),
+            equalizer: Signal::new(equalizer.clone()),
+            album_eq_preset: Signal::new(None),
+            eq_presets: Signal::new(eq_presets),
+            output_device: Signal::new(output_device),
+            toasts: Signal::new(Vec::new()),
+            library_roots: Signal::new(load_library_roots(&db)),
+            library_scan: Signal::new(None),
+            scan_progress: Signal::new(None),
+            library_watcher: Signal::new(None),
+            indexed_paths: Signal::new(HashSet::new()),
+            library_update_counter: Signal::new(0),
+            selected_songs: Signal::new(Vec::new()),
             mode: mode.clone(),
+            repeat,
+            stop_after,
+            shuffle_strategy,
             command_sender: sender.clone(),
             playlist_update_counter: Signal::new(0),
-            queue: Signal::new(QueueState::new(playing_state.clone(), sender.clone(), mode.clone())),
-        }
+            queue: Signal::new(QueueState::new(playing_state.clone(), sender.clone(), QueueSettings { mode, repeat, stop_after, shuffle_strategy }, &session, &db)),
+            current_play: Signal::new(None),
+            play_history_counter: Signal::new(0),
+            db,
+        };
+        player_ctx.send_cmd(AudioControllerCommand::SetVolume(session.volume));
+        player_ctx.send_cmd(AudioControllerCommand::SetCrossfade(crossfade_ms));
+        player_ctx.send_cmd(AudioControllerCommand::SetSpeed(speed));
+        player_ctx.send_cmd(AudioControllerCommand::SetPreservePitch(preserve_pitch));
+        player_ctx.send_cmd(AudioControllerCommand::SetReplayGain(replay_gain));
+        player_ctx.send_cmd(AudioControllerCommand::SetEqualizer(equalizer));
+        player_ctx.start_library_scan();
+        player_ctx.restart_library_watcher();
+        player_ctx
     }
 
     pub fn previous_song(&mut self) {
         self.queue.write().previous_song();
     }
 
+    /// Skips to the next song, also when repeating the current one.
     pub fn next_song(&mut self) {
-        self.queue.write().next_song();
+        self.queue.write().next_song(true);
     }
 
     pub fn set_volume(&mut self, volume: f32) {
@@ -98,6 +208,399 @@ impl PlayerContext {
         *self.volume.read()
     }
 
+    pub fn set_speed(&mut self, speed: f32) {
+        let speed = speed.clamp(MIN_SPEED, MAX_SPEED);
+        self.send_cmd(AudioControllerCommand::SetSpeed(speed));
+        self.speed.set(speed);
+        if let Err(e) = self.db.set_setting(SPEED_SETTING, &speed.to_string()) {
+            eprintln!("Failed to save speed setting: {:?}", e);
+        }
+    }
+
+    pub fn get_speed(&self) -> f32 {
+        *self.speed.read()
+    }
+
+    pub fn set_preserve_pitch(&mut self, preserve_pitch: bool) {
+        self.send_cmd(AudioControllerCommand::SetPreservePitch(preserve_pitch));
+        self.preserve_pitch.set(preserve_pitch);
+        if let Err(e) = self.db.set_setting(PRESERVE_PITCH_SETTING, &preserve_pitch.to_string()) {
+            eprintln!("Failed to save pitch setting: {:?}", e);
+        }
+    }
+
+    pub fn set_replay_gain(&mut self, settings: ReplayGainSettings) {
+        self.send_cmd(AudioControllerCommand::SetReplayGain(settings));
+        if settings.analyze_untagged != self.replay_gain.read().analyze_untagged {
+            if let Some(analysis) = self.loudness_analysis.write().take() {
+                analysis.cancel();
+            }
+            if settings.analyze_untagged {
+                self.loudness_analysis.set(Some(LoudnessAnalysis::start()));
+            }
+        }
+        self.replay_gain.set(settings);
+        if let Err(e) = settings.save(&self.db) {
+            eprintln!("Failed to save replay gain settings: {:?}", e);
+        }
+    }
+
+    /// The equalizer being heard, the album's preset if it has one, otherwise the default curve.
+    pub fn active_equalizer(&self) -> EqualizerSettings {
+        match &*self.album_eq_preset.read() {
+            Some(preset) => preset.settings.clone(),
+            None => self.equalizer.read().clone(),
+        }
+    }
+
+    /// Edits the active equalizer, writing through to the album preset when one is in use.
+    pub fn set_equalizer(&mut self, settings: EqualizerSettings) {
+        self.send_cmd(AudioControllerCommand::SetEqualizer(settings.clone()));
+        let album_preset = self.album_eq_preset.peek().clone();
+        if let Some(mut preset) = album_preset {
+            if let Err(e) = self.db.save_eq_preset(&preset.name, &settings) {
+                eprintln!("Failed to save equalizer preset: {:?}", e);
+            }
+            preset.settings = settings;
+            self.album_eq_preset.set(Some(preset));
+            self.reload_eq_presets();
+        } else {
+            if let Err(e) = settings.save(&self.db) {
+                eprintln!("Failed to save equalizer settings: {:?}", e);
+            }
+            self.equalizer.set(settings);
+        }
+    }
+
+    /// Switches to the preset of the given album, or back to the default curve when it has none.
+    pub fn apply_album_equalizer(&mut self, album_id: Option<i32>) {
+        let preset = album_id
+            .and_then(|album_id| self.db.get_album_eq_preset(album_id).ok().flatten());
+        let settings = match &preset {
+            Some(preset) => preset.settings.clone(),
+            None => self.equalizer.peek().clone(),
+        };
+        self.album_eq_preset.set(preset);
+        self.send_cmd(AudioControllerCommand::SetEqualizer(settings));
+    }
+
+    pub fn set_album_eq_preset(&mut self, album_id: i32, preset_id: Option<i32>) {
+        if let Err(e) = self.db.set_album_eq_preset(album_id, preset_id) {
+            eprintln!("Failed to set album equalizer preset: {:?}", e);
+        }
+        self.apply_album_equalizer(Some(album_id));
+    }
+
+    pub fn save_eq_preset(&mut self, name: &str) {
+        let settings = self.active_equalizer();
+        if let Err(e) = self.db.save_eq_preset(name, &settings) {
+            eprintln!("Failed to save equalizer preset: {:?}", e);
+        }
+        self.reload_eq_presets();
+    }
+
+    pub fn delete_eq_preset(&mut self, preset_id: i32) {
+        if let Err(e) = self.db.delete_eq_preset(preset_id) {
+            eprintln!("Failed to delete equalizer preset: {:?}", e);
+        }
+        if self.album_eq_preset.peek().as_ref().is_some_and(|preset| preset.id == preset_id) {
+            self.album_eq_preset.set(None);
+            self.send_cmd(AudioControllerCommand::SetEqualizer(self.equalizer.peek().clone()));
+        }
+        self.reload_eq_presets();
+    }
+
+    fn reload_eq_presets(&mut self) {
+        self.eq_presets.set(self.db.get_eq_presets().unwrap_or_default());
+    }
+
+    /// Switches the output to the named device, `None` meaning the system default.
+    pub fn set_output_device(&mut self, device: Option<String>) {
+        self.send_cmd(AudioControllerCommand::SetOutputDevice(device.clone()));
+        let result = match &device {
+            Some(name) => self.db.set_setting(OUTPUT_DEVICE_SETTING, name),
+            None => self.db.set_setting(OUTPUT_DEVICE_SETTING, ""),
+        };
+        if let Err(e) = result {
+            eprintln!("Failed to save output device setting: {:?}", e);
+        }
+        self.output_device.set(device);
+    }
+
+    pub fn set_library_roots(&mut self, roots: Vec<String>) {
+        if let Err(e) = save_library_roots(&self.db, &roots) {
+            eprintln!("Failed to save library roots: {:?}", e);
+        }
+        self.library_roots.set(roots);
+        self.restart_library_watcher();
+    }
+
+    fn restart_library_watcher(&mut self) {
+        //dropping the old watcher stops it
+        self.library_watcher.set(None);
+        let roots = self.library_roots.peek().clone();
+        if roots.is_empty() {
+            return;
+        }
+        let (sender, mut receiver) = unbounded_channel::<()>();
+        self.library_watcher.set(LibraryWatcher::start(&roots, sender));
+        let mut library_update_counter = self.library_update_counter;
+        spawn(async move {
+            while receiver.recv().await.is_some() {
+                library_update_counter += 1;
+            }
+        });
+    }
+
+    /// Walks the library roots in the background, does nothing when a scan is already running.
+    pub fn start_library_scan(&mut self) {
+        let roots = self.library_roots.peek().clone();
+        if roots.is_empty() || self.library_scan.peek().is_some() {
+            return;
+        }
+        let (sender, mut receiver) = unbounded_channel::<ScanProgress>();
+        self.library_scan.set(Some(LibraryScan::start(roots, sender)));
+        self.scan_progress.set(Some(ScanProgress::default()));
+        let mut ctx = self.clone();
+        spawn(async move {
+            while let Some(progress) = receiver.recv().await {
+                ctx.scan_progress.set(Some(progress));
+            }
+            ctx.finish_library_scan();
+        });
+    }
+
+    /// Reads the files into the library in the background, the file browser refreshes as each batch is written.
+    pub fn index_files(&mut self, paths: Vec<PathBuf>) {
+        let paths: Vec<PathBuf> = {
+            let mut indexed = self.indexed_paths.write();
+            paths.into_iter().filter(|path| indexed.insert(path.clone())).collect()
+        };
+        if paths.is_empty() {
+            return;
+        }
+        let (sender, mut receiver) = unbounded_channel::<ScanProgress>();
+        index_files(paths, sender);
+        let mut library_update_counter = self.library_update_counter;
+        spawn(async move {
+            while receiver.recv().await.is_some() {
+                library_update_counter += 1;
+            }
+        });
+    }
+
+    pub fn cancel_library_scan(&self) {
+        if let Some(scan) = &*self.library_scan.peek() {
+            scan.cancel();
+        }
+    }
+
+    fn finish_library_scan(&mut self) {
+        self.library_scan.set(None);
+        self.library_update_counter += 1;
+        if let Some(progress) = self.scan_progress.take() {
+            let outcome = if progress.cancelled { "cancelled" } else { "finished" };
+            self.notify(format!(
+                "Library scan {}: {} added, {} updated, {} failed",
+                outcome, progress.added, progress.updated, progress.failed
+            ));
+        }
+        //new songs may lack ReplayGain tags, restart the analysis so it picks them up
+        if self.replay_gain.peek().analyze_untagged {
+            if let Some(analysis) = self.loudness_analysis.write().take() {
+                analysis.cancel();
+            }
+            self.loudness_analysis.set(Some(LoudnessAnalysis::start()));
+        }
+    }
+
+    pub fn set_crossfade(&mut self, duration_ms: u64) {
+        self.send_cmd(AudioControllerCommand::SetCrossfade(duration_ms));
+        self.crossfade_ms.set(duration_ms);
+        if let Err(e) = self.db.set_setting(CROSSFADE_SETTING, &duration_ms.to_string()) {
+            eprintln!("Failed to save crossfade setting: {:?}", e);
+        }
+    }
+
+    pub fn set_repeat(&mut self, repeat: RepeatMode) {
+        self.repeat.set(repeat);
+        //the preloaded song was picked under the old setting
+        self.queue.write().discard_preloaded();
+    }
+
+    /// Stops once the current song or the play next queue is done, the next song stays cued.
+    pub fn set_stop_after(&mut self, stop_after: StopAfter) {
+        self.stop_after.set(stop_after);
+        self.queue.write().discard_preloaded_past_stop();
+    }
+
+    pub fn set_shuffle_strategy(&mut self, strategy: ShuffleStrategy) {
+        self.shuffle_strategy.set(strategy);
+        if let Err(e) = self.db.set_setting(SHUFFLE_STRATEGY_SETTING, strategy.as_str()) {
+            eprintln!("Failed to save shuffle setting: {:?}", e);
+        }
+    }
+
+    /// Shows a message to the user for a few seconds.
+    pub fn notify(&mut self, message: String) {
+        let toast = Toast::new(message);
+        let id = toast.id;
+        self.toasts.write().push(toast);
+        let mut toasts = self.toasts;
+        spawn(async move {
+            tokio::time::sleep(tokio::time::Duration::from_millis(TOAST_DURATION_MS)).await;
+            toasts.write().retain(|toast| toast.id != id);
+        });
+    }
+
+    /// Sets the star rating of a song, 0 clears it.
+    pub fn set_rating(&mut self, song_id: i32, stars: u8) {
+        match self.db.set_song_rating(song_id, stars) {
+            Ok(()) => self.library_update_counter += 1,
+            Err(e) => self.notify(format!("Failed to save rating: {}", e)),
+        }
+    }
+
+    pub fn set_loved(&mut self, song_id: i32, loved: bool) {
+        match self.db.set_song_loved(song_id, loved) {
+            Ok(()) => self.library_update_counter += 1,
+            Err(e) => self.notify(format!("Failed to save rating: {}", e)),
+        }
+    }
+
+    /// Writes the stored rating of a song into its file's tags.
+    pub fn write_rating_to_file(&mut self, song_id: i32) {
+        let song = match self.db.get_song_view(song_id) {
+            Ok(Some(song)) => song,
+            _ => return,
+        };
+        match write_rating(&song.path, song.rating.stars) {
+            Ok(()) => self.notify(format!("Saved rating to {}", song.title)),
+            Err(e) => self.notify(format!("Failed to save rating to file: {}", e)),
+        }
+    }
+
+    fn is_current_song(&self, song: &SongView) -> bool {
+        self.playing_state.read().current_song().is_some_and(|current| current.id == song.id)
+    }
+
+    /// Records the play of the current song, `ended` when it played out.
+    pub fn finish_play(&self, ended: bool) {
+        let mut current_play = self.current_play;
+        let mut play_history_counter = self.play_history_counter;
+        let play = current_play.write().take();
+        if let Some(play) = play {
+            play.finish(&self.db, ended, *self.crossfade_ms.peek());
+            play_history_counter += 1;
+        }
+    }
+
+    pub fn handle_audio_event(&mut self, event: AudioControllerEvent) {
+        match event {
+            AudioControllerEvent::Progress(progress_ms) => {
+                self.playing_state.with_mut(|state| {
+                    if let Some(progress) = state.progress_mut() {
+                        *progress = progress_ms;
+                    }
+                });
+                if let Some(play) = self.current_play.write().as_mut() {
+                    play.progress(progress_ms);
+                }
+            },
+            AudioControllerEvent::TrackStarted(song) => {
+                self.finish_play(false);
+                self.current_play.set(Some(PlayInProgress::start(&song)));
+                if self.queue.read().is_preloaded(&song) {
+                    self.queue.write().advance_to_preloaded();
+                } else if !self.is_current_song(&song) {
+                    *self.playing_state.write() = PlayerPlayingState::Playing { song, progress_ms: 0 };
+                }
+            },
+            AudioControllerEvent::TrackEnded(song) => {
+                if self.current_play.peek().as_ref().is_some_and(|play| play.song_id() == song.id) {
+                    self.finish_play(true);
+                }
+                if self.playing_state.read().is_playing() && self.is_current_song(&song) {
+                    *self.playing_state.write() = PlayerPlayingState::SongFinished { song };
+                }
+            },
+            AudioControllerEvent::NextTrackWanted => {
+                let next = self.queue.write().preload_next();
+                if let Some(song) = next {
+                    self.send_cmd(AudioControllerCommand::Preload(song));
+                }
+            },
+            AudioControllerEvent::PreloadDropped => {
+                self.queue.write().discard_preloaded();
+            },
+            AudioControllerEvent::DecodeFailed(song) => {
+                self.notify(format!("Could not play {}", song.title));
+                if self.queue.read().is_preloaded(&song) {
+                    self.queue.write().skip_preloaded();
+                } else if self.is_current_song(&song) {
+                    *self.playing_state.write() = PlayerPlayingState::SongFinished { song };
+                }
+            },
+            AudioControllerEvent::SeekFailed(position_ms) => {
+                let seconds = position_ms / 1000;
+                self.notify(format!("Could not seek to {:02}:{:02}", seconds / 60, seconds % 60));
+            },
+            AudioControllerEvent::DeviceLost => {
+                self.notify("Output device lost, switching to the default device".to_string());
+            },
+            AudioControllerEvent::BufferUnderrun => {
+                self.notify("Playback stalled, the file is not loading fast enough".to_string());
+            },
+        }
+    }
+
+    pub fn capture_session(&self) -> Session {
+        let queue = self.queue.peek();
+        let fallback = match &*queue.current_fallback_queue.peek() {
+            QueueFallbackMode::Playlist { playlist } => SessionFallback::Playlist {
+                playlist_id: playlist.id(),
+                index: playlist.current_index(),
+            },
+            QueueFallbackMode::Folder { path, current_item, .. } => SessionFallback::Folder {
+                path: path.clone(),
+                item: *current_item,
+            },
+            QueueFallbackMode::Album { album_id, current_item, .. } => SessionFallback::Album {
+                album_id: *album_id,
+                item: *current_item,
+            },
+            QueueFallbackMode::PlaylistFile { path, current_item, .. } => SessionFallback::PlaylistFile {
+                path: path.clone(),
+                item: *current_item,
+            },
+            QueueFallbackMode::None => SessionFallback::None,
+        };
+        let play_next = queue.play_next_queue.peek().iter().map(|song| song.id).collect();
+        let history = queue.last_played.peek();
+        let history = history.iter().skip(history.len().saturating_sub(SAVED_HISTORY_LEN)).map(|song| song.id).collect();
+        let state = self.playing_state.peek();
+        Session {
+            play_next,
+            history,
+            fallback,
+            current_song: state.current_song().map(|song| song.id),
+            position_ms: state.progress(),
+            volume: *self.volume.peek(),
+            mode: *self.mode.peek(),
+            repeat: *self.repeat.peek(),
+        }
+    }
+
+    pub fn db(&self) -> &Db {
+        &self.db
+    }
+
+    pub fn save_session(&self) {
+        if let Err(e) = self.capture_session().save(&self.db) {
+            eprintln!("Failed to save session: {:?}", e);
+        }
+    }
+
     pub fn play(&mut self) {
         self.send_cmd(AudioControllerCommand::Play);
         self.playing_state.write().play();
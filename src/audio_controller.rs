@@ -1,24 +1,86 @@
+use std::{f32::consts::FRAC_PI_2, fs::File, io::BufReader, sync::{Arc, atomic::{AtomicBool, Ordering}}, time::{Duration, Instant}};
 
-use std::{fs::File, io::BufReader};
-
-use dioxus::signals::{ReadableExt, Signal, WritableExt};
-use rodio::{Decoder, OutputStream, Sink, StreamError};
-use tokio::sync::mpsc::{Receiver};
+use rodio::{Decoder, OutputStream, Sink, Source, StreamError, mixer::Mixer};
+use tokio::sync::mpsc::{Receiver, UnboundedSender};
 use uuid::Error;
 
-use crate::{audio_controller_command::AudioControllerCommand, player_playing_state::PlayerPlayingState};
+use crate::{audio_controller_command::AudioControllerCommand, audio_controller_event::AudioControllerEvent, db::SongView, equalizer::{EqualizerControl, EqualizerSource}, output_device::open_output_stream, replay_gain::{GainSource, ReplayGainSettings, TrackGain}, tempo::{PlaybackPosition, SpeedControl, TempoSource}};
+
+const PRELOAD_AHEAD_MS: u64 = 5000; //how long before the end of a track the next one gets appended to the sink
+const STALL_TIMEOUT_MS: u64 = 1000; //how long the position may stand still while playing before reporting an underrun
+
+type TrackSource = GainSource<TempoSource<EqualizerSource<Decoder<BufReader<File>>>>>;
+
+struct Track {
+    song: SongView,
+    source: TrackSource,
+    position: PlaybackPosition,
+    length: Option<Duration>,
+    gain: TrackGain,
+}
+
+enum PreloadedTrack {
+    Appended { song: SongView, cancelled: Arc<AtomicBool>, position: PlaybackPosition, length: Option<Duration>, gain: TrackGain },
+    Crossfade { track: Track },
+}
+
+struct Crossfade {
+    outgoing: Sink,
+    start_pos: Duration,
+    duration: Duration,
+}
 
+struct Resume {
+    position: Duration,
+    paused: bool,
+}
 
 pub struct AudioController {
     receiver: Receiver<AudioControllerCommand>,
-    playing_state: Signal<PlayerPlayingState>,
+    events: UnboundedSender<AudioControllerEvent>,
+    volume: f32,
+    speed: SpeedControl,
+    replay_gain: ReplayGainSettings,
+    equalizer: EqualizerControl,
+    crossfade_duration: Duration,
+    current_song: Option<SongView>,
+    current_position: Option<PlaybackPosition>,
+    current_gain: Option<TrackGain>,
+    current_length: Option<Duration>,
+    preloaded: Option<PreloadedTrack>,
+    preload_requested: bool,
+    crossfade: Option<Crossfade>,
+    output_device: Option<String>,
+    device_lost: Arc<AtomicBool>,
+    resume: Option<Resume>,
+    last_progress_ms: u64,
+    stalled_since: Instant,
+    underrun_reported: bool,
 }
 
 impl AudioController {
-    pub fn new(receiver: Receiver<AudioControllerCommand>, playing_state: Signal<PlayerPlayingState>) -> Self {
+    pub fn new(receiver: Receiver<AudioControllerCommand>, events: UnboundedSender<AudioControllerEvent>, output_device: Option<String>) -> Self {
         AudioController {
             receiver,
-            playing_state,
+            events,
+            volume: 1.0,
+            speed: SpeedControl::new(),
+            replay_gain: ReplayGainSettings::default(),
+            equalizer: EqualizerControl::new(),
+            crossfade_duration: Duration::ZERO,
+            current_song: None,
+            current_position: None,
+            current_gain: None,
+            current_length: None,
+            preloaded: None,
+            preload_requested: false,
+            crossfade: None,
+            output_device,
+            device_lost: Arc::new(AtomicBool::new(false)),
+            resume: None,
+            last_progress_ms: 0,
+            stalled_since: Instant::now(),
+            underrun_reported: false,
         }
     }
 
@@ -26,81 +88,358 @@ impl AudioController {
         loop {
             if let Err(e) = self.update_loop().await {
                 eprintln!("AudioController encountered an error: {}", e);
+                tokio::time::sleep(tokio::time::Duration::from_secs(1)).await;
             }
         }
     }
 
     pub async fn update_loop(&mut self) -> Result<(), StreamError> {
-        let stream_handle = rodio::OutputStreamBuilder::open_default_stream()?;
-        let mut sink = rodio::Sink::connect_new(&stream_handle.mixer());
+        self.device_lost.store(false, Ordering::SeqCst);
+        let stream_handle = open_output_stream(self.output_device.as_deref(), self.device_lost.clone())?;
+        let mixer = stream_handle.mixer().clone();
+        let mut sink = rodio::Sink::connect_new(&mixer);
+        sink.set_volume(self.volume);
+        if let Some(resume) = self.resume.take() {
+            self.resume(&mut sink, resume);
+        }
         loop {
             tokio::select! {
-                Some(cmd) = self.receiver.recv() => self.handle_command(cmd, &mut sink).await,
+                Some(cmd) = self.receiver.recv() => {
+                    if let AudioControllerCommand::SetOutputDevice(device) = cmd {
+                        self.output_device = device;
+                        self.suspend(&sink);
+                        return Ok(());
+                    }
+                    self.handle_command(cmd, &mut sink).await
+                },
                 _ = tokio::time::sleep(tokio::time::Duration::from_millis(20)) => {
-                    if !sink.is_paused() {
-                        self.playing_state.with_mut(|state| {
-                            if let Some(progress) = state.progress_mut(){
-                                *progress = sink.get_pos().as_millis() as u64;
-                                if let PlayerPlayingState::Playing { song, .. } = state.clone() {
-                                    if sink.empty() {
-                                        *state = PlayerPlayingState::SongFinished { song };
-                                    }
-                                }
-                            }
-                        });
+                    if self.device_lost.load(Ordering::SeqCst) {
+                        eprintln!("Output device lost, reopening the stream");
+                        self.emit(AudioControllerEvent::DeviceLost);
+                        self.suspend(&sink);
+                        return Ok(());
                     }
-                }
+                    self.tick(&mut sink, &mixer)
+                },
             }
         };
     }
 
+    fn emit(&self, event: AudioControllerEvent) {
+        if let Err(e) = self.events.send(event) {
+            eprintln!("Failed to send audio controller event: {:?}", e);
+        }
+    }
+
+    /// Remembers where the current track is before the output stream gets dropped.
+    /// A preloaded track is dropped with the stream, it gets requested again after resuming.
+    fn suspend(&mut self, sink: &Sink) {
+        if self.resume.is_none() && !sink.empty() {
+            self.resume = Some(Resume { position: self.position(sink), paused: sink.is_paused() });
+        }
+        if let Some(crossfade) = self.crossfade.take() {
+            crossfade.outgoing.stop();
+        }
+        if self.preloaded.take().is_some() {
+            self.emit(AudioControllerEvent::PreloadDropped);
+        }
+        self.preload_requested = false;
+    }
+
+    /// Reopens the current track on a new stream at the position it was suspended at.
+    fn resume(&mut self, sink: &mut Sink, resume: Resume) {
+        let Some(song) = self.current_song.clone() else {
+            return;
+        };
+        let Some(track) = self.open_track(&song) else {
+            eprintln!("Failed to reopen song after switching output: {}", song.path);
+            self.current_song = None;
+            self.emit(AudioControllerEvent::DecodeFailed(song));
+            return;
+        };
+        self.set_current(track.song, track.position, track.length, track.gain);
+        sink.append(track.source);
+        if sink.try_seek(resume.position).is_err() {
+            eprintln!("Failed to restore position after switching output");
+            self.emit(AudioControllerEvent::SeekFailed(resume.position.as_millis() as u64));
+        }
+        if resume.paused {
+            sink.pause();
+        }
+    }
+
+    fn tick(&mut self, sink: &mut Sink, mixer: &Mixer) {
+        self.update_crossfade(sink);
+        if sink.is_paused() || sink.empty() {
+            self.stalled_since = Instant::now();
+        }
+        if sink.is_paused() {
+            return;
+        }
+        if matches!(self.preloaded, Some(PreloadedTrack::Appended { .. })) && sink.len() < 2 {
+            if let Some(PreloadedTrack::Appended { song, position, length, gain, .. }) = self.preloaded.take() {
+                self.set_current(song.clone(), position, length, gain);
+                self.emit(AudioControllerEvent::TrackStarted(song));
+            }
+        }
+        if sink.empty() {
+            if let Some(song) = self.current_song.take() {
+                self.emit(AudioControllerEvent::TrackEnded(song));
+            }
+            return;
+        }
+        self.update_progress(sink);
+        let preload_ahead = self.crossfade_duration.max(Duration::from_millis(PRELOAD_AHEAD_MS));
+        if self.preloaded.is_none() && !self.preload_requested && self.crossfade.is_none() && sink.len() == 1
+            && self.remaining(sink).is_some_and(|r| r < preload_ahead) {
+            self.preload_requested = true;
+            self.emit(AudioControllerEvent::NextTrackWanted);
+        }
+        if matches!(self.preloaded, Some(PreloadedTrack::Crossfade { .. })) && self.remaining(sink).is_some_and(|r| r <= self.crossfade_duration) {
+            self.start_crossfade(sink, mixer);
+        }
+    }
+
+    /// Reports the position, or an underrun when it stopped moving although the sink is playing.
+    fn update_progress(&mut self, sink: &Sink) {
+        let position_ms = self.position(sink).as_millis() as u64;
+        if position_ms != self.last_progress_ms {
+            self.last_progress_ms = position_ms;
+            self.stalled_since = Instant::now();
+            self.underrun_reported = false;
+            self.emit(AudioControllerEvent::Progress(position_ms));
+        } else if !self.underrun_reported && self.stalled_since.elapsed() > Duration::from_millis(STALL_TIMEOUT_MS) {
+            self.underrun_reported = true;
+            self.emit(AudioControllerEvent::BufferUnderrun);
+        }
+    }
+
+    /// Position within the current track, which runs ahead of the sink's own clock when sped up.
+    fn position(&self, sink: &Sink) -> Duration {
+        match &self.current_position {
+            Some(position) => position.get(),
+            None => sink.get_pos(),
+        }
+    }
+
+    /// Wall clock time left until the current track ends at the current speed.
+    fn remaining(&self, sink: &Sink) -> Option<Duration> {
+        self.current_length.map(|length| length.saturating_sub(self.position(sink)).div_f32(self.speed.speed()))
+    }
+
+    fn set_current(&mut self, song: SongView, position: PlaybackPosition, length: Option<Duration>, gain: TrackGain) {
+        self.current_song = Some(song);
+        self.current_position = Some(position);
+        self.current_length = length;
+        self.current_gain = Some(gain);
+        self.stalled_since = Instant::now();
+    }
+
+    fn open_track(&self, song: &SongView) -> Option<Track> {
+        let file = File::open(&song.path).ok()?;
+        let decoder = Decoder::new(BufReader::new(file)).ok()?;
+        let length = decoder.total_duration();
+        let (source, position) = TempoSource::new(self.equalizer.wrap(decoder), self.speed.clone());
+        let gain = TrackGain::new(song.replay_gain, &self.replay_gain);
+        Some(Track { song: song.clone(), source: gain.wrap(source), position, length, gain })
+    }
+
+    fn set_replay_gain(&mut self, settings: ReplayGainSettings) {
+        self.replay_gain = settings;
+        if let Some(gain) = &self.current_gain {
+            gain.apply(&settings);
+        }
+        match &self.preloaded {
+            Some(PreloadedTrack::Appended { gain, .. }) => gain.apply(&settings),
+            Some(PreloadedTrack::Crossfade { track }) => track.gain.apply(&settings),
+            None => {},
+        }
+    }
+
+    fn should_crossfade(&self, next: &SongView) -> bool {
+        if self.crossfade_duration.is_zero() {
+            return false;
+        }
+        match &self.current_song {
+            Some(current) => current.album != next.album || current.artist != next.artist,
+            None => false,
+        }
+    }
+
+    fn preload(&mut self, song: SongView, sink: &Sink) {
+        self.preload_requested = false;
+        let Some(track) = self.open_track(&song) else {
+            eprintln!("Failed to preload next song: {}", song.path);
+            self.emit(AudioControllerEvent::DecodeFailed(song));
+            return;
+        };
+        if self.should_crossfade(&song) {
+            self.preloaded = Some(PreloadedTrack::Crossfade { track });
+        } else {
+            let cancelled = Arc::new(AtomicBool::new(false));
+            let cancel_flag = cancelled.clone();
+            sink.append(track.source.stoppable().periodic_access(Duration::from_millis(5), move |src| {
+                if cancel_flag.load(Ordering::SeqCst) {
+                    src.stop();
+                }
+            }));
+            self.preloaded = Some(PreloadedTrack::Appended { song, cancelled, position: track.position, length: track.length, gain: track.gain });
+        }
+    }
+
+    fn start_crossfade(&mut self, sink: &mut Sink, mixer: &Mixer) {
+        let Some(PreloadedTrack::Crossfade { track }) = self.preloaded.take() else {
+            return;
+        };
+        let incoming = Sink::connect_new(mixer);
+        incoming.set_volume(0.0);
+        incoming.append(track.source);
+        let outgoing = std::mem::replace(sink, incoming);
+        self.crossfade = Some(Crossfade {
+            start_pos: outgoing.get_pos(),
+            duration: self.remaining(&outgoing).unwrap_or(self.crossfade_duration).min(self.crossfade_duration),
+            outgoing,
+        });
+        self.set_current(track.song.clone(), track.position, track.length, track.gain);
+        self.emit(AudioControllerEvent::TrackStarted(track.song));
+    }
+
+    /// Applies an equal-power fade between the outgoing and the current sink,
+    /// driven by how far the outgoing track has played since the fade started.
+    fn update_crossfade(&mut self, sink: &Sink) {
+        let Some(crossfade) = &self.crossfade else {
+            return;
+        };
+        let elapsed = crossfade.outgoing.get_pos().saturating_sub(crossfade.start_pos);
+        let t = if crossfade.duration.is_zero() { 1.0 } else { (elapsed.as_secs_f32() / crossfade.duration.as_secs_f32()).min(1.0) };
+        if t >= 1.0 || crossfade.outgoing.empty() {
+            self.finish_crossfade(sink);
+            return;
+        }
+        crossfade.outgoing.set_volume(self.volume * (t * FRAC_PI_2).cos());
+        sink.set_volume(self.volume * (t * FRAC_PI_2).sin());
+    }
+
+    fn finish_crossfade(&mut self, sink: &Sink) {
+        if let Some(crossfade) = self.crossfade.take() {
+            crossfade.outgoing.stop();
+        }
+        sink.set_volume(self.volume);
+    }
+
+    /// Replaces whatever is playing with the song, leaving the sink paused.
+    fn load(&mut self, song: SongView, sink: &mut Sink) -> bool {
+        let Some(track) = self.open_track(&song) else {
+            eprintln!("Failed to decode song: {}", song.path);
+            self.emit(AudioControllerEvent::DecodeFailed(song));
+            return false;
+        };
+        self.preloaded = None;
+        self.preload_requested = false;
+        self.finish_crossfade(sink);
+        self.set_current(track.song, track.position, track.length, track.gain);
+        sink.clear();
+        sink.append(track.source);
+        self.emit(AudioControllerEvent::TrackStarted(song));
+        true
+    }
+
+    fn cancel_preloaded(&mut self) {
+        self.preload_requested = false;
+        if let Some(PreloadedTrack::Appended { cancelled, .. }) = self.preloaded.take() {
+            cancelled.store(true, Ordering::SeqCst);
+        }
+    }
+
     pub async fn handle_command(&mut self, cmd: AudioControllerCommand, sink: &mut Sink) {
         match cmd {
             AudioControllerCommand::Play => {
                 sink.play();
+                if let Some(crossfade) = &self.crossfade {
+                    crossfade.outgoing.play();
+                }
             },
             AudioControllerCommand::Pause => {
                 sink.pause();
+                if let Some(crossfade) = &self.crossfade {
+                    crossfade.outgoing.pause();
+                }
             },
-            AudioControllerCommand::Load(path) => {
-                if let Ok(file) = File::open(&path) {
-                    if let Ok(source) = Decoder::new(BufReader::new(file)) {
-                        sink.clear();
-                        sink.append(source);
-                        sink.play();
-                    }
+            AudioControllerCommand::Load(song) => {
+                if self.load(song, sink) {
+                    sink.play();
+                }
+            },
+            AudioControllerCommand::LoadPaused(song, progress_ms) => {
+                if self.load(song, sink) && sink.try_seek(Duration::from_millis(progress_ms)).is_err() {
+                    self.emit(AudioControllerEvent::SeekFailed(progress_ms));
                 }
             },
+            AudioControllerCommand::Preload(song) => {
+                self.preload(song, sink);
+            },
+            AudioControllerCommand::CancelPreloaded => {
+                self.cancel_preloaded();
+            },
             AudioControllerCommand::SetVolume(volume) => {
-                sink.set_volume(volume);
+                self.volume = volume;
+                if self.crossfade.is_none() {
+                    sink.set_volume(volume);
+                }
+            },
+            AudioControllerCommand::SetSpeed(speed) => {
+                self.speed.set_speed(speed);
+            },
+            AudioControllerCommand::SetPreservePitch(preserve_pitch) => {
+                self.speed.set_preserve_pitch(preserve_pitch);
+            },
+            AudioControllerCommand::SetReplayGain(settings) => {
+                self.set_replay_gain(settings);
+            },
+            AudioControllerCommand::SetEqualizer(settings) => {
+                self.equalizer.set(settings);
+            },
+            AudioControllerCommand::SetCrossfade(duration_ms) => {
+                self.crossfade_duration = Duration::from_millis(duration_ms);
             },
             AudioControllerCommand::SetProgress(progress_ms) => {
+                self.finish_crossfade(sink);
+                self.stalled_since = Instant::now();
                 if sink.try_seek(std::time::Duration::from_millis(progress_ms)).is_err(){
                     eprintln!("Failed to seek to {} ms", progress_ms);
                     println!("Trying to fallback by reloading the track");
-                    if let Some(song) = self.playing_state.clone().read().current_song() {
-                        if let Ok(file) = File::open(&song.path) {
-                            if let Ok(source) = Decoder::new(BufReader::new(file)) {
-                                sink.clear();
-                                sink.append(source);
-                                if sink.try_seek(std::time::Duration::from_millis(progress_ms)).is_err(){
-                                    eprintln!("Fallback seek also failed");
-                                } else { 
-                                    println!("Fallback seek succeeded");
-                                }
-                                sink.play();
+                    if let Some(song) = self.current_song.clone() {
+                        if let Some(track) = self.open_track(&song) {
+                            if self.preloaded.take().is_some() {
+                                self.emit(AudioControllerEvent::PreloadDropped);
+                            }
+                            self.preload_requested = false;
+                            self.set_current(track.song, track.position, track.length, track.gain);
+                            sink.clear();
+                            sink.append(track.source);
+                            if sink.try_seek(std::time::Duration::from_millis(progress_ms)).is_err(){
+                                eprintln!("Fallback seek also failed");
+                                self.emit(AudioControllerEvent::SeekFailed(progress_ms));
+                            } else {
+                                println!("Fallback seek succeeded");
                             }
+                            sink.play();
                         }
                     }
                 };
             },
+            AudioControllerCommand::SetOutputDevice(_) => {}, //handled by update_loop, which has to reopen the stream
             AudioControllerCommand::Stop => {
+                self.preloaded = None;
+                self.preload_requested = false;
+                self.current_song = None;
+                self.finish_crossfade(sink);
                 sink.stop();
             },
             _ => {
                 eprintln!("Unhandled audio controller command");
             }
         }
-        
+
     }
-}
\ No newline at end of file
+}
@@ -1,3 +1,5 @@
+use crate::{db::SongView, equalizer::EqualizerSettings, replay_gain::ReplayGainSettings};
+
 pub enum AudioControllerCommand {
     Play,
     Pause,
@@ -6,6 +8,14 @@ pub enum AudioControllerCommand {
     Previous,
     SetVolume(f32),
     SetSpeed(f32),
+    SetPreservePitch(bool),
+    SetCrossfade(u64),
+    SetReplayGain(ReplayGainSettings),
+    SetEqualizer(EqualizerSettings),
+    SetOutputDevice(Option<String>),
     SetProgress(u64),
-    Load(String),
+    Load(SongView),
+    LoadPaused(SongView, u64),
+    Preload(SongView),
+    CancelPreloaded,
 }
\ No newline at end of file
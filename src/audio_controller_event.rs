@@ -0,0 +1,15 @@
+use crate::db::SongView;
+
+/// Sent by the audio controller to tell the UI what the sink is doing.
+#[derive(Debug)]
+pub enum AudioControllerEvent {
+    TrackStarted(SongView),
+    TrackEnded(SongView),
+    Progress(u64),
+    NextTrackWanted,
+    PreloadDropped,
+    DecodeFailed(SongView),
+    SeekFailed(u64),
+    DeviceLost,
+    BufferUnderrun,
+}
@@ -0,0 +1,226 @@
+use std::{collections::HashMap, path::Path};
+
+use id3::TagLike;
+use symphonia::core::{formats::FormatOptions, io::MediaSourceStream, meta::MetadataOptions, probe::Hint};
+
+use crate::rating::SongRating;
+
+const RATING_USER: &str = "round"; //POPM frames are kept per user, usually an email address
+
+/// Reads the free-form text fields that audiotags does not expose
+/// (ID3 TXXX frames, vorbis comments and MP4 freeform atoms), keyed by upper case name.
+pub fn read_custom_tags(path: &str) -> HashMap<String, String> {
+    let mut fields = HashMap::new();
+    let extension = Path::new(path)
+        .extension()
+        .and_then(|e| e.to_str())
+        .unwrap_or("")
+        .to_lowercase();
+
+    match extension.as_str() {
+        "mp3" | "aac" | "wav" => {
+            if let Ok(tag) = id3::Tag::read_from_path(path) {
+                for text in tag.extended_texts() {
+                    fields.insert(text.description.to_uppercase(), text.value.clone());
+                }
+            }
+        }
+        "flac" => {
+            if let Ok(tag) = metaflac::Tag::read_from_path(path) {
+                if let Some(comments) = tag.vorbis_comments() {
+                    for (key, values) in comments.comments.iter() {
+                        if let Some(value) = values.first() {
+                            fields.insert(key.to_uppercase(), value.clone());
+                        }
+                    }
+                }
+            }
+        }
+        "ogg" => {
+            for (key, value) in read_ogg_comments(path) {
+                fields.entry(key.to_uppercase()).or_insert(value);
+            }
+        }
+        "m4a" | "mp4" | "m4b" => {
+            if let Ok(tag) = mp4ameta::Tag::read_from_path(path) {
+                for (ident, value) in tag.strings() {
+                    let ident = ident.to_string();
+                    if let Some(name) = ident.rsplit(':').next() {
+                        if ident.starts_with("----") {
+                            fields.insert(name.to_uppercase(), value.to_string());
+                        }
+                    }
+                }
+            }
+        }
+        _ => {}
+    }
+
+    fields
+}
+
+/// Vorbis comments of an Ogg file, which neither audiotags nor metaflac read.
+fn read_ogg_comments(path: &str) -> Vec<(String, String)> {
+    let Ok(file) = std::fs::File::open(path) else {
+        return Vec::new();
+    };
+    let stream = MediaSourceStream::new(Box::new(file), Default::default());
+    let mut hint = Hint::new();
+    hint.with_extension("ogg");
+    let probed = symphonia::default::get_probe()
+        .format(&hint, stream, &FormatOptions::default(), &MetadataOptions::default());
+    let Ok(mut probed) = probed else {
+        return Vec::new();
+    };
+    probed.format.metadata().current()
+        .map(|revision| revision.tags().iter().map(|tag| (tag.key.clone(), tag.value.to_string())).collect())
+        .unwrap_or_default()
+}
+
+/// Whether the file is marked as part of a compilation (ID3 TCMP, vorbis COMPILATION in FLAC or Ogg, or the MP4 cpil flag).
+pub fn read_compilation_flag(path: &str) -> bool {
+    let extension = Path::new(path)
+        .extension()
+        .and_then(|e| e.to_str())
+        .unwrap_or("")
+        .to_lowercase();
+    let is_set = |value: &str| matches!(value.trim(), "1" | "true" | "TRUE" | "True");
+
+    match extension.as_str() {
+        "mp3" | "aac" | "wav" => id3::Tag::read_from_path(path)
+            .ok()
+            .and_then(|tag| tag.get("TCMP").and_then(|frame| frame.content().text()).map(is_set))
+            .unwrap_or(false),
+        "flac" | "ogg" => read_custom_tags(path).get("COMPILATION").is_some_and(|value| is_set(value)),
+        "m4a" | "mp4" | "m4b" => mp4ameta::Tag::read_from_path(path).is_ok_and(|tag| tag.compilation()),
+        _ => false,
+    }
+}
+
+/// Writes a star rating to the file, as an ID3 POPM frame, a vorbis RATING comment (1 to 5)
+/// or an MP4 RATING freeform atom. 0 removes the rating.
+pub fn write_rating(path: &str, stars: u8) -> Result<(), String> {
+    let extension = Path::new(path)
+        .extension()
+        .and_then(|e| e.to_str())
+        .unwrap_or("")
+        .to_lowercase();
+
+    match extension.as_str() {
+        "mp3" | "aac" | "wav" => {
+            let existing = id3::no_tag_ok(id3::Tag::read_from_path(path)).map_err(|e| e.to_string())?;
+            let version = existing.as_ref().map(|tag| tag.version()).unwrap_or(id3::Version::Id3v24);
+            let mut tag = existing.unwrap_or_default();
+            //each player keeps its own frame, with a play counter next to the rating
+            let (ours, others): (Vec<id3::Frame>, Vec<id3::Frame>) = tag
+                .remove("POPM")
+                .into_iter()
+                .partition(|frame| frame.content().popularimeter().is_some_and(|popm| popm.user == RATING_USER));
+            for frame in others {
+                tag.add_frame(frame);
+            }
+            let counter = ours
+                .iter()
+                .find_map(|frame| frame.content().popularimeter())
+                .map_or(0, |popm| popm.counter);
+            if stars > 0 || counter > 0 {
+                tag.add_frame(id3::Frame::with_content(
+                    "POPM",
+                    id3::Content::Popularimeter(id3::frame::Popularimeter {
+                        user: RATING_USER.to_string(),
+                        rating: SongRating::popm_value(stars),
+                        counter,
+                    }),
+                ));
+            }
+            tag.write_to_path(path, version).map_err(|e| e.to_string())
+        }
+        "flac" => {
+            let mut tag = metaflac::Tag::read_from_path(path).map_err(|e| e.to_string())?;
+            if stars > 0 {
+                tag.set_vorbis("RATING", vec![stars.to_string()]);
+            } else {
+                tag.remove_vorbis("RATING");
+            }
+            tag.save().map_err(|e| e.to_string())
+        }
+        "m4a" | "mp4" | "m4b" => {
+            let mut tag = mp4ameta::Tag::read_from_path(path).map_err(|e| e.to_string())?;
+            let ident = mp4ameta::FreeformIdent::new("com.apple.iTunes", "RATING");
+            if stars > 0 {
+                tag.set_data(ident, mp4ameta::Data::Utf8(stars.to_string()));
+            } else {
+                tag.remove_data_of(&ident);
+            }
+            tag.write_to_path(path).map_err(|e| e.to_string())
+        }
+        _ => Err(format!("Ratings can't be written to .{} files", extension)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn popm(user: &str, rating: u8, counter: u64) -> id3::Frame {
+        id3::Frame::with_content("POPM", id3::Content::Popularimeter(id3::frame::Popularimeter { user: user.to_string(), rating, counter }))
+    }
+
+    /// An mp3 without audio, holding the given POPM frames.
+    fn tagged_file(name: &str, frames: Vec<id3::Frame>) -> String {
+        let path = std::env::temp_dir().join(format!("round-custom-tags-{}-{}.mp3", std::process::id(), name));
+        std::fs::write(&path, []).unwrap();
+        let mut tag = id3::Tag::new();
+        for frame in frames {
+            tag.add_frame(frame);
+        }
+        tag.write_to_path(&path, id3::Version::Id3v24).unwrap();
+        path.to_string_lossy().to_string()
+    }
+
+    fn popm_frames(path: &str) -> Vec<(String, u8, u64)> {
+        let tag = id3::Tag::read_from_path(path).unwrap();
+        let mut frames: Vec<_> = tag
+            .frames()
+            .filter_map(|frame| frame.content().popularimeter())
+            .map(|popm| (popm.user.clone(), popm.rating, popm.counter))
+            .collect();
+        frames.sort();
+        frames
+    }
+
+    #[test]
+    fn rating_leaves_other_players_frames_alone() {
+        let path = &tagged_file(
+            "players",
+            vec![popm("Windows Media Player 9 Series", 196, 0), popm("someone@example.com", 64, 42), popm(RATING_USER, 255, 7)],
+        );
+
+        write_rating(path, 3).unwrap();
+        assert_eq!(
+            popm_frames(path),
+            [
+                ("Windows Media Player 9 Series".to_string(), 196, 0),
+                (RATING_USER.to_string(), SongRating::popm_value(3), 7),
+                ("someone@example.com".to_string(), 64, 42),
+            ]
+        );
+
+        //the frame stays while it holds a play count
+        write_rating(path, 0).unwrap();
+        assert!(popm_frames(path).contains(&(RATING_USER.to_string(), 0, 7)));
+        assert_eq!(popm_frames(path).len(), 3);
+        let _ = std::fs::remove_file(path);
+    }
+
+    #[test]
+    fn unrating_an_untouched_file_adds_no_frame() {
+        let path = &tagged_file("unrated", vec![popm("someone@example.com", 64, 42)]);
+
+        write_rating(path, 0).unwrap();
+        assert_eq!(popm_frames(path), [("someone@example.com".to_string(), 64, 42)]);
+        write_rating(path, 5).unwrap();
+        assert_eq!(popm_frames(path), [(RATING_USER.to_string(), 255, 0), ("someone@example.com".to_string(), 64, 42)]);
+        let _ = std::fs::remove_file(path);
+    }
+}
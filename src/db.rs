@@ -1,13 +1,25 @@
-use std::path::{Path, PathBuf};
+use std::{collections::HashSet, path::{Path, PathBuf}};
 
 use audiotags::{AudioTag, MimeType, Picture, Tag};
 use directories::UserDirs;
 use rodio::Source;
-use rusqlite::{params, Connection, OptionalExtension, Result};
+use rusqlite::{params, params_from_iter, Connection, OptionalExtension, Result, Row, Transaction};
 
-use crate::errors::SongAddError;
+use crate::{migrations::{self, DB_STATE_VERSION}, equalizer::{EqualizerPreset, EqualizerSettings}, custom_tags::read_compilation_flag, errors::SongAddError, library_scanner::file_stamp, replay_gain::{ReplayGain, REFERENCE_LOUDNESS}, search::{SearchResults, fts_query}, rating::{SongRating, MAX_STARS}, smart_playlist::SmartPlaylistRules, statistics::ListeningTotals, song_tags::{AudioFormat, SongTags}};
 
-const DB_STATE_VERSION: i32 = 2; //Change this when the DB schema changes
+const DB_FILE_NAME: &str = "music_library.db";
+
+/// Album artist of compilations that carry no album artist tag of their own.
+pub const VARIOUS_ARTISTS: &str = "Various Artists";
+const COMPILATION_MIN_ARTISTS: usize = 3; //distinct track artists in one folder and album before it counts as a compilation
+
+/// Columns read by `song_view_from_row`, expects `songs s`, `albums al` and `artists ar` to be joined.
+const SONG_VIEW_COLUMNS: &str = "s.id, s.path, s.title, ar.name, al.name, al.cover_art_path, s.track_number, s.duration_seconds, s.play_count,
+    s.track_gain, s.track_peak, s.album_gain, s.album_peak, s.album_id,
+    s.track_artist, s.genre, s.year, s.disc_number, s.disc_total, s.track_total, s.composer, s.comment,
+    s.bitrate, s.sample_rate, s.channels, s.rating, s.loved";
+/// Queries selecting more than the song read the extra columns from this index on.
+const SONG_VIEW_COLUMN_COUNT: usize = 27;
 
 #[derive(Clone, Debug, PartialEq, Eq, Hash)]
 pub struct SongView {
@@ -20,12 +32,118 @@ pub struct SongView {
     pub track_number: Option<u16>,
     pub duration_seconds: u64,
     pub play_count: i32,
+    pub replay_gain: ReplayGain,
+    pub album_id: i32,
+    pub tags: SongTags,
+    pub format: AudioFormat,
+    pub rating: SongRating,
+}
+
+impl SongView {
+    /// The artist of this track, which differs from the album artist on compilations.
+    pub fn display_artist(&self) -> &str {
+        self.tags.track_artist.as_deref().unwrap_or(&self.artist)
+    }
+}
+
+/// A song in a playlist. The same song may appear several times, each with its own entry id.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PlaylistEntry {
+    pub id: i32,
+    pub song: SongView,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ArtistView {
+    pub id: i32,
+    pub name: String,
+    pub album_count: usize,
+    pub cover_art_path: Option<String>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct AlbumView {
+    pub id: i32,
+    pub name: String,
+    pub artist: String,
+    pub cover_art_path: Option<String>,
+}
+
+/// Columns read by `artist_view_from_row`, expects `albums al` and `artists ar` joined and grouped by artist.
+const ARTIST_VIEW_COLUMNS: &str = "ar.id, ar.name, COUNT(DISTINCT al.id), MAX(al.cover_art_path)";
+
+fn artist_view_from_row(row: &Row) -> Result<ArtistView> {
+    Ok(ArtistView {
+        id: row.get(0)?,
+        name: row.get(1)?,
+        album_count: row.get(2)?,
+        cover_art_path: row.get(3)?,
+    })
+}
+
+/// Columns read by `album_view_from_row`, expects `albums al` and `artists ar` to be joined.
+const ALBUM_VIEW_COLUMNS: &str = "al.id, al.name, ar.name, al.cover_art_path";
+
+fn album_view_from_row(row: &Row) -> Result<AlbumView> {
+    Ok(AlbumView {
+        id: row.get(0)?,
+        name: row.get(1)?,
+        artist: row.get(2)?,
+        cover_art_path: row.get(3)?,
+    })
+}
+
+fn song_view_from_row(row: &Row) -> Result<SongView> {
+    Ok(SongView {
+        id: row.get(0)?,
+        path: row.get(1)?,
+        title: row.get(2)?,
+        artist: row.get(3)?,
+        album: row.get(4)?,
+        album_art_path: row.get(5)?,
+        track_number: row.get(6)?,
+        duration_seconds: row.get(7)?,
+        play_count: row.get(8)?,
+        replay_gain: ReplayGain {
+            track_gain_db: row.get(9)?,
+            track_peak: row.get(10)?,
+            album_gain_db: row.get(11)?,
+            album_peak: row.get(12)?,
+        },
+        album_id: row.get(13)?,
+        tags: tags_from_row(row, 14)?,
+        format: format_from_row(row, 22)?,
+        rating: SongRating { stars: row.get(25)?, loved: row.get(26)? },
+    })
+}
+
+/// Reads `track_artist` through `comment` starting at column `first`.
+fn tags_from_row(row: &Row, first: usize) -> Result<SongTags> {
+    Ok(SongTags {
+        track_artist: row.get(first)?,
+        genre: row.get(first + 1)?,
+        year: row.get(first + 2)?,
+        disc_number: row.get(first + 3)?,
+        disc_total: row.get(first + 4)?,
+        track_total: row.get(first + 5)?,
+        composer: row.get(first + 6)?,
+        comment: row.get(first + 7)?,
+    })
+}
+
+/// Reads `bitrate`, `sample_rate` and `channels` starting at column `first`.
+fn format_from_row(row: &Row, first: usize) -> Result<AudioFormat> {
+    Ok(AudioFormat {
+        bitrate_kbps: row.get(first)?,
+        sample_rate: row.get(first + 1)?,
+        channels: row.get(first + 2)?,
+    })
 }
 
 impl PartialOrd for SongView {
     fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
         if self.artist == other.artist && self.album == other.album {
-            self.track_number.partial_cmp(&other.track_number)
+            (self.tags.disc_number, self.track_number).partial_cmp(&(other.tags.disc_number, other.track_number))
         } else {
             Some(
                 self.artist
@@ -45,6 +163,9 @@ pub struct SongDbEntry {
     pub track_number: Option<u16>,
     pub duration_seconds: u64,
     pub play_count: i32,
+    pub replay_gain: ReplayGain,
+    pub tags: SongTags,
+    pub format: AudioFormat,
 }
 
 impl SongDbEntry {
@@ -76,6 +197,100 @@ impl SongDbEntry {
             track_number: self.track_number,
             duration_seconds: self.duration_seconds,
             play_count: self.play_count,
+            replay_gain: self.replay_gain,
+            album_id: self.album_id,
+            tags: self.tags.clone(),
+            format: self.format,
+            rating: db.get_song_rating(self.id)?,
+        })
+    }
+}
+
+/// What gets read from an audio file to add it to the library, gathered before any database work
+/// so the library scanner doesn't decode files while holding its write transaction.
+pub struct SongFile {
+    pub path: String,
+    title: String,
+    artist: String,
+    has_album_artist: bool,
+    compilation_flag: bool,
+    album_name: String,
+    cover: Option<(Vec<u8>, MimeType)>,
+    track_number: Option<u16>,
+    duration_seconds: u64,
+    replay_gain: ReplayGain,
+    tags: SongTags,
+    format: AudioFormat,
+    stamp: Option<(i64, i64)>,
+}
+
+impl SongFile {
+    pub fn read(path: &str) -> Result<SongFile, SongAddError> {
+        //taken first, a file changing while it is read gets read again on the next scan
+        let stamp = file_stamp(Path::new(path));
+        let tag = Tag::new().read_from_path(path);
+
+        let album_name = tag
+            .as_ref()
+            .ok()
+            .and_then(|t| t.album().map(|a| a.title.to_string()))
+            .unwrap_or_else(|| "No Album".to_string());
+
+        let tags = tag.as_ref().map(|t| SongTags::from_tag(t.as_ref())).unwrap_or_default();
+        let has_album_artist = tag.as_ref().ok().and_then(|t| t.album_artist()).is_some();
+        let compilation_flag = !has_album_artist && read_compilation_flag(path);
+        let artist = Db::try_get_album_artist_then_artist(tag.as_ref().ok());
+        let cover = tag
+            .as_ref()
+            .ok()
+            .and_then(|t| t.album_cover())
+            .map(|pic| (pic.data.to_vec(), pic.mime_type));
+
+        let title = tag
+            .as_ref()
+            .ok()
+            .and_then(|t| t.title().map(|t| t.to_string()))
+            .unwrap_or_else(|| {
+                std::path::Path::new(path)
+                    .file_name()
+                    .unwrap_or_default()
+                    .to_string_lossy()
+                    .to_string()
+            });
+
+        let track_number = tag
+            .as_ref()
+            .ok()
+            .and_then(|t| t.track().0.map(|n| n as u16));
+
+        let file = std::fs::File::open(path)?;
+        let file_size = file.metadata().ok().map(|metadata| metadata.len());
+        let decoder = rodio::Decoder::new(file);
+
+        if decoder.is_err() {
+            println!("Warning: Could not determine duration for file: {}", path);
+            return Err(decoder.err().unwrap().into());
+        }
+
+        let decoder = decoder.unwrap();
+        let total_duration = decoder.total_duration();
+        let duration_seconds = total_duration.map(|d| d.as_secs()).unwrap_or(0);
+        let format = AudioFormat::new(decoder.sample_rate(), decoder.channels(), total_duration, file_size);
+
+        Ok(SongFile {
+            path: path.to_string(),
+            title,
+            artist,
+            has_album_artist,
+            compilation_flag,
+            album_name,
+            cover,
+            track_number,
+            duration_seconds,
+            replay_gain: ReplayGain::from_file(path),
+            tags,
+            format,
+            stamp,
         })
     }
 }
@@ -98,83 +313,115 @@ impl Db {
             panic!("Could not determine user directories");
         };
 
-        let db = Db {
-            conn: Connection::open(PathBuf::from(&cache_dir).join("music_library.db")).unwrap(),
+        Db::open(cache_dir)
+    }
+
+    /// Opens the library kept in `cache_dir`, migrating it when needed.
+    pub fn open(cache_dir: String) -> Db {
+        let mut db = Db {
+            conn: Connection::open(PathBuf::from(&cache_dir).join(DB_FILE_NAME)).unwrap(),
             cache_path: cache_dir,
         };
 
         db.conn.execute("PRAGMA foreign_keys = ON;", []).unwrap();
+        db.conn.busy_timeout(std::time::Duration::from_secs(5)).unwrap(); //background tasks open their own connections
 
-        if db.handle_db_version_change().is_err() {
-            panic!("Failed to handle DB version change");
+        if let Err(e) = db.handle_db_version_change() {
+            panic!("Failed to handle DB version change: {:?}", e);
         }
 
-        db.conn
-            .execute(
-                "CREATE TABLE IF NOT EXISTS artists (
-                id      INTEGER PRIMARY KEY,
-                name    TEXT NOT NULL UNIQUE
-            )",
-                [],
-            )
-            .unwrap();
+        db
+    }
 
-        db.conn
-            .execute(
-                "CREATE TABLE IF NOT EXISTS albums (
-                id               INTEGER PRIMARY KEY,
-                name             TEXT NOT NULL,
-                artist_id        INTEGER NOT NULL,
-                cover_art_path   TEXT,
-                FOREIGN KEY(artist_id) REFERENCES artists(id),
-                UNIQUE(name, artist_id)
-            )",
-                [],
+    pub fn get_setting(&self, key: &str) -> Result<Option<String>> {
+        self.conn
+            .query_row(
+                "SELECT value FROM settings WHERE key = ?1",
+                params![key],
+                |row| row.get(0),
             )
-            .unwrap();
+            .optional()
+    }
 
-        db.conn
-            .execute(
-                "CREATE TABLE IF NOT EXISTS songs (
-                id               INTEGER PRIMARY KEY,
-                path             TEXT NOT NULL UNIQUE,
-                title            TEXT NOT NULL,
-                album_id         INTEGER NOT NULL,
-                track_number     INTEGER,
-                duration_seconds INTEGER NOT NULL DEFAULT 0,
-                play_count       INTEGER NOT NULL DEFAULT 0,
-                FOREIGN KEY(album_id) REFERENCES albums(id)
-            )",
-                [],
-            )
-            .unwrap();
+    pub fn set_setting(&self, key: &str, value: &str) -> Result<()> {
+        self.conn.execute(
+            "INSERT INTO settings (key, value) VALUES (?1, ?2)
+             ON CONFLICT(key) DO UPDATE SET value = excluded.value",
+            params![key, value],
+        )?;
+        Ok(())
+    }
 
-        db.conn
-            .execute(
-                "CREATE TABLE IF NOT EXISTS playlists (
-                id               INTEGER PRIMARY KEY,
-                name             TEXT NOT NULL,
-                cover_art_path   TEXT
-            )",
-                [],
-            )
-            .unwrap();
+    pub fn get_eq_presets(&self) -> Result<Vec<EqualizerPreset>> {
+        let mut stmt = self.conn.prepare("SELECT id, name, settings FROM eq_presets ORDER BY name")?;
+        stmt.query_map([], |row| {
+            let settings: String = row.get(2)?;
+            Ok(EqualizerPreset {
+                id: row.get(0)?,
+                name: row.get(1)?,
+                settings: EqualizerSettings::from_json(&settings).unwrap_or_default(),
+            })
+        })
+        .and_then(|iter| iter.collect())
+    }
 
-        db.conn
-            .execute(
-                "CREATE TABLE IF NOT EXISTS playlist_songs (
-                playlist_id               INTEGER NOT NULL,
-                song_id                   INTEGER NOT NULL,
-                position                  INTEGER NOT NULL,
-                FOREIGN KEY(playlist_id) REFERENCES playlists(id),
-                FOREIGN KEY(song_id) REFERENCES songs(id),
-                PRIMARY KEY(playlist_id, song_id)
-            )",
-                [],
+    /// Creates the preset, or overwrites the settings of an existing one with the same name.
+    pub fn save_eq_preset(&self, name: &str, settings: &EqualizerSettings) -> Result<i32> {
+        self.conn.execute(
+            "INSERT INTO eq_presets (name, settings) VALUES (?1, ?2)
+             ON CONFLICT(name) DO UPDATE SET settings = excluded.settings",
+            params![name, settings.to_json()],
+        )?;
+        self.conn.query_row(
+            "SELECT id FROM eq_presets WHERE name = ?1",
+            params![name],
+            |row| row.get(0),
+        )
+    }
+
+    pub fn delete_eq_preset(&self, preset_id: i32) -> Result<()> {
+        self.conn.execute(
+            "DELETE FROM album_eq_presets WHERE preset_id = ?1",
+            params![preset_id],
+        )?;
+        self.conn
+            .execute("DELETE FROM eq_presets WHERE id = ?1", params![preset_id])?;
+        Ok(())
+    }
+
+    pub fn get_album_eq_preset(&self, album_id: i32) -> Result<Option<EqualizerPreset>> {
+        self.conn
+            .query_row(
+                "SELECT p.id, p.name, p.settings
+                 FROM album_eq_presets ap
+                 JOIN eq_presets p ON ap.preset_id = p.id
+                 WHERE ap.album_id = ?1",
+                params![album_id],
+                |row| {
+                    let settings: String = row.get(2)?;
+                    Ok(EqualizerPreset {
+                        id: row.get(0)?,
+                        name: row.get(1)?,
+                        settings: EqualizerSettings::from_json(&settings).unwrap_or_default(),
+                    })
+                },
             )
-            .unwrap();
+            .optional()
+    }
 
-        db
+    pub fn set_album_eq_preset(&self, album_id: i32, preset_id: Option<i32>) -> Result<()> {
+        match preset_id {
+            Some(preset_id) => self.conn.execute(
+                "INSERT INTO album_eq_presets (album_id, preset_id) VALUES (?1, ?2)
+                 ON CONFLICT(album_id) DO UPDATE SET preset_id = excluded.preset_id",
+                params![album_id, preset_id],
+            )?,
+            None => self.conn.execute(
+                "DELETE FROM album_eq_presets WHERE album_id = ?1",
+                params![album_id],
+            )?,
+        };
+        Ok(())
     }
 
     fn get_or_insert_artist_id(&self, artist_name: &str) -> Result<i32> {
@@ -227,7 +474,9 @@ impl Db {
             }
         });
         self.conn.execute(
-            "INSERT OR IGNORE INTO albums (name, artist_id, cover_art_path) VALUES (?1, ?2, ?3)",
+            "INSERT INTO albums (name, artist_id, cover_art_path) VALUES (?1, ?2, ?3)
+             ON CONFLICT(name, artist_id) DO UPDATE SET
+               cover_art_path = COALESCE(excluded.cover_art_path, albums.cover_art_path)",
             params![album_name, artist_id, cover_art_path],
         )?;
         println!("Added album: {} by artist ID: {}", album_name, artist_id);
@@ -313,19 +562,56 @@ impl Db {
         print!("Adding song: {} by album ID: {}", song.title, song.album_id);
         self.conn
             .execute(
-                "INSERT OR IGNORE INTO songs
-         (path, title, album_id, track_number, duration_seconds, play_count)
-          VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+                "INSERT INTO songs
+         (path, title, album_id, track_number, duration_seconds, play_count, track_gain, track_peak, album_gain, album_peak,
+          track_artist, genre, year, disc_number, disc_total, track_total, composer, comment, bitrate, sample_rate, channels, added_at)
+          VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16, ?17, ?18, ?19, ?20, ?21,
+            CAST(strftime('%s', 'now') AS INTEGER))
+          ON CONFLICT(path) DO UPDATE SET
+            title = excluded.title,
+            album_id = excluded.album_id,
+            track_number = excluded.track_number,
+            duration_seconds = excluded.duration_seconds,
+            track_gain = excluded.track_gain,
+            track_peak = excluded.track_peak,
+            album_gain = excluded.album_gain,
+            album_peak = excluded.album_peak,
+            track_artist = excluded.track_artist,
+            genre = excluded.genre,
+            year = excluded.year,
+            disc_number = excluded.disc_number,
+            disc_total = excluded.disc_total,
+            track_total = excluded.track_total,
+            composer = excluded.composer,
+            comment = excluded.comment,
+            bitrate = excluded.bitrate,
+            sample_rate = excluded.sample_rate,
+            channels = excluded.channels,
+            loudness_analyzed = 0",
                 params![
                     song.path,
                     song.title,
                     song.album_id,
                     song.track_number,
                     song.duration_seconds,
-                    song.play_count
+                    song.play_count,
+                    song.replay_gain.track_gain_db,
+                    song.replay_gain.track_peak,
+                    song.replay_gain.album_gain_db,
+                    song.replay_gain.album_peak,
+                    song.tags.track_artist,
+                    song.tags.genre,
+                    song.tags.year,
+                    song.tags.disc_number,
+                    song.tags.disc_total,
+                    song.tags.track_total,
+                    song.tags.composer,
+                    song.tags.comment,
+                    song.format.bitrate_kbps,
+                    song.format.sample_rate,
+                    song.format.channels
                 ],
-            )
-            .unwrap();
+            )?;
         let song_id = self.conn.query_row(
             "SELECT id FROM songs WHERE path = ?1",
             params![song.path],
@@ -339,12 +625,18 @@ impl Db {
             track_number: song.track_number,
             duration_seconds: song.duration_seconds,
             play_count: song.play_count,
+            replay_gain: song.replay_gain,
+            tags: song.tags.clone(),
+            format: song.format,
         })
     }
 
     pub fn add_or_get_song_by_path(&self, path: &str) -> Result<SongDbEntry, SongAddError> {
         if let Ok(Some(song)) = self.conn.query_row(
-            "SELECT s.id, s.path, s.title, s.album_id, s.track_number, s.duration_seconds, s.play_count
+            "SELECT s.id, s.path, s.title, s.album_id, s.track_number, s.duration_seconds, s.play_count,
+                    s.track_gain, s.track_peak, s.album_gain, s.album_peak,
+                    s.track_artist, s.genre, s.year, s.disc_number, s.disc_total, s.track_total, s.composer, s.comment,
+                    s.bitrate, s.sample_rate, s.channels
              FROM songs s
              WHERE s.path = ?1",
             params![path],
@@ -361,6 +653,14 @@ impl Db {
                     track_number: row.get(4)?,
                     duration_seconds: row.get(5)?,
                     play_count: row.get(6)?,
+                    replay_gain: ReplayGain {
+                        track_gain_db: row.get(7)?,
+                        track_peak: row.get(8)?,
+                        album_gain_db: row.get(9)?,
+                        album_peak: row.get(10)?,
+                    },
+                    tags: tags_from_row(row, 11)?,
+                    format: format_from_row(row, 19)?,
                 })
             }
         ).optional() {
@@ -371,62 +671,136 @@ impl Db {
     }
 
     pub fn add_song_by_path(&self, path: &str) -> Result<SongDbEntry, SongAddError> {
-        let tag = Tag::new().read_from_path(path);
-
-        let artist = Self::try_get_album_artist_then_artist(tag.as_ref().ok());
-        let artist_id = self.get_or_insert_artist_id(&artist).unwrap();
+        self.add_song_file(&SongFile::read(path)?)
+    }
 
-        let album_name = tag
-            .as_ref()
-            .ok()
-            .and_then(|t| t.album().map(|a| a.title.to_string()))
-            .unwrap_or_else(|| "No Album".to_string());
+    /// Writes a song read by `SongFile::read`, only database work happens here.
+    pub fn add_song_file(&self, file: &SongFile) -> Result<SongDbEntry, SongAddError> {
+        let path = file.path.as_str();
+        let compilation = !file.has_album_artist
+            && (file.compilation_flag || self.is_compilation_folder(path, &file.album_name, file.tags.track_artist.as_deref())?);
+        let artist = if compilation {
+            VARIOUS_ARTISTS
+        } else {
+            file.artist.as_str()
+        };
+        let artist_id = self.get_or_insert_artist_id(artist)?;
 
         let album_id = self.get_or_insert_album_id(
-            &album_name,
+            &file.album_name,
             artist_id,
-            tag.as_ref().ok().and_then(|t| t.album_cover()),
+            file.cover.as_ref().map(|(data, mime_type)| Picture::new(data, *mime_type)),
             path,
         )?;
 
-        let title = tag
-            .as_ref()
-            .ok()
-            .and_then(|t| t.title().map(|t| t.to_string()))
-            .unwrap_or_else(|| {
-                std::path::Path::new(path)
-                    .file_name()
-                    .unwrap_or_default()
-                    .to_string_lossy()
-                    .to_string()
-            });
+        let song = SongDbEntry {
+            id: 0,
+            path: file.path.clone(),
+            title: file.title.clone(),
+            album_id,
+            track_number: file.track_number,
+            duration_seconds: file.duration_seconds,
+            play_count: 0,
+            replay_gain: file.replay_gain,
+            tags: file.tags.clone(),
+            format: file.format,
+        };
 
-        let track_number = tag
-            .as_ref()
-            .ok()
-            .and_then(|t| t.track().0.map(|n| n as u16));
+        let previous_album_id: Option<i32> = self
+            .conn
+            .query_row("SELECT album_id FROM songs WHERE path = ?1", params![path], |row| row.get(0))
+            .optional()?;
 
-        let duration_seconds = rodio::Decoder::new(std::fs::File::open(path).unwrap())
-            .map(|d| d.total_duration().map(|d| d.as_secs()));
+        let song = self.add_song(&song)?;
+        if let Some((mtime, size)) = file.stamp {
+            self.set_file_stamp(song.id, mtime, size)?;
+        }
+        if compilation {
+            self.merge_into_compilation(path, &file.album_name, album_id)?;
+        }
+        if let Some(previous_album_id) = previous_album_id.filter(|id| *id != album_id) {
+            self.remove_album_if_empty(previous_album_id)?;
+        }
+        Ok(song)
+    }
 
-        if duration_seconds.is_err() {
-            println!("Warning: Could not determine duration for file: {}", path);
-            return Err(duration_seconds.err().unwrap().into());
+    /// Songs sharing the folder of `path` and the album name, whose album was named after their own artist
+    /// because they had no album artist tag. Yields each song's id, album id, track artist and album artist.
+    fn untagged_album_siblings(&self, path: &str, album_name: &str) -> Result<Vec<(i32, i32, String, String)>> {
+        let Some(folder) = Path::new(path).parent() else {
+            return Ok(Vec::new());
+        };
+        let folder = format!("{}{}", folder.to_string_lossy(), std::path::MAIN_SEPARATOR);
+        let mut stmt = self.conn.prepare(
+            "SELECT s.id, s.album_id, COALESCE(s.track_artist, ar.name), ar.name
+             FROM songs s
+             JOIN albums al ON s.album_id = al.id
+             JOIN artists ar ON al.artist_id = ar.id
+             WHERE al.name = ?1
+               AND substr(s.path, 1, length(?2)) = ?2
+               AND instr(substr(s.path, length(?2) + 1), ?3) = 0
+               AND s.path != ?4
+               AND (ar.name = ?5 OR ar.name = COALESCE(s.track_artist, 'Unknown Artist'))",
+        )?;
+        stmt.query_map(
+            params![album_name, folder, std::path::MAIN_SEPARATOR.to_string(), path, VARIOUS_ARTISTS],
+            |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?)),
+        )
+        .and_then(|iter| iter.collect())
+    }
+
+    /// A folder holds a compilation when its songs of this album already went to Various Artists,
+    /// or when they name enough different artists between them.
+    fn is_compilation_folder(&self, path: &str, album_name: &str, track_artist: Option<&str>) -> Result<bool> {
+        let siblings = self.untagged_album_siblings(path, album_name)?;
+        if siblings.iter().any(|(_, _, _, album_artist)| album_artist == VARIOUS_ARTISTS) {
+            return Ok(true);
         }
+        let mut artists: HashSet<&str> = siblings.iter().map(|(_, _, artist, _)| artist.as_str()).collect();
+        artists.insert(track_artist.unwrap_or("Unknown Artist"));
+        Ok(artists.len() >= COMPILATION_MIN_ARTISTS)
+    }
 
-        let duration_seconds = duration_seconds.unwrap().unwrap();
+    /// Moves the songs next to `path` that were split into one album per artist into the compilation album.
+    fn merge_into_compilation(&self, path: &str, album_name: &str, album_id: i32) -> Result<()> {
+        let mut emptied = HashSet::new();
+        for (song_id, song_album_id, _, _) in self.untagged_album_siblings(path, album_name)? {
+            if song_album_id != album_id {
+                self.conn.execute(
+                    "UPDATE songs SET album_id = ?1 WHERE id = ?2",
+                    params![album_id, song_id],
+                )?;
+                emptied.insert(song_album_id);
+            }
+        }
+        for album_id in emptied {
+            self.remove_album_if_empty(album_id)?;
+        }
+        Ok(())
+    }
 
-        let song = SongDbEntry {
-            id: 0,
-            path: path.to_string(),
-            title,
-            album_id,
-            track_number,
-            duration_seconds,
-            play_count: 0,
-        };
+    /// Modification time and size recorded when the file was last read, `None` if the song is unknown.
+    pub fn get_file_stamp(&self, path: &str) -> Result<Option<(Option<i64>, Option<i64>)>> {
+        self.conn
+            .query_row(
+                "SELECT file_mtime, file_size FROM songs WHERE path = ?1",
+                params![path],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .optional()
+    }
 
-        self.add_song(&song)
+    fn set_file_stamp(&self, song_id: i32, mtime: i64, size: i64) -> Result<()> {
+        self.conn.execute(
+            "UPDATE songs SET file_mtime = ?1, file_size = ?2 WHERE id = ?3",
+            params![mtime, size, song_id],
+        )?;
+        Ok(())
+    }
+
+    /// Groups the following writes into one transaction, committed when `commit` is called on it.
+    pub fn batch(&self) -> Result<Transaction<'_>> {
+        self.conn.unchecked_transaction()
     }
 
     fn try_get_album_artist_then_artist(tag: Option<&Box<dyn AudioTag + Send + Sync>>) -> String {
@@ -447,11 +821,8 @@ impl Db {
         self.conn
             .execute("INSERT INTO playlists (name) VALUES (?1)", params![name])?;
 
-        self.conn.query_row(
-            "SELECT id FROM playlists WHERE name = ?1",
-            params![name],
-            |row| row.get(0),
-        )
+        //names are not unique, an imported playlist may share one with an existing playlist
+        Ok(self.conn.last_insert_rowid() as i32)
     }
 
     pub fn add_song_to_playlist(&self, playlist_id: i32, song_id: i32) -> Result<()> {
@@ -468,42 +839,133 @@ impl Db {
             )
             .unwrap_or(0);
         self.conn.execute(
-            "INSERT OR IGNORE INTO playlist_songs (playlist_id, song_id, position) VALUES (?1, ?2, ?3)",
+            "INSERT INTO playlist_songs (playlist_id, song_id, position) VALUES (?1, ?2, ?3)",
             params![playlist_id, song_id, next_position],
         )?;
         Ok(())
     }
 
+    /// Appends the songs in order, in one transaction.
+    pub fn add_songs_to_playlist(&self, playlist_id: i32, song_ids: &[i32]) -> Result<()> {
+        let tx = self.conn.unchecked_transaction()?;
+        let first_position: i32 = tx.query_row(
+            "SELECT COALESCE(MAX(position), 0) + 1 FROM playlist_songs WHERE playlist_id = ?1",
+            params![playlist_id],
+            |row| row.get(0),
+        )?;
+        {
+            let mut stmt = tx.prepare("INSERT INTO playlist_songs (playlist_id, song_id, position) VALUES (?1, ?2, ?3)")?;
+            for (offset, song_id) in song_ids.iter().enumerate() {
+                stmt.execute(params![playlist_id, song_id, first_position + offset as i32])?;
+            }
+        }
+        tx.commit()
+    }
+
+    /// Creates a playlist whose songs are the ones matching `rules`.
+    pub fn create_smart_playlist(&self, name: &str, rules: &SmartPlaylistRules) -> Result<i32> {
+        self.conn.execute(
+            "INSERT INTO playlists (name, rules) VALUES (?1, ?2)",
+            params![name, rules.to_json()],
+        )?;
+        Ok(self.conn.last_insert_rowid() as i32)
+    }
+
+    pub fn update_smart_playlist(&self, playlist_id: i32, name: &str, rules: &SmartPlaylistRules) -> Result<()> {
+        self.conn.execute(
+            "UPDATE playlists SET name = ?1, rules = ?2 WHERE id = ?3",
+            params![name, rules.to_json(), playlist_id],
+        )?;
+        Ok(())
+    }
+
+    /// The rules of a smart playlist, `None` for a regular one.
+    pub fn get_playlist_rules(&self, playlist_id: i32) -> Result<Option<SmartPlaylistRules>> {
+        let rules: Option<String> = self.conn.query_row(
+            "SELECT rules FROM playlists WHERE id = ?1",
+            params![playlist_id],
+            |row| row.get(0),
+        )?;
+        Ok(rules.and_then(|rules| SmartPlaylistRules::from_json(&rules)))
+    }
+
+    pub fn get_smart_playlist_songs(&self, rules: &SmartPlaylistRules) -> Result<Vec<SongView>> {
+        let (clauses, values) = rules
+            .to_sql()
+            .map_err(|e| rusqlite::Error::ToSqlConversionFailure(e.into()))?;
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT {SONG_VIEW_COLUMNS}
+             FROM songs s
+             JOIN albums al ON s.album_id = al.id
+             JOIN artists ar ON al.artist_id = ar.id
+             {clauses}",
+        ))?;
+
+        stmt.query_map(params_from_iter(values), song_view_from_row)
+            .and_then(|iter| iter.collect())
+    }
+
+    /// Songs of a playlist in order, smart playlists get their rules evaluated.
     pub fn get_songs_in_playlist(&self, playlist_id: i32) -> Result<Vec<SongView>> {
-        let mut stmt = self.conn.prepare(
-            "SELECT s.id, s.path, s.title, ar.name, al.name, al.cover_art_path, s.track_number, s.duration_seconds, s.play_count
+        if let Some(rules) = self.get_playlist_rules(playlist_id)? {
+            return self.get_smart_playlist_songs(&rules);
+        }
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT {SONG_VIEW_COLUMNS}
              FROM songs s
              JOIN albums al ON s.album_id = al.id
              JOIN artists ar ON al.artist_id = ar.id
              JOIN playlist_songs ps ON s.id = ps.song_id
              WHERE ps.playlist_id = ?1
              ORDER BY ps.position ASC",
-        )?;
+        ))?;
+
+        stmt.query_map(params![playlist_id], song_view_from_row)
+            .and_then(|iter| iter.collect())
+    }
+
+    pub fn get_playlist_entries(&self, playlist_id: i32) -> Result<Vec<PlaylistEntry>> {
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT {SONG_VIEW_COLUMNS}, ps.id
+             FROM songs s
+             JOIN albums al ON s.album_id = al.id
+             JOIN artists ar ON al.artist_id = ar.id
+             JOIN playlist_songs ps ON s.id = ps.song_id
+             WHERE ps.playlist_id = ?1
+             ORDER BY ps.position ASC",
+        ))?;
 
         stmt.query_map(params![playlist_id], |row| {
-            Ok(SongView {
-                id: row.get(0)?,
-                path: row.get(1)?,
-                title: row.get(2)?,
-                artist: row.get(3)?,
-                album: row.get(4)?,
-                album_art_path: row.get(5)?,
-                track_number: row.get(6)?,
-                duration_seconds: row.get(7)?,
-                play_count: row.get(8)?,
-            })
+            //the entry id follows the song columns
+            Ok(PlaylistEntry { id: row.get(SONG_VIEW_COLUMN_COUNT)?, song: song_view_from_row(row)? })
         })
         .and_then(|iter| iter.collect())
     }
 
+    /// Moves an entry to `index` (0-based, clamped to the end) and renumbers the playlist.
+    pub fn move_playlist_entry(&self, playlist_id: i32, entry_id: i32, index: usize) -> Result<()> {
+        let tx = self.conn.unchecked_transaction()?;
+        let mut entry_ids: Vec<i32> = tx
+            .prepare("SELECT id FROM playlist_songs WHERE playlist_id = ?1 ORDER BY position ASC")?
+            .query_map(params![playlist_id], |row| row.get(0))?
+            .collect::<Result<_>>()?;
+        let Some(from) = entry_ids.iter().position(|id| *id == entry_id) else {
+            return Err(rusqlite::Error::QueryReturnedNoRows);
+        };
+        entry_ids.remove(from);
+        entry_ids.insert(index.min(entry_ids.len()), entry_id);
+
+        let mut update = tx.prepare("UPDATE playlist_songs SET position = ?1 WHERE id = ?2")?;
+        for (position, id) in entry_ids.iter().enumerate() {
+            update.execute(params![position as i32 + 1, id])?;
+        }
+        drop(update);
+        tx.commit()
+    }
+
     pub fn get_nth_playlist_song(&self, playlist_id: i32, n: usize) -> Result<Option<SongView>> {
-        let mut stmt = self.conn.prepare(
-            "SELECT s.id, s.path, s.title, ar.name, al.name, al.cover_art_path, s.track_number, s.duration_seconds, s.play_count
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT {SONG_VIEW_COLUMNS}
             FROM playlist_songs ps
                 JOIN songs s ON ps.song_id = s.id
                 JOIN albums al ON s.album_id = al.id
@@ -511,22 +973,10 @@ impl Db {
             WHERE ps.playlist_id = ?1
             ORDER BY ps.position ASC
             LIMIT 1 OFFSET ?2"
-        )?;
+        ))?;
 
-        stmt.query_row(params![playlist_id, n as i64], |row| {
-            Ok(SongView {
-                id: row.get(0)?,
-                path: row.get(1)?,
-                title: row.get(2)?,
-                artist: row.get(3)?,
-                album: row.get(4)?,
-                album_art_path: row.get(5)?,
-                track_number: row.get(6)?,
-                duration_seconds: row.get(7)?,
-                play_count: row.get(8)?,
-            })
-        })
-        .optional()
+        stmt.query_row(params![playlist_id, n as i64], song_view_from_row)
+            .optional()
     }
 
     pub fn get_playlist_song_count(&self, playlist_id: i32) -> Result<usize> {
@@ -537,46 +987,254 @@ impl Db {
         )
     }
 
-    pub fn get_song_view_by_path(&self, path: &str) -> Result<SongView> {
-        if let Ok(Some(song_entry)) = self
-            .conn
+    pub fn get_song_view(&self, song_id: i32) -> Result<Option<SongView>> {
+        self.conn
+            .query_row(
+                &format!(
+                    "SELECT {SONG_VIEW_COLUMNS}
+                     FROM songs s
+                     JOIN albums al ON s.album_id = al.id
+                     JOIN artists ar ON al.artist_id = ar.id
+                     WHERE s.id = ?1"
+                ),
+                params![song_id],
+                song_view_from_row,
+            )
+            .optional()
+    }
+
+    pub fn get_artists(&self) -> Result<Vec<ArtistView>> {
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT {ARTIST_VIEW_COLUMNS}
+             FROM artists ar
+             JOIN albums al ON al.artist_id = ar.id
+             GROUP BY ar.id
+             ORDER BY ar.name COLLATE NOCASE",
+        ))?;
+
+        stmt.query_map([], artist_view_from_row)
+            .and_then(|iter| iter.collect())
+    }
+
+    pub fn get_albums(&self) -> Result<Vec<AlbumView>> {
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT {ALBUM_VIEW_COLUMNS}
+             FROM albums al
+             JOIN artists ar ON al.artist_id = ar.id
+             ORDER BY al.name COLLATE NOCASE",
+        ))?;
+
+        stmt.query_map([], album_view_from_row)
+            .and_then(|iter| iter.collect())
+    }
+
+    pub fn get_artist_albums(&self, artist_id: i32) -> Result<Vec<AlbumView>> {
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT {ALBUM_VIEW_COLUMNS}
+             FROM albums al
+             JOIN artists ar ON al.artist_id = ar.id
+             WHERE al.artist_id = ?1
+             ORDER BY al.name COLLATE NOCASE",
+        ))?;
+
+        stmt.query_map(params![artist_id], album_view_from_row)
+            .and_then(|iter| iter.collect())
+    }
+
+    pub fn get_album_songs(&self, album_id: i32) -> Result<Vec<SongView>> {
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT {SONG_VIEW_COLUMNS}
+             FROM songs s
+             JOIN albums al ON s.album_id = al.id
+             JOIN artists ar ON al.artist_id = ar.id
+             WHERE s.album_id = ?1
+             ORDER BY s.disc_number, s.track_number, s.title",
+        ))?;
+
+        stmt.query_map(params![album_id], song_view_from_row)
+            .and_then(|iter| iter.collect())
+    }
+
+    pub fn get_artist_songs(&self, artist_id: i32) -> Result<Vec<SongView>> {
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT {SONG_VIEW_COLUMNS}
+             FROM songs s
+             JOIN albums al ON s.album_id = al.id
+             JOIN artists ar ON al.artist_id = ar.id
+             WHERE al.artist_id = ?1
+             ORDER BY al.name, s.disc_number, s.track_number, s.title",
+        ))?;
+
+        stmt.query_map(params![artist_id], song_view_from_row)
+            .and_then(|iter| iter.collect())
+    }
+
+    /// Songs stored under any folder with the given file name, used to relocate moved playlist entries.
+    pub fn find_songs_by_file_name(&self, file_name: &str) -> Result<Vec<SongView>> {
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT {SONG_VIEW_COLUMNS}
+             FROM songs s
+             JOIN albums al ON s.album_id = al.id
+             JOIN artists ar ON al.artist_id = ar.id
+             WHERE s.path LIKE ?1 ESCAPE '\\'",
+        ))?;
+
+        //stored paths use the separator of the platform, and LIKE ignores ASCII case so the names are compared again
+        let escape = |text: &str| text.replace('\\', "\\\\").replace('%', "\\%").replace('_', "\\_");
+        let pattern = format!("%{}{}", escape(std::path::MAIN_SEPARATOR_STR), escape(file_name));
+        let songs: Vec<SongView> = stmt.query_map(params![pattern], song_view_from_row)
+            .and_then(|iter| iter.collect())?;
+        Ok(songs
+            .into_iter()
+            .filter(|song| Path::new(&song.path).file_name().is_some_and(|name| name == file_name))
+            .collect())
+    }
+
+    /// Songs whose title matches, ignoring case.
+    pub fn find_songs_by_title(&self, title: &str) -> Result<Vec<SongView>> {
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT {SONG_VIEW_COLUMNS}
+             FROM songs s
+             JOIN albums al ON s.album_id = al.id
+             JOIN artists ar ON al.artist_id = ar.id
+             WHERE s.title = ?1 COLLATE NOCASE",
+        ))?;
+
+        stmt.query_map(params![title.trim()], song_view_from_row)
+            .and_then(|iter| iter.collect())
+    }
+
+    /// Genres with the number of songs tagged with each.
+    pub fn get_genres(&self) -> Result<Vec<(String, usize)>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT genre, COUNT(*) FROM songs
+             WHERE genre IS NOT NULL
+             GROUP BY genre
+             ORDER BY genre COLLATE NOCASE",
+        )?;
+
+        stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))
+            .and_then(|iter| iter.collect())
+    }
+
+    /// Years with the number of songs released in each, newest first.
+    pub fn get_years(&self) -> Result<Vec<(i32, usize)>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT year, COUNT(*) FROM songs
+             WHERE year IS NOT NULL
+             GROUP BY year
+             ORDER BY year DESC",
+        )?;
+
+        stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))
+            .and_then(|iter| iter.collect())
+    }
+
+    pub fn get_genre_songs(&self, genre: &str) -> Result<Vec<SongView>> {
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT {SONG_VIEW_COLUMNS}
+             FROM songs s
+             JOIN albums al ON s.album_id = al.id
+             JOIN artists ar ON al.artist_id = ar.id
+             WHERE s.genre = ?1
+             ORDER BY ar.name, al.name, s.disc_number, s.track_number, s.title",
+        ))?;
+
+        stmt.query_map(params![genre], song_view_from_row)
+            .and_then(|iter| iter.collect())
+    }
+
+    pub fn get_year_songs(&self, year: i32) -> Result<Vec<SongView>> {
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT {SONG_VIEW_COLUMNS}
+             FROM songs s
+             JOIN albums al ON s.album_id = al.id
+             JOIN artists ar ON al.artist_id = ar.id
+             WHERE s.year = ?1
+             ORDER BY ar.name, al.name, s.disc_number, s.track_number, s.title",
+        ))?;
+
+        stmt.query_map(params![year], song_view_from_row)
+            .and_then(|iter| iter.collect())
+    }
+
+    /// Full-text search over the song index, artists and albums are matched on their own columns only.
+    pub fn search(&self, input: &str, limit: usize) -> Result<SearchResults> {
+        let (Some(songs_query), Some(albums_query), Some(artists_query)) = (
+            fts_query(input, None),
+            fts_query(input, Some("album artist")),
+            fts_query(input, Some("artist")),
+        ) else {
+            return Ok(SearchResults::default());
+        };
+
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT {SONG_VIEW_COLUMNS}
+             FROM song_search
+             JOIN songs s ON s.id = song_search.rowid
+             JOIN albums al ON s.album_id = al.id
+             JOIN artists ar ON al.artist_id = ar.id
+             WHERE song_search MATCH ?1
+             ORDER BY rank
+             LIMIT ?2",
+        ))?;
+        let songs = stmt.query_map(params![songs_query, limit as i64], song_view_from_row)
+            .and_then(|iter| iter.collect())?;
+
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT {ALBUM_VIEW_COLUMNS}
+             FROM song_search
+             JOIN songs s ON s.id = song_search.rowid
+             JOIN albums al ON s.album_id = al.id
+             JOIN artists ar ON al.artist_id = ar.id
+             WHERE song_search MATCH ?1
+             GROUP BY al.id
+             ORDER BY MIN(rank)
+             LIMIT ?2",
+        ))?;
+        let albums = stmt.query_map(params![albums_query, limit as i64], album_view_from_row)
+            .and_then(|iter| iter.collect())?;
+
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT {ARTIST_VIEW_COLUMNS}
+             FROM song_search
+             JOIN songs s ON s.id = song_search.rowid
+             JOIN albums al ON s.album_id = al.id
+             JOIN artists ar ON al.artist_id = ar.id
+             WHERE song_search MATCH ?1
+             GROUP BY ar.id
+             ORDER BY MIN(rank)
+             LIMIT ?2",
+        ))?;
+        let artists = stmt.query_map(params![artists_query, limit as i64], artist_view_from_row)
+            .and_then(|iter| iter.collect())?;
+
+        Ok(SearchResults { artists, albums, songs })
+    }
+
+    /// Looks the song up without touching the file, `None` when it hasn't been indexed yet.
+    pub fn find_song_view_by_path(&self, path: &str) -> Result<Option<SongView>> {
+        self.conn
             .query_row(
-                "SELECT
-                            s.id,
-                            s.path,
-                            s.title,
-                            ar.name,
-                            al.name,
-                            al.cover_art_path,
-                            s.track_number,
-                            s.duration_seconds,
-                            s.play_count
+                &format!(
+                    "SELECT {SONG_VIEW_COLUMNS}
                         FROM songs s
                         JOIN albums al ON s.album_id = al.id
                         JOIN artists ar ON al.artist_id = ar.id
-                        WHERE s.path = ?1",
+                        WHERE s.path = ?1"
+                ),
                 params![path],
-                |row| {
-                    Ok(SongView {
-                        id: row.get(0)?,
-                        path: row.get(1)?,
-                        title: row.get(2)?,
-                        artist: row.get(3)?,
-                        album: row.get(4)?,
-                        album_art_path: row.get(5)?,
-                        track_number: row.get(6)?,
-                        duration_seconds: row.get(7)?,
-                        play_count: row.get(8)?,
-                    })
-                },
+                song_view_from_row,
             )
             .optional()
-        {
+    }
+
+    pub fn get_song_view_by_path(&self, path: &str) -> Result<SongView> {
+        if let Ok(Some(song_entry)) = self.find_song_view_by_path(path) {
             Ok(song_entry)
         } else {
             if let Some(song) = self.add_or_get_song_by_path(path).ok() {
-                let song_view = song.to_song_view(self).unwrap();
-                Ok(song_view)
+                song.to_song_view(self)
             } else {
                 Err(rusqlite::Error::QueryReturnedNoRows)
             }
@@ -592,15 +1250,17 @@ impl Db {
         stmt.query_row(params![id], |row| Ok((row.get(0)?, row.get(1)?)))
     }
 
-    pub fn get_all_playlists(&self) -> Result<Vec<(i32, String)>> {
+    /// Id, name and whether each playlist is a smart one.
+    pub fn get_all_playlists(&self) -> Result<Vec<(i32, String, bool)>> {
         let mut stmt = self
             .conn
-            .prepare("SELECT id, name FROM playlists ORDER BY name ASC")?;
+            .prepare("SELECT id, name, rules IS NOT NULL FROM playlists ORDER BY name ASC")?;
 
-        stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))
+        stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))
             .and_then(|iter| iter.collect())
     }
 
+    /// Removes every entry of the song from the playlist.
     pub fn remove_song_from_playlist(&self, playlist_id: i32, song_id: i32) -> Result<()> {
         self.conn.execute(
             "DELETE FROM playlist_songs WHERE playlist_id = ?1 AND song_id = ?2",
@@ -609,6 +1269,15 @@ impl Db {
         Ok(())
     }
 
+    /// Removes a single entry, leaving other copies of the same song in place.
+    pub fn remove_playlist_entry(&self, entry_id: i32) -> Result<()> {
+        self.conn.execute(
+            "DELETE FROM playlist_songs WHERE id = ?1",
+            params![entry_id],
+        )?;
+        Ok(())
+    }
+
     pub fn delete_playlist(&self, playlist_id: i32) -> Result<()> {
         self.conn.execute(
             "DELETE FROM playlist_songs WHERE playlist_id = ?1",
@@ -627,24 +1296,70 @@ impl Db {
         Ok(())
     }
 
-    pub fn handle_db_version_change(&self) -> Result<()> {
-        let user_version: i32 = self
-            .conn
-            .query_row("PRAGMA user_version;", [], |row| row.get(0))?;
+    pub fn get_songs_without_loudness(&self, limit: usize) -> Result<Vec<(i32, String)>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT id, path FROM songs
+             WHERE track_gain IS NULL AND loudness_analyzed = 0
+             LIMIT ?1",
+        )?;
 
-        if user_version != DB_STATE_VERSION {
-            println!(
-                "Database version mismatch. Expected: {}, Found: {}. Purging database.",
-                DB_STATE_VERSION, user_version
-            );
-            let _ = self.purge_db();
-            self.conn
-                .pragma_update(None, "user_version", &DB_STATE_VERSION)?;
-        }
+        stmt.query_map(params![limit as i64], |row| Ok((row.get(0)?, row.get(1)?)))
+            .and_then(|iter| iter.collect())
+    }
 
+    pub fn store_loudness_analysis(&self, song_id: i32, result: Option<(f32, f32)>) -> Result<()> {
+        let (gain, peak) = result.unzip();
+        self.conn.execute(
+            "UPDATE songs SET track_gain = ?1, track_peak = ?2, loudness_analyzed = 1 WHERE id = ?3",
+            params![gain, peak, song_id],
+        )?;
         Ok(())
     }
 
+    /// Derives album gains for albums where every song has a track gain but some lack an album gain,
+    /// by power averaging the track loudness weighted by duration.
+    pub fn fill_missing_album_gains(&self) -> Result<()> {
+        let album_ids: Vec<i32> = {
+            let mut stmt = self.conn.prepare(
+                "SELECT album_id FROM songs
+                 GROUP BY album_id
+                 HAVING SUM(album_gain IS NULL) > 0 AND SUM(track_gain IS NULL) = 0",
+            )?;
+            stmt.query_map([], |row| row.get(0))
+                .and_then(|iter| iter.collect())?
+        };
+
+        for album_id in album_ids {
+            let tracks: Vec<(f32, Option<f32>, u64)> = {
+                let mut stmt = self.conn.prepare(
+                    "SELECT track_gain, track_peak, duration_seconds FROM songs WHERE album_id = ?1",
+                )?;
+                stmt.query_map(params![album_id], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))
+                    .and_then(|iter| iter.collect())?
+            };
+            let total_duration: f64 = tracks.iter().map(|(_, _, duration)| (*duration).max(1) as f64).sum();
+            let mean_power: f64 = tracks.iter()
+                .map(|(gain, _, duration)| {
+                    let loudness = (REFERENCE_LOUDNESS - gain) as f64;
+                    10f64.powf(loudness / 10.0) * (*duration).max(1) as f64
+                })
+                .sum::<f64>() / total_duration;
+            let album_gain = REFERENCE_LOUDNESS - (10.0 * mean_power.log10()) as f32;
+            let album_peak = tracks.iter().filter_map(|(_, peak, _)| *peak).reduce(f32::max);
+
+            self.conn.execute(
+                "UPDATE songs SET album_gain = ?1, album_peak = ?2 WHERE album_id = ?3 AND album_gain IS NULL",
+                params![album_gain, album_peak, album_id],
+            )?;
+        }
+        Ok(())
+    }
+
+    pub fn handle_db_version_change(&mut self) -> anyhow::Result<()> {
+        let db_path = PathBuf::from(&self.cache_path).join(DB_FILE_NAME);
+        migrations::migrate(&mut self.conn, &db_path)
+    }
+
     pub fn remove_a_song_from_db(&self, path: &str) -> Result<()> {
         let song_info: Option<(i32, i32)> = self
             .conn
@@ -660,42 +1375,221 @@ impl Db {
                 "DELETE FROM playlist_songs WHERE song_id = ?1",
                 params![song_id],
             )?;
+            self.conn.execute(
+                "DELETE FROM plays WHERE song_id = ?1",
+                params![song_id],
+            )?;
 
             self.conn
                 .execute("DELETE FROM songs WHERE id = ?1", params![song_id])?;
 
-            let album_has_songs: bool = self.conn.query_row(
-                "SELECT EXISTS(SELECT 1 FROM songs WHERE album_id = ?1)",
-                params![album_id],
-                |row| row.get(0),
+            self.remove_album_if_empty(album_id)?;
+        }
+
+        Ok(())
+    }
+
+    pub fn get_song_rating(&self, song_id: i32) -> Result<SongRating> {
+        self.conn
+            .query_row(
+                "SELECT rating, loved FROM songs WHERE id = ?1",
+                params![song_id],
+                |row| Ok(SongRating { stars: row.get(0)?, loved: row.get(1)? }),
+            )
+            .optional()
+            .map(Option::unwrap_or_default)
+    }
+
+    /// Sets the star rating, 0 clears it.
+    pub fn set_song_rating(&self, song_id: i32, stars: u8) -> Result<()> {
+        self.conn.execute(
+            "UPDATE songs SET rating = ?1 WHERE id = ?2",
+            params![stars.min(MAX_STARS), song_id],
+        )?;
+        Ok(())
+    }
+
+    pub fn set_song_loved(&self, song_id: i32, loved: bool) -> Result<()> {
+        self.conn.execute("UPDATE songs SET loved = ?1 WHERE id = ?2", params![loved, song_id])?;
+        Ok(())
+    }
+
+    /// Stores a play, and adds to the song's play count when it was long enough to count.
+    pub fn record_play(&self, song_id: i32, started_at: i64, listened_ms: u64, completed: bool, counted: bool) -> Result<()> {
+        let tx = self.conn.unchecked_transaction()?;
+        tx.execute(
+            "INSERT INTO plays (song_id, started_at, listened_ms, completed, counted) VALUES (?1, ?2, ?3, ?4, ?5)",
+            params![song_id, started_at, listened_ms as i64, completed, counted],
+        )?;
+        if counted {
+            tx.execute(
+                "UPDATE songs SET play_count = play_count + 1 WHERE id = ?1",
+                params![song_id],
             )?;
+        }
+        tx.commit()
+    }
 
-            if !album_has_songs {
-                let artist_id: i32 = self.conn.query_row(
-                    "SELECT artist_id FROM albums WHERE id = ?1",
-                    params![album_id],
-                    |row| row.get(0),
-                )?;
+    /// Most played songs since the given unix time, with their number of counted plays.
+    pub fn get_top_songs(&self, since: i64, limit: usize) -> Result<Vec<(SongView, usize)>> {
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT {SONG_VIEW_COLUMNS}, COUNT(p.id) AS plays
+             FROM plays p
+             JOIN songs s ON p.song_id = s.id
+             JOIN albums al ON s.album_id = al.id
+             JOIN artists ar ON al.artist_id = ar.id
+             WHERE p.counted AND p.started_at >= ?1
+             GROUP BY s.id
+             ORDER BY plays DESC, MAX(p.started_at) DESC
+             LIMIT ?2",
+        ))?;
 
-                self.conn
-                    .execute("DELETE FROM albums WHERE id = ?1", params![album_id])?;
+        stmt.query_map(params![since, limit as i64], |row| Ok((song_view_from_row(row)?, row.get(SONG_VIEW_COLUMN_COUNT)?)))
+            .and_then(|iter| iter.collect())
+    }
 
-                let artist_has_albums: bool = self.conn.query_row(
-                    "SELECT EXISTS(SELECT 1 FROM albums WHERE artist_id = ?1)",
-                    params![artist_id],
-                    |row| row.get(0),
-                )?;
+    /// Most played artists, compilation tracks count for their own artist rather than Various Artists.
+    pub fn get_top_artists(&self, since: i64, limit: usize) -> Result<Vec<(String, usize)>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT COALESCE(s.track_artist, ar.name) AS artist, COUNT(p.id) AS plays
+             FROM plays p
+             JOIN songs s ON p.song_id = s.id
+             JOIN albums al ON s.album_id = al.id
+             JOIN artists ar ON al.artist_id = ar.id
+             WHERE p.counted AND p.started_at >= ?1
+             GROUP BY artist
+             ORDER BY plays DESC, artist COLLATE NOCASE
+             LIMIT ?2",
+        )?;
 
-                if !artist_has_albums {
-                    self.conn
-                        .execute("DELETE FROM artists WHERE id = ?1", params![artist_id])?;
-                }
-            }
+        stmt.query_map(params![since, limit as i64], |row| Ok((row.get(0)?, row.get(1)?)))
+            .and_then(|iter| iter.collect())
+    }
+
+    pub fn get_top_albums(&self, since: i64, limit: usize) -> Result<Vec<(AlbumView, usize)>> {
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT {ALBUM_VIEW_COLUMNS}, COUNT(p.id) AS plays
+             FROM plays p
+             JOIN songs s ON p.song_id = s.id
+             JOIN albums al ON s.album_id = al.id
+             JOIN artists ar ON al.artist_id = ar.id
+             WHERE p.counted AND p.started_at >= ?1
+             GROUP BY al.id
+             ORDER BY plays DESC, al.name COLLATE NOCASE
+             LIMIT ?2",
+        ))?;
+
+        stmt.query_map(params![since, limit as i64], |row| Ok((album_view_from_row(row)?, row.get(4)?)))
+            .and_then(|iter| iter.collect())
+    }
+
+    /// Counted plays, time listened and skips since the given unix time, skipped plays included in the time.
+    pub fn get_listening_totals(&self, since: i64) -> Result<ListeningTotals> {
+        self.conn.query_row(
+            "SELECT COALESCE(SUM(counted), 0), COALESCE(SUM(listened_ms), 0), COALESCE(SUM(NOT completed), 0)
+             FROM plays
+             WHERE started_at >= ?1",
+            params![since],
+            |row| {
+                Ok(ListeningTotals {
+                    plays: row.get(0)?,
+                    listened_ms: row.get::<_, i64>(1)? as u64,
+                    skips: row.get(2)?,
+                })
+            },
+        )
+    }
+
+    /// Local days with at least one counted play, as ascending day numbers, followed by today's.
+    pub fn get_listening_days(&self) -> Result<(Vec<i64>, i64)> {
+        let mut stmt = self.conn.prepare(
+            "SELECT DISTINCT CAST(julianday(date(started_at, 'unixepoch', 'localtime')) AS INTEGER) AS day
+             FROM plays
+             WHERE counted
+             ORDER BY day",
+        )?;
+        let days = stmt.query_map([], |row| row.get(0))?.collect::<Result<Vec<i64>>>()?;
+        let today = self.conn.query_row(
+            "SELECT CAST(julianday(date('now', 'localtime')) AS INTEGER)",
+            [],
+            |row| row.get(0),
+        )?;
+        Ok((days, today))
+    }
+
+    /// Deletes the album once its last song is gone, and its artist once their last album is.
+    fn remove_album_if_empty(&self, album_id: i32) -> Result<()> {
+        let album_has_songs: bool = self.conn.query_row(
+            "SELECT EXISTS(SELECT 1 FROM songs WHERE album_id = ?1)",
+            params![album_id],
+            |row| row.get(0),
+        )?;
+
+        if album_has_songs {
+            return Ok(());
+        }
+
+        let Some(artist_id): Option<i32> = self
+            .conn
+            .query_row(
+                "SELECT artist_id FROM albums WHERE id = ?1",
+                params![album_id],
+                |row| row.get(0),
+            )
+            .optional()?
+        else {
+            return Ok(());
+        };
+
+        self.conn.execute(
+            "DELETE FROM album_eq_presets WHERE album_id = ?1",
+            params![album_id],
+        )?;
+        self.conn
+            .execute("DELETE FROM albums WHERE id = ?1", params![album_id])?;
+
+        let artist_has_albums: bool = self.conn.query_row(
+            "SELECT EXISTS(SELECT 1 FROM albums WHERE artist_id = ?1)",
+            params![artist_id],
+            |row| row.get(0),
+        )?;
+
+        if !artist_has_albums {
+            self.conn
+                .execute("DELETE FROM artists WHERE id = ?1", params![artist_id])?;
         }
 
         Ok(())
     }
 
+    /// Paths of the songs at `path` or anywhere inside it when it is a folder.
+    pub fn get_song_paths_under(&self, path: &str) -> Result<Vec<String>> {
+        let folder = format!("{}{}", path, std::path::MAIN_SEPARATOR);
+        let mut stmt = self.conn.prepare(
+            "SELECT path FROM songs WHERE path = ?1 OR substr(path, 1, length(?2)) = ?2",
+        )?;
+        stmt.query_map(params![path, folder], |row| row.get(0))
+            .and_then(|iter| iter.collect())
+    }
+
+    /// Points the songs at `from`, or inside it when it is a folder, to their new location under `to`.
+    /// Ids stay the same, so playlists and play counts follow the files.
+    pub fn move_songs(&self, from: &str, to: &str) -> Result<usize> {
+        let folder = format!("{}{}", from, std::path::MAIN_SEPARATOR);
+        let moved = self.conn.execute(
+            "UPDATE songs SET path = ?2 || substr(path, length(?1) + 1)
+             WHERE path = ?1 OR substr(path, 1, length(?3)) = ?3",
+            params![from, to, folder],
+        )?;
+        //covers found next to the songs move with them
+        self.conn.execute(
+            "UPDATE albums SET cover_art_path = ?2 || substr(cover_art_path, length(?1) + 1)
+             WHERE substr(cover_art_path, 1, length(?3)) = ?3",
+            params![from, to, folder],
+        )?;
+        Ok(moved)
+    }
+
     pub fn check_db_ver(&self) -> Result<bool> {
         let user_version: i32 = self
             .conn
@@ -703,14 +1597,29 @@ impl Db {
 
         Ok(user_version == DB_STATE_VERSION)
     }
+}
 
-    pub fn purge_db(&self) -> Result<()> {
-        self.conn.execute("DROP TABLE playlist_songs", [])?;
-        self.conn.execute("DROP TABLE playlists", [])?;
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-        self.conn.execute("DROP TABLE songs", [])?;
-        self.conn.execute("DROP TABLE albums", [])?;
-        self.conn.execute("DROP TABLE artists", [])?;
-        Ok(())
+    #[test]
+    fn song_view_column_count_matches_columns() {
+        assert_eq!(SONG_VIEW_COLUMNS.split(',').count(), SONG_VIEW_COLUMN_COUNT);
+    }
+
+    #[test]
+    fn find_song_view_by_path_leaves_unindexed_files_alone() {
+        let dir = std::env::temp_dir().join(format!("round-db-{}-find-song-view", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let db = Db::open(dir.to_string_lossy().to_string());
+        let path = dir.join("broken.mp3").to_string_lossy().to_string();
+        std::fs::write(&path, b"not audio").unwrap();
+
+        assert_eq!(db.find_song_view_by_path(&path).unwrap(), None);
+        assert_eq!(db.get_file_stamp(&path).unwrap(), None);
+        assert!(db.get_song_view_by_path(&path).is_err());
+        let _ = std::fs::remove_dir_all(&dir);
     }
 }
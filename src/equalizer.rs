@@ -0,0 +1,275 @@
+use std::{str::FromStr, sync::{Arc, Mutex, atomic::{AtomicU64, Ordering}}, time::Duration};
+
+use rodio::{ChannelCount, SampleRate, Source, source::SeekError};
+use serde::{Deserialize, Serialize};
+
+use crate::{biquad::Biquad, db::Db};
+
+pub const EQUALIZER_SETTING: &str = "equalizer";
+pub const GRAPHIC_BANDS_HZ: [f32; 10] = [31.0, 62.0, 125.0, 250.0, 500.0, 1000.0, 2000.0, 4000.0, 8000.0, 16000.0];
+pub const MAX_BAND_GAIN_DB: f32 = 12.0;
+const GRAPHIC_BAND_Q: f32 = 1.41; //one octave bandwidth
+const PARAMETER_CHECK_INTERVAL: usize = 512; //samples between checks for new settings
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FilterKind {
+    Peaking,
+    LowShelf,
+    HighShelf,
+}
+
+impl FilterKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            FilterKind::Peaking => "peaking",
+            FilterKind::LowShelf => "low_shelf",
+            FilterKind::HighShelf => "high_shelf",
+        }
+    }
+}
+
+impl FromStr for FilterKind {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<FilterKind, String> {
+        match value {
+            "peaking" => Ok(FilterKind::Peaking),
+            "low_shelf" => Ok(FilterKind::LowShelf),
+            "high_shelf" => Ok(FilterKind::HighShelf),
+            _ => Err(format!("Unknown filter kind: {}", value)),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ParametricBand {
+    pub kind: FilterKind,
+    pub frequency: f32,
+    pub gain_db: f32,
+    pub q: f32,
+}
+
+impl Default for ParametricBand {
+    fn default() -> Self {
+        ParametricBand {
+            kind: FilterKind::Peaking,
+            frequency: 1000.0,
+            gain_db: 0.0,
+            q: 1.0,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct EqualizerSettings {
+    pub enabled: bool,
+    pub preamp_db: f32,
+    pub graphic: [f32; 10],
+    pub parametric: Vec<ParametricBand>,
+}
+
+impl Default for EqualizerSettings {
+    fn default() -> Self {
+        EqualizerSettings {
+            enabled: true,
+            preamp_db: 0.0,
+            graphic: [0.0; 10],
+            parametric: Vec::new(),
+        }
+    }
+}
+
+impl EqualizerSettings {
+    pub fn load(db: &Db) -> EqualizerSettings {
+        db.get_setting(EQUALIZER_SETTING)
+            .ok()
+            .flatten()
+            .and_then(|value| EqualizerSettings::from_json(&value))
+            .unwrap_or_default()
+    }
+
+    pub fn save(&self, db: &Db) -> rusqlite::Result<()> {
+        db.set_setting(EQUALIZER_SETTING, &self.to_json())
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).unwrap_or_default()
+    }
+
+    pub fn from_json(value: &str) -> Option<EqualizerSettings> {
+        serde_json::from_str(value).ok()
+    }
+
+    /// Filter cascade for the given sample rate, bands without gain or above Nyquist are left out.
+    fn filters(&self, sample_rate: u32) -> Vec<Biquad> {
+        if !self.enabled {
+            return Vec::new();
+        }
+        let fs = sample_rate as f64;
+        let graphic = GRAPHIC_BANDS_HZ.iter()
+            .zip(self.graphic.iter())
+            .map(|(frequency, gain_db)| ParametricBand {
+                kind: FilterKind::Peaking,
+                frequency: *frequency,
+                gain_db: *gain_db,
+                q: GRAPHIC_BAND_Q,
+            });
+        graphic
+            .chain(self.parametric.iter().copied())
+            .filter(|band| band.gain_db.abs() > 0.01 && (band.frequency as f64) < fs / 2.0 && band.frequency > 0.0)
+            .map(|band| {
+                let (frequency, gain_db, q) = (band.frequency as f64, band.gain_db as f64, band.q.max(0.1) as f64);
+                match band.kind {
+                    FilterKind::Peaking => Biquad::peaking(fs, frequency, gain_db, q),
+                    FilterKind::LowShelf => Biquad::low_shelf(fs, frequency, gain_db, q),
+                    FilterKind::HighShelf => Biquad::high_shelf(fs, frequency, gain_db, q),
+                }
+            })
+            .collect()
+    }
+
+    fn preamp_factor(&self) -> f32 {
+        if self.enabled { 10f32.powf(self.preamp_db / 20.0) } else { 1.0 }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct EqualizerPreset {
+    pub id: i32,
+    pub name: String,
+    pub settings: EqualizerSettings,
+}
+
+/// Equalizer settings shared with every playing track, so changes are heard without reloading.
+#[derive(Clone)]
+pub struct EqualizerControl {
+    settings: Arc<Mutex<EqualizerSettings>>,
+    version: Arc<AtomicU64>,
+}
+
+impl Default for EqualizerControl {
+    fn default() -> Self {
+        EqualizerControl::new()
+    }
+}
+
+impl EqualizerControl {
+    pub fn new() -> Self {
+        EqualizerControl {
+            settings: Arc::new(Mutex::new(EqualizerSettings::default())),
+            version: Arc::new(AtomicU64::new(0)),
+        }
+    }
+
+    pub fn set(&self, settings: EqualizerSettings) {
+        *self.settings.lock().unwrap() = settings;
+        self.version.fetch_add(1, Ordering::Release);
+    }
+
+    pub fn wrap<S: Source>(&self, input: S) -> EqualizerSource<S> {
+        EqualizerSource {
+            input,
+            control: self.clone(),
+            version: None,
+            channels: 0,
+            sample_rate: 0,
+            filters: Vec::new(),
+            preamp: 1.0,
+            channel: 0,
+            until_check: 0,
+        }
+    }
+}
+
+pub struct EqualizerSource<S: Source> {
+    input: S,
+    control: EqualizerControl,
+    version: Option<u64>,
+    channels: usize,
+    sample_rate: u32,
+    filters: Vec<Vec<Biquad>>, //per channel
+    preamp: f32,
+    channel: usize,
+    until_check: usize,
+}
+
+impl<S: Source> EqualizerSource<S> {
+    fn refresh(&mut self) {
+        let version = self.control.version.load(Ordering::Acquire);
+        let channels = self.input.channels().max(1) as usize;
+        let sample_rate = self.input.sample_rate();
+        if self.version == Some(version) && self.channels == channels && self.sample_rate == sample_rate {
+            return;
+        }
+        let settings = self.control.settings.lock().unwrap().clone();
+        let filters = settings.filters(sample_rate);
+        let keep_state = self.channels == channels
+            && self.filters.first().is_some_and(|existing| existing.len() == filters.len());
+        if keep_state {
+            for channel_filters in self.filters.iter_mut() {
+                for (filter, new) in channel_filters.iter_mut().zip(filters.iter()) {
+                    filter.set_coefficients(new);
+                }
+            }
+        } else {
+            self.filters = vec![filters; channels];
+            self.channel = 0;
+        }
+        self.preamp = settings.preamp_factor();
+        self.version = Some(version);
+        self.channels = channels;
+        self.sample_rate = sample_rate;
+    }
+}
+
+impl<S: Source> Iterator for EqualizerSource<S> {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        if self.until_check == 0 {
+            self.refresh();
+            self.until_check = PARAMETER_CHECK_INTERVAL;
+        }
+        self.until_check -= 1;
+
+        let sample = self.input.next()?;
+        let filters = &mut self.filters[self.channel];
+        self.channel = (self.channel + 1) % self.channels;
+        if filters.is_empty() && self.preamp == 1.0 {
+            return Some(sample);
+        }
+        let filtered = filters
+            .iter_mut()
+            .fold(sample as f64, |value, filter| filter.process(value));
+        Some(filtered as f32 * self.preamp)
+    }
+}
+
+impl<S: Source> Source for EqualizerSource<S> {
+    fn current_span_len(&self) -> Option<usize> {
+        self.input.current_span_len()
+    }
+
+    fn channels(&self) -> ChannelCount {
+        self.input.channels()
+    }
+
+    fn sample_rate(&self) -> SampleRate {
+        self.input.sample_rate()
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        self.input.total_duration()
+    }
+
+    fn try_seek(&mut self, pos: Duration) -> Result<(), SeekError> {
+        self.input.try_seek(pos)?;
+        //drop the filter state so the old signal doesn't ring into the new position
+        self.filters.clear();
+        self.version = None;
+        self.until_check = 0;
+        Ok(())
+    }
+}
@@ -0,0 +1,301 @@
+use dioxus::prelude::*;
+
+use crate::{app_context::PlayerContext, equalizer::{EqualizerSettings, FilterKind, ParametricBand, GRAPHIC_BANDS_HZ, MAX_BAND_GAIN_DB}};
+
+fn band_label(frequency: f32) -> String {
+    if frequency >= 1000.0 {
+        format!("{}k", frequency / 1000.0)
+    } else {
+        format!("{}", frequency)
+    }
+}
+
+fn gain_style(gain_db: f32) -> String {
+    format!("--slider-progress: {}%;", (gain_db + MAX_BAND_GAIN_DB) / (2.0 * MAX_BAND_GAIN_DB) * 100.0)
+}
+
+#[component]
+pub fn equalizer_panel() -> Element {
+    let player_context = use_context::<PlayerContext>();
+
+    let ctx = player_context.clone();
+    let settings = use_memo(move || ctx.active_equalizer());
+    let album_preset = player_context.album_eq_preset;
+    let presets = player_context.eq_presets;
+    let playing_state = player_context.playing_state;
+    let current_album = use_memo(move || playing_state.read().current_song().map(|song| song.album_id));
+
+    let mut selected_preset = use_signal(|| None::<i32>);
+    let mut preset_name = use_signal(String::new);
+
+    let update = {
+        let ctx = player_context.clone();
+        move |change: &dyn Fn(&mut EqualizerSettings)| {
+            let mut new_settings = settings();
+            change(&mut new_settings);
+            ctx.clone().set_equalizer(new_settings);
+        }
+    };
+
+    let graphic_bands = GRAPHIC_BANDS_HZ.iter().enumerate().map(|(index, frequency)| {
+        let update = update.clone();
+        let gain_db = settings().graphic[index];
+        rsx! {
+            div { class: "eq-band", key: "{index}",
+                div { class: "eq-band-slider",
+                    input {
+                        class: "slider eq-slider",
+                        r#type: "range",
+                        title: "{gain_db:+.1} dB",
+                        min: "-{MAX_BAND_GAIN_DB}",
+                        max: "{MAX_BAND_GAIN_DB}",
+                        step: "0.5",
+                        value: gain_db,
+                        style: gain_style(gain_db),
+                        oninput: move |evt: Event<FormData>| {
+                            if let Ok(gain_db) = evt.value().parse::<f32>() {
+                                update(&|s| s.graphic[index] = gain_db);
+                            }
+                        },
+                    }
+                }
+                span { class: "eq-band-label", "{band_label(*frequency)}" }
+            }
+        }
+    });
+
+    let parametric_bands = settings().parametric.into_iter().enumerate().map(|(index, band)| {
+        let set_band = {
+            let update = update.clone();
+            move |change: &dyn Fn(&mut ParametricBand)| {
+                update(&|s| {
+                    if let Some(band) = s.parametric.get_mut(index) {
+                        change(band);
+                    }
+                });
+            }
+        };
+        let remove = update.clone();
+        rsx! {
+            div { class: "eq-parametric-band", key: "{index}",
+                select {
+                    value: band.kind.as_str(),
+                    onchange: {
+                        let set_band = set_band.clone();
+                        move |evt: Event<FormData>| {
+                            if let Ok(kind) = evt.value().parse::<FilterKind>() {
+                                set_band(&|b| b.kind = kind);
+                            }
+                        }
+                    },
+                    option { value: "peaking", "Peak" }
+                    option { value: "low_shelf", "Low shelf" }
+                    option { value: "high_shelf", "High shelf" }
+                }
+                input {
+                    r#type: "number",
+                    title: "Frequency (Hz)",
+                    min: "20",
+                    max: "20000",
+                    value: band.frequency,
+                    onchange: {
+                        let set_band = set_band.clone();
+                        move |evt: Event<FormData>| {
+                            if let Ok(frequency) = evt.value().parse::<f32>() {
+                                set_band(&|b| b.frequency = frequency.clamp(20.0, 20000.0));
+                            }
+                        }
+                    },
+                }
+                input {
+                    r#type: "number",
+                    title: "Gain (dB)",
+                    min: "-{MAX_BAND_GAIN_DB}",
+                    max: "{MAX_BAND_GAIN_DB}",
+                    step: "0.5",
+                    value: band.gain_db,
+                    onchange: {
+                        let set_band = set_band.clone();
+                        move |evt: Event<FormData>| {
+                            if let Ok(gain_db) = evt.value().parse::<f32>() {
+                                set_band(&|b| b.gain_db = gain_db.clamp(-MAX_BAND_GAIN_DB, MAX_BAND_GAIN_DB));
+                            }
+                        }
+                    },
+                }
+                input {
+                    r#type: "number",
+                    title: "Q",
+                    min: "0.1",
+                    max: "10",
+                    step: "0.1",
+                    value: band.q,
+                    onchange: move |evt: Event<FormData>| {
+                        if let Ok(q) = evt.value().parse::<f32>() {
+                            set_band(&|b| b.q = q.clamp(0.1, 10.0));
+                        }
+                    },
+                }
+                button {
+                    title: "Remove band",
+                    onclick: move |_| remove(&|s| { s.parametric.remove(index); }),
+                    "✕"
+                }
+            }
+        }
+    });
+
+    let load_preset = {
+        let ctx = player_context.clone();
+        move |preset_id: i32| {
+            let Some(preset) = presets.read().iter().find(|p| p.id == preset_id).cloned() else {
+                return;
+            };
+            let mut ctx = ctx.clone();
+            match (current_album(), album_preset.read().is_some()) {
+                (Some(album_id), true) => ctx.set_album_eq_preset(album_id, Some(preset.id)),
+                _ => ctx.set_equalizer(preset.settings),
+            }
+        }
+    };
+
+    rsx! {
+        div { class: "equalizer-panel",
+            div { class: "settings-section",
+                div { class: "settings-row",
+                    h4 { "equalizer" }
+                    input {
+                        r#type: "checkbox",
+                        title: "Enable equalizer",
+                        checked: settings().enabled,
+                        onchange: {
+                            let update = update.clone();
+                            move |evt: Event<FormData>| {
+                                let checked = evt.checked();
+                                update(&|s| s.enabled = checked);
+                            }
+                        },
+                    }
+                }
+                if let (Some(preset), Some(album_id)) = (album_preset(), current_album()) {
+                    div { class: "settings-row eq-album-preset",
+                        span { "Album preset: {preset.name}" }
+                        button {
+                            onclick: {
+                                let ctx = player_context.clone();
+                                move |_| ctx.clone().set_album_eq_preset(album_id, None)
+                            },
+                            "Clear"
+                        }
+                    }
+                }
+                div { class: "settings-row",
+                    span { "Pre-amp: {settings().preamp_db:+.1} dB" }
+                    input {
+                        class: "slider",
+                        r#type: "range",
+                        min: "-{MAX_BAND_GAIN_DB}",
+                        max: "{MAX_BAND_GAIN_DB}",
+                        step: "0.5",
+                        value: settings().preamp_db,
+                        style: gain_style(settings().preamp_db),
+                        oninput: {
+                            let update = update.clone();
+                            move |evt: Event<FormData>| {
+                                if let Ok(preamp_db) = evt.value().parse::<f32>() {
+                                    update(&|s| s.preamp_db = preamp_db);
+                                }
+                            }
+                        },
+                    }
+                }
+                div { class: "eq-graphic",
+                    {graphic_bands}
+                }
+                div { class: "common-button",
+                    button {
+                        onclick: {
+                            let update = update.clone();
+                            move |_| update(&|s| s.graphic = [0.0; 10])
+                        },
+                        "Flat"
+                    }
+                }
+            }
+            div { class: "settings-section",
+                h4 { "parametric bands" }
+                {parametric_bands}
+                div { class: "common-button",
+                    button {
+                        onclick: move |_| update(&|s| s.parametric.push(ParametricBand::default())),
+                        "+ Band"
+                    }
+                }
+            }
+            div { class: "settings-section",
+                h4 { "presets" }
+                div { class: "settings-row",
+                    select {
+                        value: selected_preset().map(|id| id.to_string()).unwrap_or_default(),
+                        onchange: move |evt: Event<FormData>| {
+                            let preset_id = evt.value().parse::<i32>().ok();
+                            selected_preset.set(preset_id);
+                            if let Some(preset_id) = preset_id {
+                                load_preset(preset_id);
+                            }
+                        },
+                        option { value: "", "Choose preset" }
+                        for preset in presets() {
+                            option { key: "{preset.id}", value: "{preset.id}", "{preset.name}" }
+                        }
+                    }
+                    button {
+                        disabled: selected_preset().is_none(),
+                        onclick: {
+                            let ctx = player_context.clone();
+                            move |_| {
+                                if let Some(preset_id) = selected_preset.take() {
+                                    ctx.clone().delete_eq_preset(preset_id);
+                                }
+                            }
+                        },
+                        "Delete"
+                    }
+                }
+                div { class: "settings-row",
+                    input {
+                        r#type: "text",
+                        placeholder: "Preset name",
+                        value: preset_name(),
+                        oninput: move |evt: Event<FormData>| preset_name.set(evt.value()),
+                    }
+                    button {
+                        disabled: preset_name().trim().is_empty(),
+                        onclick: {
+                            let ctx = player_context.clone();
+                            move |_| {
+                                let name = preset_name().trim().to_string();
+                                ctx.clone().save_eq_preset(&name);
+                                preset_name.set(String::new());
+                            }
+                        },
+                        "Save"
+                    }
+                }
+                if let Some(album_id) = current_album() {
+                    div { class: "common-button",
+                        button {
+                            disabled: selected_preset().is_none(),
+                            title: "Always use the selected preset for this album",
+                            onclick: {
+                                let ctx = player_context.clone();
+                                move |_| ctx.clone().set_album_eq_preset(album_id, selected_preset())
+                            },
+                            "Use for this album"
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
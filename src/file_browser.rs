@@ -6,8 +6,12 @@ use crate::{
     app_context::{DatabaseContext, PlayerContext},
     context_menu::{ContextMenuItem, context_menu},
     create_playlist_dialog::create_playlist_dialog,
+    library_browser::library_browser,
     db::{Db, SongView},
-    playlist_browser::playlist_browser, queue_state::QueueFallbackMode,
+    playlist_browser::playlist_browser, queue_state::QueueFallbackMode, search::search_results,
+    song_info_dialog::song_info_dialog, tag_editor::can_edit_tags, tag_editor_dialog::tag_editor_dialog,
+    playlist_file::{PlaylistImport, import_playlist_file, is_playlist_file}, playlist_import_dialog::playlist_import_dialog, statistics_page::statistics_page,
+    rating::{SongRating, MAX_STARS},
 };
 
 pub const RECOGNIZED_FILE_EXTENSIONS: [&str; 5] = ["mp3", "flac", "wav", "aac", "ogg"];
@@ -15,6 +19,7 @@ pub const RECOGNIZED_FILE_EXTENSIONS: [&str; 5] = ["mp3", "flac", "wav", "aac",
 #[derive(Clone, Debug, PartialEq, Eq, Hash)]
 pub enum SongFileData {
     Song{ song_view: SongView },
+    NotIndexed { }, //audio file the library scanner hasn't read yet
     NotSong { },
 }
 
@@ -22,9 +27,17 @@ impl PartialOrd for SongFileData {
     fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
         match (self, other) {
             (SongFileData::Song { song_view: a, .. }, SongFileData::Song { song_view: b, .. }) => a.partial_cmp(b),
-            (SongFileData::NotSong {}, SongFileData::NotSong {}) => Some(std::cmp::Ordering::Equal),
-            (SongFileData::Song { .. }, SongFileData::NotSong {}) => Some(std::cmp::Ordering::Less),
-            (SongFileData::NotSong {}, SongFileData::Song { .. }) => Some(std::cmp::Ordering::Greater),
+            (a, b) => a.rank().partial_cmp(&b.rank()),
+        }
+    }
+}
+
+impl SongFileData {
+    fn rank(&self) -> u8 {
+        match self {
+            SongFileData::Song { .. } => 0,
+            SongFileData::NotIndexed {} => 1,
+            SongFileData::NotSong {} => 2,
         }
     }
 }
@@ -42,6 +55,10 @@ pub struct FileEntry {
 }
 
 impl FileEntry {
+    pub fn is_playlist_file(&self) -> bool {
+        !self.is_folder && is_playlist_file(&self.path)
+    }
+
     pub fn from_song_view(song: &SongView) -> Self {
         FileEntry {
             path: PathBuf::from(&song.path),
@@ -57,6 +74,15 @@ pub struct ScanResult {
     pub only_contains_audio: bool,
 }
 
+impl ScanResult {
+    pub fn unindexed_files(&self) -> Vec<PathBuf> {
+        self.entries.iter()
+            .filter(|entry| matches!(entry.song_data, SongFileData::NotIndexed {}))
+            .map(|entry| entry.path.clone())
+            .collect()
+    }
+}
+
 pub fn scan_dir(path: &str, db: &Db) -> ScanResult {
     let mut entries = Vec::new();
 
@@ -72,7 +98,8 @@ pub fn scan_dir(path: &str, db: &Db) -> ScanResult {
                 if is_folder {
                     only_contains_audio = false;
                 }
-                if !is_folder &&
+                let playlist = !is_folder && is_playlist_file(&path);
+                if !is_folder && !playlist &&
                     !&entry.file_type()
                     .is_ok_and(|ft| {
                         RECOGNIZED_FILE_EXTENSIONS.contains(
@@ -86,14 +113,10 @@ pub fn scan_dir(path: &str, db: &Db) -> ScanResult {
                 entries.push(FileEntry {
                     path: path.to_path_buf(),
                     is_folder,
-                    song_data: if is_folder {
+                    song_data: if is_folder || playlist {
                         SongFileData::NotSong {}
                     } else {
-                        if let Some(song_data) = get_song_file_data(&path, db) {
-                            song_data
-                        } else {
-                            SongFileData::NotSong {}
-                        }
+                        get_song_file_data(&path, db)
                     },
                 });
 
@@ -117,12 +140,15 @@ pub fn scan_dir(path: &str, db: &Db) -> ScanResult {
     ScanResult { entries, only_contains_audio }
 }
 
-pub fn get_song_file_data(file_path: &Path, db: &Db) -> Option<SongFileData> {
-    let song = db.get_song_view_by_path(file_path.to_string_lossy().as_ref()).ok();
-    if let Some(song) = song {
-        Some(SongFileData::Song { song_view: song})
-    } else {
-        None
+/// Only looks at the database, reading the tags of a big unindexed folder here would block the UI.
+pub fn get_song_file_data(file_path: &Path, db: &Db) -> SongFileData {
+    match db.find_song_view_by_path(file_path.to_string_lossy().as_ref()) {
+        Ok(Some(song)) => SongFileData::Song { song_view: song },
+        Ok(None) => SongFileData::NotIndexed {},
+        Err(e) => {
+            eprintln!("Failed to look up {:?}: {:?}", file_path, e);
+            SongFileData::NotIndexed {}
+        }
     }
 }
 
@@ -134,11 +160,27 @@ pub fn file_browser(starting_path: String) -> Element {
 
     let player_context = use_context::<PlayerContext>();
 
+    let mut search_query = use_signal(String::new);
+
     let items = {
         let db = db.clone();
-        use_memo(move  || scan_dir(current_path().as_str(), db.get()))
+        let library_update_counter = player_context.library_update_counter;
+        use_memo(move  || {
+            let _ = library_update_counter();
+            scan_dir(current_path().as_str(), db.get())
+        })
     };
 
+    {
+        let mut player_context = player_context.clone();
+        use_effect(move || {
+            let unindexed = items().unindexed_files();
+            if !unindexed.is_empty() {
+                player_context.index_files(unindexed);
+            }
+        });
+    }
+
     let playing_state = use_context::<PlayerContext>().playing_state.clone();
 
     let open_folder = move |file_path: String| {
@@ -150,61 +192,137 @@ pub fn file_browser(starting_path: String) -> Element {
             class: "file-explorer",
             div {
                 class: "content-section",
-                h2 { "Files" }
-                div {
-                    class: if !items().only_contains_audio { "item-grid" } else { "item-list" },
-                    self::file_shortcut {
-                        name: "..",
-                        icon: rsx! { "📁" },
-                        path: Path::new(&current_path()).parent().unwrap_or_else(|| Path::new("")).to_string_lossy().to_string(),
-                        on_click: open_folder.clone(),
-                        on_context_menu: move |evt: Event<MouseData>| {
-                            evt.prevent_default();
+                input {
+                    class: "search-box",
+                    r#type: "search",
+                    placeholder: "Search songs, albums and artists",
+                    value: search_query(),
+                    oninput: move |evt: Event<FormData>| search_query.set(evt.value()),
+                    onkeydown: move |evt: Event<KeyboardData>| {
+                        if evt.key() == Key::Escape {
+                            search_query.set(String::new());
+                        }
+                    },
+                }
+                library_scan_status { }
+                if !search_query().trim().is_empty() {
+                    search_results { query: search_query().trim().to_string() }
+                } else {
+                    h2 { "Files" }
+                    div {
+                        class: if !items().only_contains_audio { "item-grid" } else { "item-list" },
+                        self::file_shortcut {
+                            name: "..",
+                            icon: rsx! { "📁" },
+                            path: Path::new(&current_path()).parent().unwrap_or_else(|| Path::new("")).to_string_lossy().to_string(),
+                            on_click: open_folder.clone(),
+                            on_context_menu: move |evt: Event<MouseData>| {
+                                evt.prevent_default();
+                            }
                         }
-                    }
                     
-                    for (index, item) in items().entries.iter().enumerate() {
-                        {
-                            let scan_result = items();
-                            let item = item.clone();
-                            let index = index;
-                            let mut current_path = current_path.clone();
-                            let db = db.clone();
-                            let mut player_context = player_context.clone();
-                            rsx! {
-                                self::song_file { file: item.clone(),
-                                     on_click: move |path| {
-                                        if item.is_folder {
-                                            current_path.set(path);
-                                        } else {
-                                            if let Ok(song) = db.get().get_song_view_by_path(&path) {
-                                                player_context.queue.write().play_song_instant(&song);
-                                                player_context.queue.write().current_fallback_queue.set(QueueFallbackMode::Folder {
-                                                    path: current_path().clone(),
-                                                    current_item: index,
-                                                    entries: scan_result.clone(),
-                                                });
+                        for (index, item) in items().entries.iter().enumerate() {
+                            {
+                                let scan_result = items();
+                                let item = item.clone();
+                                let index = index;
+                                let mut current_path = current_path.clone();
+                                let db = db.clone();
+                                let mut player_context = player_context.clone();
+                                rsx! {
+                                    self::song_file { file: item.clone(),
+                                         on_click: move |path| {
+                                            if item.is_folder {
+                                                current_path.set(path);
+                                            } else if item.is_playlist_file() {
+                                                open_playlist_file(player_context.clone(), db.get(), &path);
+                                            } else {
+                                                if let Ok(song) = db.get().get_song_view_by_path(&path) {
+                                                    player_context.queue.write().play_song_instant(&song);
+                                                    player_context.queue.write().current_fallback_queue.set(QueueFallbackMode::Folder {
+                                                        path: current_path().clone(),
+                                                        current_item: index,
+                                                        entries: scan_result.clone(),
+                                                    });
+                                                }
                                             }
-                                        }
-                                     }
+                                         }
+                                    }
                                 }
                             }
                         }
                     }
+                    library_browser {}
+                    playlist_browser {}
+                    statistics_page {}
                 }
-                playlist_browser {}
             }
             
         }
     }
 }
 
+/// Plays a playlist file straight from the file browser, without importing it.
+pub fn open_playlist_file(mut player_context: PlayerContext, db: &Db, path: &str) {
+    let result = player_context.queue.write().play_playlist_file(path, db);
+    match result {
+        Ok(0) => {}
+        Ok(missing) => player_context.notify(format!("{} entries of the playlist were not found", missing)),
+        Err(e) => player_context.notify(format!("Failed to open playlist: {}", e)),
+    }
+}
+
+/// Creates a library playlist from a playlist file. Returns the import when some entries
+/// were not found, so they can be listed instead of only counted.
+pub fn import_playlist(mut player_context: PlayerContext, db: &Db, path: &str) -> Option<PlaylistImport> {
+    match import_playlist_file(db, Path::new(path)) {
+        Ok(import) => {
+            player_context.playlist_update_counter += 1;
+            if !import.missing.is_empty() {
+                return Some(import);
+            }
+            player_context.notify(format!("Imported {} songs into {}", import.added, import.name));
+        }
+        Err(e) => player_context.notify(format!("Failed to import playlist: {}", e)),
+    }
+    None
+}
+
+#[component]
+pub fn library_scan_status() -> Element {
+    let player_context = use_context::<PlayerContext>();
+    let Some(progress) = (player_context.scan_progress)() else {
+        return rsx! {};
+    };
+
+    rsx! {
+        div { class: "scan-status",
+            span { "Scanning library: {progress.seen} files, {progress.added} added, {progress.updated} updated, {progress.failed} failed" }
+            button {
+                onclick: move |_| player_context.cancel_library_scan(),
+                "Cancel"
+            }
+        }
+    }
+}
+
 #[component]
-pub fn song_file(file: FileEntry, on_click: EventHandler<String>) -> Element {
+pub fn song_file(
+    file: FileEntry,
+    on_click: EventHandler<String>,
+    label: Option<Element>,
+    /// Extra context menu entries for songs, e.g. the ones only a playlist offers.
+    #[props(default)] menu_items: Vec<ContextMenuItem>,
+) -> Element {
     let mut show_context_menu = use_signal(|| false);
     let mut context_menu_pos = use_signal(|| (0.0, 0.0));
     let mut show_add_to_playlist_menu = use_signal(|| false);
     let mut add_playlist_menu_pos = use_signal(|| (0.0, 0.0));
+    let mut show_song_info = use_signal(|| false);
+    let mut show_rating_menu = use_signal(|| false);
+    let mut rating_menu_pos = use_signal(|| (0.0, 0.0));
+    let mut show_tag_editor = use_signal(|| false);
+    let mut import_summary = use_signal(|| None::<PlaylistImport>);
     
     let db = use_context::<DatabaseContext>();
     let mut player_context = use_context::<PlayerContext>();
@@ -219,7 +337,9 @@ pub fn song_file(file: FileEntry, on_click: EventHandler<String>) -> Element {
                     rsx!{"🎵"}
                 }
             }
+            SongFileData::NotSong {  } if file.is_playlist_file() => rsx!{"📜"},
             SongFileData::NotSong {  } => rsx!{"🎵"},
+            SongFileData::NotIndexed {  } => rsx!{ span { title: "Not yet indexed", "⏳" } },
         } }
     } else {
         rsx! { "📁" }
@@ -268,12 +388,53 @@ pub fn song_file(file: FileEntry, on_click: EventHandler<String>) -> Element {
         player_context.queue.write().play_folder_now(&fp_clone, &db_clone.get());
     };
 
-    let song_id = match &file.song_data {
-        SongFileData::Song { song_view } => Some(song_view.id),
-        SongFileData::NotSong {} => None,
+    let song_view = match &file.song_data {
+        SongFileData::Song { song_view } => Some(song_view.clone()),
+        SongFileData::NotIndexed {} | SongFileData::NotSong {} => None,
+    };
+    let song_id = song_view.as_ref().map(|song| song.id);
+
+    let mut selected_songs = player_context.selected_songs;
+    let selected = !file.is_folder && selected_songs().contains(&file_path);
+
+    //a folder edits every song inside it, a selected song the whole selection
+    let db_clone = db.clone();
+    let fp_clone = file_path.clone();
+    let is_folder = file.is_folder;
+    let tag_editor_paths = move || -> Vec<String> {
+        let paths = if is_folder {
+            db_clone.get().get_song_paths_under(&fp_clone).unwrap_or_default()
+        } else if selected_songs.peek().contains(&fp_clone) {
+            selected_songs.peek().clone()
+        } else {
+            vec![fp_clone.clone()]
+        };
+        paths.into_iter().filter(|path| can_edit_tags(path)).collect()
     };
 
-    let context_menu_items = if !file.is_folder { 
+    let is_playlist = file.is_playlist_file();
+    let db_clone = db.clone();
+    let fp_clone = file_path.clone();
+    let context_menu_items = if is_playlist {
+        let db_import = db_clone.clone();
+        let fp_import = fp_clone.clone();
+        let ctx_play = player_context.clone();
+        let ctx_import = player_context.clone();
+        vec![
+            ContextMenuItem {
+                title: "Play".to_string(),
+                action: EventHandler::new(move |_| {
+                    open_playlist_file(ctx_play.clone(), db_clone.get(), &fp_clone);
+                }),
+            },
+            ContextMenuItem {
+                title: "Import as playlist".to_string(),
+                action: EventHandler::new(move |_| {
+                    import_summary.set(import_playlist(ctx_import.clone(), db_import.get(), &fp_import));
+                }),
+            },
+        ]
+    } else if !file.is_folder { 
         let mut items = vec![
             ContextMenuItem {
                 title: "Play".to_string(),
@@ -303,7 +464,46 @@ pub fn song_file(file: FileEntry, on_click: EventHandler<String>) -> Element {
                     show_add_to_playlist_menu.set(true);
                 }),
             });
+            items.push(ContextMenuItem {
+                title: "Rate...".to_string(),
+                action: EventHandler::new(move |_| {
+                    rating_menu_pos.set(context_menu_pos());
+                    show_rating_menu.set(true);
+                }),
+            });
+            if let Some(song) = &song_view {
+                let song_id = song.id;
+                let loved = song.rating.loved;
+                let mut ctx_love = player_context.clone();
+                items.push(ContextMenuItem {
+                    title: if loved { "Unlove".to_string() } else { "Love".to_string() },
+                    action: EventHandler::new(move |_| {
+                        ctx_love.set_loved(song_id, !loved);
+                    }),
+                });
+            }
+            items.push(ContextMenuItem {
+                title: "Song info...".to_string(),
+                action: EventHandler::new(move |_| {
+                    show_song_info.set(true);
+                }),
+            });
+        }
+
+        let selection_size = selected_songs.peek().len();
+        if can_edit_tags(&file_path) {
+            items.push(ContextMenuItem {
+                title: if selected && selection_size > 1 {
+                    format!("Edit tags of {} songs...", selection_size)
+                } else {
+                    "Edit tags...".to_string()
+                },
+                action: EventHandler::new(move |_| {
+                    show_tag_editor.set(true);
+                }),
+            });
         }
+        items.extend(menu_items);
         
         items
     } else {
@@ -326,16 +526,39 @@ pub fn song_file(file: FileEntry, on_click: EventHandler<String>) -> Element {
                     add_folder_to_queue();
                 }),
             },
+            ContextMenuItem {
+                title: "Edit tags...".to_string(),
+                action: EventHandler::new(move |_| {
+                    show_tag_editor.set(true);
+                }),
+            },
         ]};
     rsx! {
         file_shortcut { 
             name: match file.song_data {
                 SongFileData::Song { song_view } => song_view.title,
-                SongFileData::NotSong {} => file.path.file_name().unwrap_or_default().to_string_lossy().to_string()
+                SongFileData::NotIndexed {} | SongFileData::NotSong {} => file.path.file_name().unwrap_or_default().to_string_lossy().to_string()
             },
             icon: icon_element,
+            label,
             path: file_path.clone(),
-            on_click: on_click.clone(),
+            selected,
+            on_click: move |path: String| {
+                selected_songs.set(Vec::new());
+                on_click.call(path);
+            },
+            on_ctrl_click: move |path: String| {
+                if is_folder {
+                    return;
+                }
+                let mut selection = selected_songs.write();
+                match selection.iter().position(|selected| *selected == path) {
+                    Some(index) => {
+                        selection.remove(index);
+                    }
+                    None => selection.push(path),
+                }
+            },
             on_context_menu: move |evt: Event<MouseData>| {
                 evt.prevent_default();
                 context_menu_pos.set((evt.client_coordinates().x, evt.client_coordinates().y));
@@ -347,38 +570,127 @@ pub fn song_file(file: FileEntry, on_click: EventHandler<String>) -> Element {
             items: context_menu_items.clone()}
         if let Some(song_id) = song_id {
             add_to_playlist_menu {
-                song_id,
+                song_ids: vec![song_id],
                 show: show_add_to_playlist_menu,
                 pos: add_playlist_menu_pos
             }
         }
+        if let Some(song_id) = song_id {
+            rating_menu {
+                song_id,
+                show: show_rating_menu,
+                pos: rating_menu_pos
+            }
+        }
+        if let Some(song) = song_view {
+            song_info_dialog { song, show: show_song_info }
+        }
+        if show_tag_editor() {
+            tag_editor_dialog { paths: tag_editor_paths(), show: show_tag_editor }
+        }
+        playlist_import_dialog { import: import_summary }
     }
 }
 
 #[component]
-pub fn file_shortcut(name: String, icon: Element, path: String, on_click: EventHandler<String>, on_context_menu: EventHandler<Event<MouseData>>) -> Element {
+pub fn file_shortcut(
+    name: String,
+    icon: Element,
+    label: Option<Element>,
+    path: String,
+    #[props(default)] selected: bool,
+    on_click: EventHandler<String>,
+    on_ctrl_click: Option<EventHandler<String>>,
+    on_context_menu: EventHandler<Event<MouseData>>,
+) -> Element {
     
     rsx! {
         if path.is_empty() {
             {}
         } else {
             button { 
-                onclick: move |_| {
-                    on_click.call(path.clone());
+                onclick: move |evt: Event<MouseData>| {
+                    let modifiers = evt.modifiers();
+                    match on_ctrl_click {
+                        Some(on_ctrl_click) if modifiers.ctrl() || modifiers.meta() => on_ctrl_click.call(path.clone()),
+                        _ => on_click.call(path.clone()),
+                    }
                 },
                 oncontextmenu: move |evt| {
                     on_context_menu.call(evt);
                 },
-                class: "file-item",
+                class: if selected { "file-item selected" } else { "file-item" },
                 div { class: "item-icon", {icon}  }
-                div { class: "item-name", "{name}" }
+                div { class: "item-name",
+                    if let Some(label) = label { {label} } else { "{name}" }
+                }
+            }
+        }
+    }
+}
+
+#[component]
+pub fn rating_menu(song_id: i32, show: Signal<bool>, pos: Signal<(f64, f64)>) -> Element {
+    let player_context = use_context::<PlayerContext>();
+
+    if !show() {
+        return rsx! {};
+    }
+
+    let (x, y) = *pos.read();
+
+    rsx! {
+        div {
+            class: "context-menu-overlay",
+            onclick: move |_| {
+                show.set(false);
+            },
+            oncontextmenu: move |evt: Event<MouseData>| {
+                evt.prevent_default();
+                show.set(false);
+            },
+
+            div {
+                class: "context-menu",
+                style: "left: {x}px; top: {y}px; position: fixed;",
+                onclick: move |evt: Event<MouseData>| evt.stop_propagation(),
+
+                for stars in (0..=MAX_STARS).rev() {
+                    {
+                        let mut player_context = player_context.clone();
+                        rsx! {
+                            button {
+                                key: "{stars}",
+                                class: "context-menu-item",
+                                onclick: move |_| {
+                                    player_context.set_rating(song_id, stars);
+                                    show.set(false);
+                                },
+                                if stars == 0 { "Clear rating" } else { "{SongRating::stars_label(stars)}" }
+                            }
+                        }
+                    }
+                }
+                {
+                    let mut player_context = player_context.clone();
+                    rsx! {
+                        button {
+                            class: "context-menu-item",
+                            onclick: move |_| {
+                                player_context.write_rating_to_file(song_id);
+                                show.set(false);
+                            },
+                            "Write rating to file"
+                        }
+                    }
+                }
             }
         }
     }
 }
 
 #[component]
-pub fn add_to_playlist_menu(song_id: i32, show: Signal<bool>, pos: Signal<(f64, f64)>) -> Element {
+pub fn add_to_playlist_menu(song_ids: Vec<i32>, show: Signal<bool>, pos: Signal<(f64, f64)>) -> Element {
     let db = use_context::<DatabaseContext>();
     let mut playlist_update = use_context::<PlayerContext>().playlist_update_counter;
     let mut show_create_dialog = use_signal(|| false);
@@ -386,7 +698,12 @@ pub fn add_to_playlist_menu(song_id: i32, show: Signal<bool>, pos: Signal<(f64,
     let db_clone = db.clone();
     let playlists = use_memo(move || {
         let _ = playlist_update();
+        //smart playlists pick their own songs
         db_clone.get().get_all_playlists().unwrap_or_default()
+            .into_iter()
+            .filter(|(_, _, smart)| !smart)
+            .map(|(id, name, _)| (id, name))
+            .collect::<Vec<_>>()
     });
 
     if !show() {
@@ -422,11 +739,14 @@ pub fn add_to_playlist_menu(song_id: i32, show: Signal<bool>, pos: Signal<(f64,
                 for (playlist_id, playlist_name) in playlists() {
                     {
                         let db_clone = db.clone();
+                        let song_ids = song_ids.clone();
                         rsx! {
                             button {
                                 class: "context-menu-item",
                                 onclick: move |_| {
-                                    let _ = db_clone.get().add_song_to_playlist(playlist_id, song_id);
+                                    for song_id in &song_ids {
+                                        let _ = db_clone.get().add_song_to_playlist(playlist_id, *song_id);
+                                    }
                                     playlist_update.set(playlist_update() + 1);
                                     show.set(false);
                                 },
@@ -441,7 +761,9 @@ pub fn add_to_playlist_menu(song_id: i32, show: Signal<bool>, pos: Signal<(f64,
         create_playlist_dialog {
             show: show_create_dialog,
             on_created: move |playlist_id| {
-                let _ = db.get().add_song_to_playlist(playlist_id, song_id);
+                for song_id in &song_ids {
+                    let _ = db.get().add_song_to_playlist(playlist_id, *song_id);
+                }
                 show.set(false);
             }
         }
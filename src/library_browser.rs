@@ -0,0 +1,454 @@
+use dioxus::prelude::*;
+
+use crate::{
+    app_context::{DatabaseContext, PlayerContext},
+    context_menu::{ContextMenuItem, context_menu},
+    db::{Db, SongView},
+    file_browser::{FileEntry, add_to_playlist_menu, song_file},
+    queue_state::QueueFallbackMode,
+    tag_editor_dialog::tag_editor_dialog,
+};
+
+#[derive(Clone, Debug, PartialEq)]
+enum LibraryView {
+    Artists,
+    Albums,
+    ArtistAlbums { artist_id: i32, artist_name: String },
+    AlbumSongs { album_id: i32, album_name: String, artist: Option<(i32, String)> },
+    Genres,
+    Years,
+    TagSongs { group: SongGroup },
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum SongGroup {
+    Album(i32),
+    Artist(i32),
+    Genre(String),
+    Year(i32),
+}
+
+impl SongGroup {
+    fn songs(&self, db: &Db) -> rusqlite::Result<Vec<SongView>> {
+        match self {
+            SongGroup::Album(album_id) => db.get_album_songs(*album_id),
+            SongGroup::Artist(artist_id) => db.get_artist_songs(*artist_id),
+            SongGroup::Genre(genre) => db.get_genre_songs(genre),
+            SongGroup::Year(year) => db.get_year_songs(*year),
+        }
+    }
+}
+
+fn art_icon(cover_art_path: Option<String>, fallback: &str) -> Element {
+    match cover_art_path {
+        Some(art) => rsx! { img { src: art, alt: "Album Art", class: "art" } },
+        None => rsx! { "{fallback}" },
+    }
+}
+
+#[component]
+pub fn library_browser() -> Element {
+    let mut current_view = use_signal(|| LibraryView::Artists);
+
+    let tab_class = move |view: LibraryView| if current_view() == view { "active" } else { "" };
+
+    rsx! {
+        div { class: "content-section",
+            h2 { "Library" }
+            match current_view() {
+                LibraryView::Artists | LibraryView::Albums | LibraryView::Genres | LibraryView::Years => rsx! {
+                    div { class: "common-button library-tabs",
+                        button {
+                            class: tab_class(LibraryView::Artists),
+                            onclick: move |_| current_view.set(LibraryView::Artists),
+                            "Artists"
+                        }
+                        button {
+                            class: tab_class(LibraryView::Albums),
+                            onclick: move |_| current_view.set(LibraryView::Albums),
+                            "Albums"
+                        }
+                        button {
+                            class: tab_class(LibraryView::Genres),
+                            onclick: move |_| current_view.set(LibraryView::Genres),
+                            "Genres"
+                        }
+                        button {
+                            class: tab_class(LibraryView::Years),
+                            onclick: move |_| current_view.set(LibraryView::Years),
+                            "Years"
+                        }
+                    }
+                    match current_view() {
+                        LibraryView::Artists => rsx! {
+                            artist_grid {
+                                on_select: move |(artist_id, artist_name)| {
+                                    current_view.set(LibraryView::ArtistAlbums { artist_id, artist_name });
+                                }
+                            }
+                        },
+                        LibraryView::Albums => rsx! {
+                            album_grid {
+                                on_select: move |(album_id, album_name)| {
+                                    current_view.set(LibraryView::AlbumSongs { album_id, album_name, artist: None });
+                                }
+                            }
+                        },
+                        _ => rsx! {
+                            tag_grid {
+                                years: current_view() == LibraryView::Years,
+                                on_select: move |group| current_view.set(LibraryView::TagSongs { group }),
+                            }
+                        },
+                    }
+                },
+                LibraryView::ArtistAlbums { artist_id, artist_name } => rsx! {
+                    h3 { class: "library-title", "{artist_name}" }
+                    div { class: "common-button library-tabs",
+                        button {
+                            onclick: move |_| current_view.set(LibraryView::Artists),
+                            "← Back to Artists"
+                        }
+                    }
+                    album_grid {
+                        artist_id,
+                        on_select: move |(album_id, album_name)| {
+                            current_view.set(LibraryView::AlbumSongs {
+                                album_id,
+                                album_name,
+                                artist: Some((artist_id, artist_name.clone())),
+                            });
+                        }
+                    }
+                },
+                LibraryView::AlbumSongs { album_id, album_name, artist } => rsx! {
+                    h3 { class: "library-title", "{album_name}" }
+                    div { class: "common-button library-tabs",
+                        button {
+                            onclick: move |_| current_view.set(match artist.clone() {
+                                Some((artist_id, artist_name)) => LibraryView::ArtistAlbums { artist_id, artist_name },
+                                None => LibraryView::Albums,
+                            }),
+                            match &artist {
+                                Some((_, artist_name)) => format!("← Back to {}", artist_name),
+                                None => "← Back to Albums".to_string(),
+                            }
+                        }
+                    }
+                    album_songs { album_id }
+                },
+                LibraryView::TagSongs { group } => rsx! {
+                    h3 { class: "library-title",
+                        match &group {
+                            SongGroup::Genre(genre) => genre.clone(),
+                            SongGroup::Year(year) => year.to_string(),
+                            _ => String::new(),
+                        }
+                    }
+                    div { class: "common-button library-tabs",
+                        if let SongGroup::Year(_) = group {
+                            button {
+                                onclick: move |_| current_view.set(LibraryView::Years),
+                                "← Back to Years"
+                            }
+                        } else {
+                            button {
+                                onclick: move |_| current_view.set(LibraryView::Genres),
+                                "← Back to Genres"
+                            }
+                        }
+                    }
+                    tag_songs { group }
+                },
+            }
+        }
+    }
+}
+
+#[component]
+fn artist_grid(on_select: EventHandler<(i32, String)>) -> Element {
+    let db = use_context::<DatabaseContext>();
+    let library_update_counter = use_context::<PlayerContext>().library_update_counter;
+
+    let artists = use_memo(move || {
+        let _ = library_update_counter();
+        db.get().get_artists().unwrap_or_default()
+    });
+
+    if artists().is_empty() {
+        return rsx! {
+            p { class: "library-empty", "No artists yet, add your music folders in the settings to build the library." }
+        };
+    }
+
+    rsx! {
+        div { class: "item-grid",
+            for artist in artists() {
+                song_group_item {
+                    key: "{artist.id}",
+                    group: SongGroup::Artist(artist.id),
+                    icon: art_icon(artist.cover_art_path.clone(), "🎤"),
+                    label: rsx! { "{artist.name}" },
+                    on_click: move |_| on_select.call((artist.id, artist.name.clone())),
+                }
+            }
+        }
+    }
+}
+
+#[component]
+fn album_grid(artist_id: Option<i32>, on_select: EventHandler<(i32, String)>) -> Element {
+    let db = use_context::<DatabaseContext>();
+    let library_update_counter = use_context::<PlayerContext>().library_update_counter;
+
+    let albums = use_memo(use_reactive!(|artist_id| {
+        let _ = library_update_counter();
+        match artist_id {
+            Some(artist_id) => db.get().get_artist_albums(artist_id),
+            None => db.get().get_albums(),
+        }
+        .unwrap_or_default()
+    }));
+
+    if albums().is_empty() {
+        return rsx! {
+            p { class: "library-empty", "No albums yet, add your music folders in the settings to build the library." }
+        };
+    }
+
+    rsx! {
+        div { class: "item-grid",
+            for album in albums() {
+                song_group_item {
+                    key: "{album.id}",
+                    group: SongGroup::Album(album.id),
+                    icon: art_icon(album.cover_art_path.clone(), "💿"),
+                    label: rsx! { "{album.name}" },
+                    on_click: move |_| on_select.call((album.id, album.name.clone())),
+                }
+            }
+        }
+    }
+}
+
+/// Genres, or years when `years` is set, with how many songs carry each.
+#[component]
+fn tag_grid(years: bool, on_select: EventHandler<SongGroup>) -> Element {
+    let db = use_context::<DatabaseContext>();
+    let library_update_counter = use_context::<PlayerContext>().library_update_counter;
+
+    let groups = use_memo(use_reactive!(|years| {
+        let _ = library_update_counter();
+        let groups: rusqlite::Result<Vec<(SongGroup, String, usize)>> = if years {
+            db.get().get_years().map(|years| {
+                years.into_iter().map(|(year, count)| (SongGroup::Year(year), year.to_string(), count)).collect()
+            })
+        } else {
+            db.get().get_genres().map(|genres| {
+                genres.into_iter().map(|(genre, count)| (SongGroup::Genre(genre.clone()), genre, count)).collect()
+            })
+        };
+        groups.unwrap_or_default()
+    }));
+
+    if groups().is_empty() {
+        return rsx! {
+            p { class: "library-empty",
+                if years { "No songs with a year tag yet." } else { "No songs with a genre tag yet." }
+            }
+        };
+    }
+
+    rsx! {
+        div { class: "item-grid",
+            for (group, name, count) in groups() {
+                song_group_item {
+                    key: "{name}",
+                    group: group.clone(),
+                    icon: rsx! { if years { "📅" } else { "🏷️" } },
+                    label: rsx! {
+                        "{name}"
+                        span { class: "search-detail",
+                            if count == 1 { " · 1 song" } else { " · {count} songs" }
+                        }
+                    },
+                    on_click: move |_| on_select.call(group.clone()),
+                }
+            }
+        }
+    }
+}
+
+#[component]
+fn tag_songs(group: SongGroup) -> Element {
+    let db = use_context::<DatabaseContext>();
+    let mut player_context = use_context::<PlayerContext>();
+    let library_update_counter = player_context.library_update_counter;
+
+    let songs = use_memo(use_reactive!(|group| {
+        let _ = library_update_counter();
+        group.songs(db.get()).unwrap_or_default()
+    }));
+
+    rsx! {
+        div { class: "item-list",
+            for song in songs() {
+                song_file {
+                    key: "{song.id}",
+                    file: FileEntry::from_song_view(&song),
+                    on_click: move |_| {
+                        if let Err(e) = player_context.queue.write().play_song_instant(&song) {
+                            eprintln!("Failed to play song: {:?}", e);
+                        }
+                    },
+                }
+            }
+        }
+    }
+}
+
+/// Adds the track artist after the title on compilations, where it differs from the album artist.
+fn track_label(song: &SongView) -> Option<Element> {
+    let artist = song.display_artist();
+    (artist != song.artist).then(|| rsx! {
+        "{song.title}"
+        span { class: "search-detail", " · {artist}" }
+    })
+}
+
+#[component]
+fn album_songs(album_id: i32) -> Element {
+    let db = use_context::<DatabaseContext>();
+    let mut player_context = use_context::<PlayerContext>();
+    let library_update_counter = player_context.library_update_counter;
+
+    let songs = use_memo(use_reactive!(|album_id| {
+        let _ = library_update_counter();
+        let mut songs = db.get().get_album_songs(album_id).unwrap_or_default();
+        songs.sort_by(|a, b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
+        songs
+    }));
+
+    rsx! {
+        div { class: "item-list",
+            for (index, song) in songs().into_iter().enumerate() {
+                song_file {
+                    key: "{song.id}",
+                    file: FileEntry::from_song_view(&song),
+                    label: track_label(&song),
+                    on_click: move |_| {
+                        if let Err(e) = player_context.queue.write().play_song_instant(&song) {
+                            eprintln!("Failed to play song: {:?}", e);
+                        }
+                        player_context.queue.write().current_fallback_queue.set(QueueFallbackMode::Album {
+                            album_id,
+                            current_item: index,
+                            songs: songs(),
+                        });
+                    },
+                }
+            }
+        }
+    }
+}
+
+/// An album, artist, genre or year, its actions apply to all of its songs. Clicking plays them unless `on_click` is given.
+#[component]
+pub fn song_group_item(group: SongGroup, icon: Element, label: Element, on_click: Option<EventHandler<()>>) -> Element {
+    let mut show_context_menu = use_signal(|| false);
+    let mut context_menu_pos = use_signal(|| (0.0, 0.0));
+    let mut show_add_to_playlist_menu = use_signal(|| false);
+    let mut add_playlist_menu_pos = use_signal(|| (0.0, 0.0));
+    let mut show_tag_editor = use_signal(|| false);
+
+    let db = use_context::<DatabaseContext>();
+    let mut player_context = use_context::<PlayerContext>();
+
+    let songs = move || -> Vec<SongView> {
+        group.songs(db.get()).unwrap_or_else(|e| {
+            eprintln!("Failed to load songs: {:?}", e);
+            Vec::new()
+        })
+    };
+
+    let songs_clone = songs.clone();
+    let mut play_now = move || {
+        player_context.queue.write().play_songs_now(songs_clone());
+    };
+
+    let context_menu_items = vec![
+        ContextMenuItem {
+            title: "Play".to_string(),
+            action: {
+                let mut play_now = play_now.clone();
+                EventHandler::new(move |_| play_now())
+            },
+        },
+        ContextMenuItem {
+            title: "Play Next".to_string(),
+            action: {
+                let songs = songs.clone();
+                EventHandler::new(move |_| {
+                    player_context.queue.write().play_songs_next(songs());
+                })
+            },
+        },
+        ContextMenuItem {
+            title: "Add to queue".to_string(),
+            action: {
+                let songs = songs.clone();
+                EventHandler::new(move |_| {
+                    player_context.queue.write().add_songs_to_queue(songs());
+                })
+            },
+        },
+        ContextMenuItem {
+            title: "Add to playlist...".to_string(),
+            action: EventHandler::new(move |_| {
+                add_playlist_menu_pos.set(context_menu_pos());
+                show_add_to_playlist_menu.set(true);
+            }),
+        },
+        ContextMenuItem {
+            title: "Edit tags...".to_string(),
+            action: EventHandler::new(move |_| {
+                show_tag_editor.set(true);
+            }),
+        },
+    ];
+
+    rsx! {
+        button {
+            class: "file-item",
+            onclick: move |_| match on_click {
+                Some(on_click) => on_click.call(()),
+                None => play_now(),
+            },
+            oncontextmenu: move |evt: Event<MouseData>| {
+                evt.prevent_default();
+                context_menu_pos.set((evt.client_coordinates().x, evt.client_coordinates().y));
+                show_context_menu.set(true);
+            },
+            div { class: "item-icon", {icon} }
+            div { class: "item-name", {label} }
+        }
+        context_menu {
+            pos: context_menu_pos,
+            show_context_menu,
+            items: context_menu_items,
+        }
+        if show_add_to_playlist_menu() {
+            add_to_playlist_menu {
+                song_ids: songs().iter().map(|song| song.id).collect::<Vec<_>>(),
+                show: show_add_to_playlist_menu,
+                pos: add_playlist_menu_pos,
+            }
+        }
+        if show_tag_editor() {
+            tag_editor_dialog {
+                paths: songs().into_iter().map(|song| song.path).collect::<Vec<_>>(),
+                show: show_tag_editor,
+            }
+        }
+    }
+}
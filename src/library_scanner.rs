@@ -0,0 +1,235 @@
+use std::{path::{Path, PathBuf}, sync::{Arc, atomic::{AtomicBool, Ordering}}, time::UNIX_EPOCH};
+
+use tokio::sync::mpsc::UnboundedSender;
+
+use crate::{db::{Db, SongFile}, file_browser::RECOGNIZED_FILE_EXTENSIONS};
+
+pub const LIBRARY_ROOTS_SETTING: &str = "library_roots";
+const SCAN_BATCH_SIZE: usize = 32; //songs read ahead, then written in one transaction
+const PROGRESS_INTERVAL: usize = 250; //files seen between progress reports
+
+pub fn load_library_roots(db: &Db) -> Vec<String> {
+    db.get_setting(LIBRARY_ROOTS_SETTING)
+        .ok()
+        .flatten()
+        .and_then(|value| serde_json::from_str(&value).ok())
+        .unwrap_or_default()
+}
+
+pub fn save_library_roots(db: &Db, roots: &[String]) -> rusqlite::Result<()> {
+    db.set_setting(LIBRARY_ROOTS_SETTING, &serde_json::to_string(roots).unwrap_or_default())
+}
+
+/// Modification time in seconds and size of a file, used to tell whether it changed since it was read.
+pub fn file_stamp(path: &Path) -> Option<(i64, i64)> {
+    let metadata = std::fs::metadata(path).ok()?;
+    let mtime = metadata.modified().ok()?.duration_since(UNIX_EPOCH).ok()?.as_secs();
+    Some((mtime as i64, metadata.len() as i64))
+}
+
+pub fn is_audio_file(path: &Path) -> bool {
+    path.extension()
+        .and_then(|s| s.to_str())
+        .is_some_and(|extension| RECOGNIZED_FILE_EXTENSIONS.contains(&extension.to_lowercase().as_str()))
+}
+
+/// Calls `visit` with every audio file under `root` until it returns false.
+/// Hidden folders are skipped and symlinked folders are not followed, they could loop back into the tree.
+pub fn for_each_audio_file(root: &Path, mut visit: impl FnMut(PathBuf) -> bool) {
+    let mut dirs = vec![root.to_path_buf()];
+    while let Some(dir) = dirs.pop() {
+        let entries = match dir.read_dir() {
+            Ok(entries) => entries,
+            Err(e) => {
+                eprintln!("Failed to read directory {:?}: {}", dir, e);
+                continue;
+            }
+        };
+        for entry in entries.flatten() {
+            let path = entry.path();
+            let Ok(file_type) = entry.file_type() else {
+                continue;
+            };
+            if file_type.is_dir() {
+                if !entry.file_name().to_string_lossy().starts_with('.') {
+                    dirs.push(path);
+                }
+            } else if is_audio_file(&path) && path.is_file() && !visit(path) {
+                return;
+            }
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FileSync {
+    Unchanged,
+    Added,
+    Updated,
+    Failed,
+}
+
+/// A file looked at by `read_changed_song_file`.
+pub enum FileRead {
+    Unchanged,
+    Read { file: Box<SongFile>, known: bool },
+    Failed,
+}
+
+/// Reads the file's tags when it is new or its size or modification time changed, without writing anything.
+pub fn read_changed_song_file(db: &Db, path: &Path) -> FileRead {
+    let path_str = path.to_string_lossy().to_string();
+    let stamp = file_stamp(path);
+    let known = match db.get_file_stamp(&path_str) {
+        Ok(Some((mtime, size))) => {
+            if stamp.is_some() && (mtime, size) == stamp.unzip() {
+                return FileRead::Unchanged;
+            }
+            true
+        }
+        Ok(None) => false,
+        Err(e) => {
+            eprintln!("Failed to look up {}: {:?}", path_str, e);
+            return FileRead::Failed;
+        }
+    };
+    match SongFile::read(&path_str) {
+        Ok(file) => FileRead::Read { file: Box::new(file), known },
+        Err(e) => {
+            eprintln!("Failed to read {}: {:?}", path_str, e);
+            FileRead::Failed
+        }
+    }
+}
+
+/// Writes a file read by `read_changed_song_file` to the database.
+pub fn store_song_file(db: &Db, file: &SongFile, known: bool) -> FileSync {
+    match db.add_song_file(file) {
+        Ok(_) if known => FileSync::Updated,
+        Ok(_) => FileSync::Added,
+        Err(e) => {
+            eprintln!("Failed to add {} to the library: {:?}", file.path, e);
+            FileSync::Failed
+        }
+    }
+}
+
+/// Adds the file to the database, or re-reads its tags when its size or modification time changed.
+pub fn sync_song_file(db: &Db, path: &Path) -> FileSync {
+    match read_changed_song_file(db, path) {
+        FileRead::Unchanged => FileSync::Unchanged,
+        FileRead::Read { file, known } => store_song_file(db, &file, known),
+        FileRead::Failed => FileSync::Failed,
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct ScanProgress {
+    pub seen: usize,
+    pub added: usize,
+    pub updated: usize,
+    pub failed: usize,
+    pub cancelled: bool,
+}
+
+/// Background pass walking the library roots and adding new or changed files to the database.
+/// Progress is sent after every batch, the sender is dropped when the scan ends.
+#[derive(Clone)]
+pub struct LibraryScan {
+    cancelled: Arc<AtomicBool>,
+}
+
+impl LibraryScan {
+    pub fn start(roots: Vec<String>, progress: UnboundedSender<ScanProgress>) -> Self {
+        let cancelled = Arc::new(AtomicBool::new(false));
+        let flag = cancelled.clone();
+        std::thread::spawn(move || {
+            let db = Db::new();
+            let mut state = ScanProgress::default();
+            for root in roots {
+                if flag.load(Ordering::Relaxed) {
+                    break;
+                }
+                scan_root(&db, Path::new(&root), &flag, &progress, &mut state);
+            }
+            state.cancelled = flag.load(Ordering::Relaxed);
+            let _ = progress.send(state);
+            println!("Library scan finished: {:?}", state);
+        });
+        LibraryScan { cancelled }
+    }
+
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+}
+
+fn scan_root(db: &Db, root: &Path, flag: &AtomicBool, progress: &UnboundedSender<ScanProgress>, state: &mut ScanProgress) {
+    let mut pending = Vec::new();
+    for_each_audio_file(root, |path| {
+        if flag.load(Ordering::Relaxed) {
+            return false;
+        }
+        scan_file(db, &path, &mut pending, progress, state);
+        true
+    });
+    write_batch(db, &mut pending, state);
+    let _ = progress.send(*state);
+}
+
+fn scan_file(db: &Db, path: &Path, pending: &mut Vec<(SongFile, bool)>, progress: &UnboundedSender<ScanProgress>, state: &mut ScanProgress) {
+    state.seen += 1;
+    match read_changed_song_file(db, path) {
+        FileRead::Unchanged => {}
+        FileRead::Read { file, known } => pending.push((*file, known)),
+        FileRead::Failed => state.failed += 1,
+    }
+    if pending.len() >= SCAN_BATCH_SIZE {
+        write_batch(db, pending, state);
+        let _ = progress.send(*state);
+    } else if state.seen.is_multiple_of(PROGRESS_INTERVAL) {
+        let _ = progress.send(*state);
+    }
+}
+
+/// Adds files found outside the library roots, like the ones the file browser shows as not yet indexed, on a background thread.
+/// Progress is sent after every batch, the sender is dropped once all of them are written.
+pub fn index_files(paths: Vec<PathBuf>, progress: UnboundedSender<ScanProgress>) {
+    std::thread::spawn(move || {
+        let db = Db::new();
+        let mut pending = Vec::new();
+        let mut state = ScanProgress::default();
+        for path in paths {
+            scan_file(&db, &path, &mut pending, &progress, &mut state);
+        }
+        write_batch(&db, &mut pending, &mut state);
+        let _ = progress.send(state);
+    });
+}
+
+/// Writes the files read so far in one transaction, held only while writing so the UI's own writes don't wait on decoding.
+fn write_batch(db: &Db, pending: &mut Vec<(SongFile, bool)>, state: &mut ScanProgress) {
+    if pending.is_empty() {
+        return;
+    }
+    let batch = match db.batch() {
+        Ok(batch) => batch,
+        Err(e) => {
+            eprintln!("Failed to start library scan transaction: {:?}", e);
+            state.failed += pending.len();
+            pending.clear();
+            return;
+        }
+    };
+    for (file, known) in pending.drain(..) {
+        match store_song_file(db, &file, known) {
+            FileSync::Unchanged => {}
+            FileSync::Added => state.added += 1,
+            FileSync::Updated => state.updated += 1,
+            FileSync::Failed => state.failed += 1,
+        }
+    }
+    if let Err(e) = batch.commit() {
+        eprintln!("Failed to commit library scan batch: {:?}", e);
+    }
+}
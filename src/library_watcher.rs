@@ -0,0 +1,136 @@
+use std::{path::Path, sync::mpsc, time::Duration};
+
+use notify_debouncer_full::{
+    DebounceEventResult, DebouncedEvent, Debouncer, RecommendedCache, new_debouncer,
+    notify::{EventKind, RecommendedWatcher, RecursiveMode, event::{ModifyKind, RenameMode}},
+};
+use tokio::sync::mpsc::UnboundedSender;
+
+use crate::{db::Db, library_scanner::{FileSync, for_each_audio_file, is_audio_file, sync_song_file}};
+
+const DEBOUNCE_MS: u64 = 2000; //tag editors often write a file in several steps
+
+/// Keeps the songs table in sync with changes under the library roots until dropped.
+/// A message is sent on `changed` after every group of events that touched the database.
+pub struct LibraryWatcher {
+    _debouncer: Debouncer<RecommendedWatcher, RecommendedCache>,
+}
+
+impl LibraryWatcher {
+    pub fn start(roots: &[String], changed: UnboundedSender<()>) -> Option<Self> {
+        let (sender, receiver) = mpsc::channel::<DebounceEventResult>();
+        let mut debouncer = match new_debouncer(Duration::from_millis(DEBOUNCE_MS), None, sender) {
+            Ok(debouncer) => debouncer,
+            Err(e) => {
+                eprintln!("Failed to start library watcher: {:?}", e);
+                return None;
+            }
+        };
+        for root in roots {
+            if let Err(e) = debouncer.watch(root, RecursiveMode::Recursive) {
+                eprintln!("Failed to watch {}: {:?}", root, e);
+            }
+        }
+
+        //ends when the debouncer is dropped and takes the sender with it
+        std::thread::spawn(move || {
+            let db = Db::new();
+            for result in receiver {
+                match result {
+                    Ok(events) => {
+                        let mut touched = false;
+                        for event in events {
+                            touched |= apply_event(&db, &event);
+                        }
+                        if touched {
+                            let _ = changed.send(());
+                        }
+                    }
+                    Err(errors) => {
+                        for e in errors {
+                            eprintln!("Library watcher error: {:?}", e);
+                        }
+                    }
+                }
+            }
+        });
+
+        Some(LibraryWatcher { _debouncer: debouncer })
+    }
+}
+
+fn apply_event(db: &Db, event: &DebouncedEvent) -> bool {
+    let mut touched = false;
+    match event.kind {
+        EventKind::Modify(ModifyKind::Name(RenameMode::Both)) if event.paths.len() == 2 => {
+            touched = move_path(db, &event.paths[0], &event.paths[1]);
+        }
+        EventKind::Modify(ModifyKind::Name(RenameMode::From)) | EventKind::Remove(_) => {
+            for path in &event.paths {
+                touched |= remove_path(db, path);
+            }
+        }
+        EventKind::Create(_) | EventKind::Modify(_) => {
+            for path in &event.paths {
+                touched |= sync_path(db, path);
+            }
+        }
+        _ => {}
+    }
+    touched
+}
+
+/// Adds or refreshes the file, or every audio file inside the folder.
+fn sync_path(db: &Db, path: &Path) -> bool {
+    if !path.exists() {
+        return remove_path(db, path);
+    }
+    if path.is_dir() {
+        let mut touched = false;
+        for_each_audio_file(path, |file| {
+            touched |= !matches!(sync_song_file(db, &file), FileSync::Unchanged | FileSync::Failed);
+            true
+        });
+        touched
+    } else if is_audio_file(path) {
+        !matches!(sync_song_file(db, path), FileSync::Unchanged | FileSync::Failed)
+    } else {
+        false
+    }
+}
+
+fn remove_path(db: &Db, path: &Path) -> bool {
+    let paths = db.get_song_paths_under(&path.to_string_lossy()).unwrap_or_default();
+    for song_path in &paths {
+        if let Err(e) = db.remove_a_song_from_db(song_path) {
+            eprintln!("Failed to remove {} from the library: {:?}", song_path, e);
+        }
+    }
+    !paths.is_empty()
+}
+
+fn move_path(db: &Db, from: &Path, to: &Path) -> bool {
+    let (from_str, to_str) = (from.to_string_lossy(), to.to_string_lossy());
+    let from_known = db.get_song_paths_under(&from_str).is_ok_and(|paths| !paths.is_empty());
+    if !from_known {
+        //e.g. a tag editor renaming its temporary file over the original
+        return sync_path(db, to);
+    }
+    //a file moved over another song replaces it
+    if let Err(e) = db.remove_a_song_from_db(&to_str) {
+        eprintln!("Failed to remove {} from the library: {:?}", to_str, e);
+    }
+    match db.move_songs(&from_str, &to_str) {
+        Ok(moved) => {
+            println!("Moved {} songs from {} to {}", moved, from_str, to_str);
+            if to.is_file() && !is_audio_file(to) {
+                remove_path(db, to);
+            }
+            true
+        }
+        Err(e) => {
+            eprintln!("Failed to move songs from {} to {}: {:?}", from_str, to_str, e);
+            false
+        }
+    }
+}
@@ -0,0 +1,180 @@
+use std::{f64::consts::PI, fs::File, io::BufReader, sync::{Arc, atomic::{AtomicBool, Ordering}}};
+
+use rodio::{Decoder, Source};
+
+use crate::{biquad::Biquad, db::Db, replay_gain::REFERENCE_LOUDNESS};
+
+const ANALYSIS_BATCH_SIZE: usize = 20;
+const ABSOLUTE_GATE: f64 = -70.0;
+const RELATIVE_GATE: f64 = -10.0;
+
+/// Integrated loudness meter following EBU R128 / ITU-R BS.1770 (K-weighting, 400 ms gated blocks).
+pub struct LoudnessMeter {
+    channels: usize,
+    filters: Vec<[Biquad; 2]>,
+    weights: Vec<f64>,
+    sub_block_len: usize,
+    sub_block_frames: usize,
+    sub_block_energy: f64,
+    sub_blocks: Vec<f64>,
+    frame: Vec<f32>,
+    peak: f32,
+}
+
+impl LoudnessMeter {
+    pub fn new(channels: usize, sample_rate: u32) -> Self {
+        let fs = sample_rate as f64;
+
+        let f0 = 1681.974450955533;
+        let gain_db = 3.999843853973347;
+        let q = 0.7071752369554196;
+        let k = (PI * f0 / fs).tan();
+        let vh = 10f64.powf(gain_db / 20.0);
+        let vb = vh.powf(0.4996667741545416);
+        let a0 = 1.0 + k / q + k * k;
+        let shelf = Biquad::new(
+            (vh + vb * k / q + k * k) / a0,
+            2.0 * (k * k - vh) / a0,
+            (vh - vb * k / q + k * k) / a0,
+            2.0 * (k * k - 1.0) / a0,
+            (1.0 - k / q + k * k) / a0,
+        );
+
+        let f0 = 38.13547087602444;
+        let q = 0.5003270373238773;
+        let k = (PI * f0 / fs).tan();
+        let a0 = 1.0 + k / q + k * k;
+        let high_pass = Biquad::new(
+            1.0,
+            -2.0,
+            1.0,
+            2.0 * (k * k - 1.0) / a0,
+            (1.0 - k / q + k * k) / a0,
+        );
+
+        let weights = (0..channels)
+            .map(|channel| match (channels, channel) {
+                (6, 3) => 0.0, //LFE of a 5.1 layout
+                (_, 0..=2) => 1.0,
+                _ => 1.41,
+            })
+            .collect();
+
+        LoudnessMeter {
+            channels,
+            filters: vec![[shelf, high_pass]; channels],
+            weights,
+            sub_block_len: (sample_rate as usize / 10).max(1),
+            sub_block_frames: 0,
+            sub_block_energy: 0.0,
+            sub_blocks: Vec::new(),
+            frame: Vec::with_capacity(channels),
+            peak: 0.0,
+        }
+    }
+
+    pub fn push(&mut self, sample: f32) {
+        self.peak = self.peak.max(sample.abs());
+        self.frame.push(sample);
+        if self.frame.len() < self.channels {
+            return;
+        }
+        for channel in 0..self.channels {
+            let [shelf, high_pass] = &mut self.filters[channel];
+            let filtered = high_pass.process(shelf.process(self.frame[channel] as f64));
+            self.sub_block_energy += self.weights[channel] * filtered * filtered;
+        }
+        self.frame.clear();
+        self.sub_block_frames += 1;
+        if self.sub_block_frames == self.sub_block_len {
+            self.sub_blocks.push(self.sub_block_energy / self.sub_block_len as f64);
+            self.sub_block_energy = 0.0;
+            self.sub_block_frames = 0;
+        }
+    }
+
+    pub fn peak(&self) -> f32 {
+        self.peak
+    }
+
+    /// Gated integrated loudness in LUFS, `None` for silence or tracks shorter than one block.
+    pub fn integrated_loudness(&self) -> Option<f64> {
+        let blocks: Vec<f64> = self.sub_blocks
+            .windows(4)
+            .map(|window| window.iter().sum::<f64>() / 4.0)
+            .collect();
+        let loudness = |energy: f64| -0.691 + 10.0 * energy.log10();
+
+        let above_absolute: Vec<f64> = blocks.iter()
+            .copied()
+            .filter(|energy| *energy > 0.0 && loudness(*energy) > ABSOLUTE_GATE)
+            .collect();
+        if above_absolute.is_empty() {
+            return None;
+        }
+        let relative_gate = loudness(above_absolute.iter().sum::<f64>() / above_absolute.len() as f64) + RELATIVE_GATE;
+
+        let gated: Vec<f64> = above_absolute.into_iter()
+            .filter(|energy| loudness(*energy) > relative_gate)
+            .collect();
+        if gated.is_empty() {
+            return None;
+        }
+        Some(loudness(gated.iter().sum::<f64>() / gated.len() as f64))
+    }
+}
+
+/// Decodes a whole file and returns its ReplayGain 2.0 track gain and sample peak.
+pub fn analyze_file(path: &str) -> Option<(f32, f32)> {
+    let file = File::open(path).ok()?;
+    let decoder = Decoder::new(BufReader::new(file)).ok()?;
+    let mut meter = LoudnessMeter::new(decoder.channels().max(1) as usize, decoder.sample_rate());
+    for sample in decoder {
+        meter.push(sample);
+    }
+    let loudness = meter.integrated_loudness()?;
+    Some((REFERENCE_LOUDNESS - loudness as f32, meter.peak()))
+}
+
+/// Background pass computing gains for songs without ReplayGain tags.
+#[derive(Clone)]
+pub struct LoudnessAnalysis {
+    cancelled: Arc<AtomicBool>,
+}
+
+impl LoudnessAnalysis {
+    pub fn start() -> Self {
+        let cancelled = Arc::new(AtomicBool::new(false));
+        let flag = cancelled.clone();
+        std::thread::spawn(move || {
+            let db = Db::new();
+            while !flag.load(Ordering::Relaxed) {
+                let songs = db.get_songs_without_loudness(ANALYSIS_BATCH_SIZE).unwrap_or_default();
+                if songs.is_empty() {
+                    break;
+                }
+                for (song_id, path) in songs {
+                    if flag.load(Ordering::Relaxed) {
+                        return;
+                    }
+                    let result = analyze_file(&path);
+                    if result.is_none() {
+                        eprintln!("Loudness analysis failed for: {}", path);
+                    }
+                    if let Err(e) = db.store_loudness_analysis(song_id, result) {
+                        eprintln!("Failed to store loudness analysis: {:?}", e);
+                    }
+                }
+            }
+            if let Err(e) = db.fill_missing_album_gains() {
+                eprintln!("Failed to compute album gains: {:?}", e);
+            }
+            println!("Loudness analysis finished");
+        });
+        LoudnessAnalysis { cancelled }
+    }
+
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+}
@@ -6,16 +6,48 @@ pub mod player_playing_state;
 pub mod player;
 pub mod file_browser;
 pub mod queue_bar;
+pub mod queue_panel;
 pub mod queue_state;
 pub mod db;
+pub mod migrations;
 pub mod app_context;
 pub mod errors;
 pub mod audio_controller;
 pub mod audio_controller_command;
+pub mod audio_controller_event;
 pub mod context_menu;
 pub mod playlist;
 pub mod playlist_browser;
 pub mod create_playlist_dialog;
+pub mod tempo;
+pub mod custom_tags;
+pub mod replay_gain;
+pub mod biquad;
+pub mod equalizer;
+pub mod equalizer_panel;
+pub mod loudness;
+pub mod settings_dialog;
+pub mod output_device;
+pub mod toast;
+pub mod session;
+pub mod library_scanner;
+pub mod library_watcher;
+pub mod search;
+pub mod library_browser;
+pub mod song_tags;
+pub mod song_info_dialog;
+pub mod tag_editor;
+pub mod tag_editor_dialog;
+pub mod playlist_file;
+pub mod playlist_export_dialog;
+pub mod playlist_import_dialog;
+pub mod smart_playlist;
+pub mod smart_playlist_dialog;
+pub mod play_history;
+pub mod statistics;
+pub mod statistics_page;
+pub mod rating;
+pub mod shuffle;
 
 use crate::{app::App};
 
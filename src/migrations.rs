@@ -0,0 +1,533 @@
+use std::path::Path;
+
+use anyhow::{anyhow, Context, Result};
+use rusqlite::{params, Connection, Transaction, TransactionBehavior};
+
+struct Migration {
+    version: i32,
+    description: &'static str,
+    apply: fn(&Transaction) -> rusqlite::Result<()>,
+}
+
+/// Schema changes in order, each one upgrades `user_version` to its `version`.
+/// Never edit a released migration, add a new one at the end instead.
+const MIGRATIONS: &[Migration] = &[
+    Migration { version: 2, description: "initial schema", apply: initial_schema },
+    Migration { version: 3, description: "replay gain and settings", apply: replay_gain_and_settings },
+    Migration { version: 4, description: "equalizer presets", apply: equalizer_presets },
+    Migration { version: 5, description: "file stamps", apply: file_stamps },
+    Migration { version: 6, description: "search index", apply: search_index },
+    Migration { version: 7, description: "extended tags", apply: extended_tags },
+    Migration { version: 8, description: "regroup compilations", apply: regroup_compilations },
+    Migration { version: 9, description: "playlist entries", apply: playlist_entries },
+    Migration { version: 10, description: "smart playlists", apply: smart_playlists },
+    Migration { version: 11, description: "play history", apply: play_history },
+    Migration { version: 12, description: "ratings", apply: ratings },
+];
+
+pub const DB_STATE_VERSION: i32 = MIGRATIONS[MIGRATIONS.len() - 1].version;
+
+/// Databases older than this predate the migrations and get rebuilt from scratch.
+const OLDEST_MIGRATABLE_VERSION: i32 = 2;
+
+fn user_version(conn: &Connection) -> rusqlite::Result<i32> {
+    conn.query_row("PRAGMA user_version;", [], |row| row.get(0))
+}
+
+fn has_tables(conn: &Connection) -> rusqlite::Result<bool> {
+    conn.query_row(
+        "SELECT EXISTS(SELECT 1 FROM sqlite_master WHERE type = 'table')",
+        [],
+        |row| row.get(0),
+    )
+}
+
+/// Copies the database next to itself before it gets touched, named after the version it had.
+fn backup(conn: &Connection, db_path: &Path, version: i32) -> Result<()> {
+    let backup_path = db_path.with_extension(format!("v{}.bak", version));
+    if backup_path.exists() {
+        std::fs::remove_file(&backup_path)?;
+    }
+    let backup_path = backup_path.to_str().ok_or_else(|| anyhow!("Invalid backup path"))?;
+    conn.execute("VACUUM INTO ?1", params![backup_path])
+        .with_context(|| format!("Failed to back up the database to {}", backup_path))?;
+    println!("Backed up the database to {}", backup_path);
+    Ok(())
+}
+
+fn drop_legacy_tables(tx: &Transaction) -> rusqlite::Result<()> {
+    for table in ["playlist_songs", "playlists", "songs", "albums", "artists"] {
+        tx.execute(&format!("DROP TABLE IF EXISTS {}", table), [])?;
+    }
+    Ok(())
+}
+
+/// Brings the database up to `DB_STATE_VERSION`, one transaction per step.
+pub fn migrate(conn: &mut Connection, db_path: &Path) -> Result<()> {
+    let mut version = user_version(conn)?;
+    if version == DB_STATE_VERSION {
+        return Ok(());
+    }
+    if version > DB_STATE_VERSION {
+        return Err(anyhow!(
+            "Database version {} is newer than this build supports ({})",
+            version, DB_STATE_VERSION
+        ));
+    }
+
+    let fresh = version == 0 && !has_tables(conn)?;
+    if !fresh {
+        backup(conn, db_path, version)?;
+    }
+
+    if !fresh && version < OLDEST_MIGRATABLE_VERSION {
+        println!("Database version {} is too old to migrate, rebuilding it", version);
+        let tx = conn.transaction()?;
+        drop_legacy_tables(&tx)?;
+        tx.pragma_update(None, "user_version", 0)?;
+        tx.commit()?;
+        version = 0;
+    }
+
+    for migration in MIGRATIONS.iter().filter(|migration| migration.version > version) {
+        //immediate so a second connection opened meanwhile waits instead of applying the step twice
+        let tx = conn.transaction_with_behavior(TransactionBehavior::Immediate)?;
+        if user_version(&tx)? >= migration.version {
+            continue;
+        }
+        println!("Migrating database to version {}: {}", migration.version, migration.description);
+        (migration.apply)(&tx)
+            .with_context(|| format!("Migration to version {} failed", migration.version))?;
+        tx.pragma_update(None, "user_version", migration.version)?;
+        tx.commit()?;
+    }
+
+    Ok(())
+}
+
+fn initial_schema(tx: &Transaction) -> rusqlite::Result<()> {
+    tx.execute_batch(
+        "CREATE TABLE IF NOT EXISTS artists (
+            id      INTEGER PRIMARY KEY,
+            name    TEXT NOT NULL UNIQUE
+        );
+
+        CREATE TABLE IF NOT EXISTS albums (
+            id               INTEGER PRIMARY KEY,
+            name             TEXT NOT NULL,
+            artist_id        INTEGER NOT NULL,
+            cover_art_path   TEXT,
+            FOREIGN KEY(artist_id) REFERENCES artists(id),
+            UNIQUE(name, artist_id)
+        );
+
+        CREATE TABLE IF NOT EXISTS songs (
+            id               INTEGER PRIMARY KEY,
+            path             TEXT NOT NULL UNIQUE,
+            title            TEXT NOT NULL,
+            album_id         INTEGER NOT NULL,
+            track_number     INTEGER,
+            duration_seconds INTEGER NOT NULL DEFAULT 0,
+            play_count       INTEGER NOT NULL DEFAULT 0,
+            FOREIGN KEY(album_id) REFERENCES albums(id)
+        );
+
+        CREATE TABLE IF NOT EXISTS playlists (
+            id               INTEGER PRIMARY KEY,
+            name             TEXT NOT NULL,
+            cover_art_path   TEXT
+        );
+
+        CREATE TABLE IF NOT EXISTS playlist_songs (
+            playlist_id               INTEGER NOT NULL,
+            song_id                   INTEGER NOT NULL,
+            position                  INTEGER NOT NULL,
+            FOREIGN KEY(playlist_id) REFERENCES playlists(id),
+            FOREIGN KEY(song_id) REFERENCES songs(id),
+            PRIMARY KEY(playlist_id, song_id)
+        );",
+    )
+}
+
+fn replay_gain_and_settings(tx: &Transaction) -> rusqlite::Result<()> {
+    tx.execute_batch(
+        "ALTER TABLE songs ADD COLUMN track_gain REAL;
+        ALTER TABLE songs ADD COLUMN track_peak REAL;
+        ALTER TABLE songs ADD COLUMN album_gain REAL;
+        ALTER TABLE songs ADD COLUMN album_peak REAL;
+        ALTER TABLE songs ADD COLUMN loudness_analyzed INTEGER NOT NULL DEFAULT 0;
+
+        CREATE TABLE IF NOT EXISTS settings (
+            key              TEXT PRIMARY KEY,
+            value            TEXT NOT NULL
+        );",
+    )
+}
+
+fn equalizer_presets(tx: &Transaction) -> rusqlite::Result<()> {
+    tx.execute_batch(
+        "CREATE TABLE IF NOT EXISTS eq_presets (
+            id               INTEGER PRIMARY KEY,
+            name             TEXT NOT NULL UNIQUE,
+            settings         TEXT NOT NULL
+        );
+
+        CREATE TABLE IF NOT EXISTS album_eq_presets (
+            album_id         INTEGER PRIMARY KEY,
+            preset_id        INTEGER NOT NULL,
+            FOREIGN KEY(album_id) REFERENCES albums(id),
+            FOREIGN KEY(preset_id) REFERENCES eq_presets(id)
+        );",
+    )
+}
+
+fn file_stamps(tx: &Transaction) -> rusqlite::Result<()> {
+    tx.execute_batch(
+        "ALTER TABLE songs ADD COLUMN file_mtime INTEGER;
+        ALTER TABLE songs ADD COLUMN file_size INTEGER;",
+    )
+}
+
+/// Triggers keep the index in step with every write to `songs`, so callers never touch it directly.
+fn search_index(tx: &Transaction) -> rusqlite::Result<()> {
+    tx.execute_batch(
+        "CREATE VIRTUAL TABLE IF NOT EXISTS song_search USING fts5(
+            title, artist, album, path,
+            tokenize = 'unicode61 remove_diacritics 2'
+        );
+
+        INSERT INTO song_search (rowid, title, artist, album, path)
+            SELECT s.id, s.title, ar.name, al.name, s.path
+            FROM songs s
+            JOIN albums al ON s.album_id = al.id
+            JOIN artists ar ON al.artist_id = ar.id;
+
+        CREATE TRIGGER IF NOT EXISTS song_search_insert AFTER INSERT ON songs BEGIN
+            INSERT INTO song_search (rowid, title, artist, album, path)
+                SELECT new.id, new.title, ar.name, al.name, new.path
+                FROM albums al
+                JOIN artists ar ON al.artist_id = ar.id
+                WHERE al.id = new.album_id;
+        END;
+
+        CREATE TRIGGER IF NOT EXISTS song_search_update AFTER UPDATE OF title, album_id, path ON songs BEGIN
+            DELETE FROM song_search WHERE rowid = old.id;
+            INSERT INTO song_search (rowid, title, artist, album, path)
+                SELECT new.id, new.title, ar.name, al.name, new.path
+                FROM albums al
+                JOIN artists ar ON al.artist_id = ar.id
+                WHERE al.id = new.album_id;
+        END;
+
+        CREATE TRIGGER IF NOT EXISTS song_search_delete AFTER DELETE ON songs BEGIN
+            DELETE FROM song_search WHERE rowid = old.id;
+        END;",
+    )
+}
+
+/// Clears the file stamps so the next scan reads the new tags from every file.
+/// The search index is rebuilt to also cover per-track artists and genres.
+fn extended_tags(tx: &Transaction) -> rusqlite::Result<()> {
+    tx.execute_batch(
+        "ALTER TABLE songs ADD COLUMN track_artist TEXT;
+        ALTER TABLE songs ADD COLUMN genre TEXT;
+        ALTER TABLE songs ADD COLUMN year INTEGER;
+        ALTER TABLE songs ADD COLUMN disc_number INTEGER;
+        ALTER TABLE songs ADD COLUMN disc_total INTEGER;
+        ALTER TABLE songs ADD COLUMN track_total INTEGER;
+        ALTER TABLE songs ADD COLUMN composer TEXT;
+        ALTER TABLE songs ADD COLUMN comment TEXT;
+        ALTER TABLE songs ADD COLUMN bitrate INTEGER;
+        ALTER TABLE songs ADD COLUMN sample_rate INTEGER;
+        ALTER TABLE songs ADD COLUMN channels INTEGER;
+        UPDATE songs SET file_mtime = NULL, file_size = NULL;
+
+        CREATE INDEX IF NOT EXISTS idx_songs_genre ON songs(genre);
+        CREATE INDEX IF NOT EXISTS idx_songs_year ON songs(year);
+
+        DROP TRIGGER IF EXISTS song_search_insert;
+        DROP TRIGGER IF EXISTS song_search_update;
+        DROP TRIGGER IF EXISTS song_search_delete;
+        DROP TABLE IF EXISTS song_search;
+
+        CREATE VIRTUAL TABLE song_search USING fts5(
+            title, artist, album, path, track_artist, genre,
+            tokenize = 'unicode61 remove_diacritics 2'
+        );
+
+        INSERT INTO song_search (rowid, title, artist, album, path, track_artist, genre)
+            SELECT s.id, s.title, ar.name, al.name, s.path, s.track_artist, s.genre
+            FROM songs s
+            JOIN albums al ON s.album_id = al.id
+            JOIN artists ar ON al.artist_id = ar.id;
+
+        CREATE TRIGGER song_search_insert AFTER INSERT ON songs BEGIN
+            INSERT INTO song_search (rowid, title, artist, album, path, track_artist, genre)
+                SELECT new.id, new.title, ar.name, al.name, new.path, new.track_artist, new.genre
+                FROM albums al
+                JOIN artists ar ON al.artist_id = ar.id
+                WHERE al.id = new.album_id;
+        END;
+
+        CREATE TRIGGER song_search_update AFTER UPDATE OF title, album_id, path, track_artist, genre ON songs BEGIN
+            DELETE FROM song_search WHERE rowid = old.id;
+            INSERT INTO song_search (rowid, title, artist, album, path, track_artist, genre)
+                SELECT new.id, new.title, ar.name, al.name, new.path, new.track_artist, new.genre
+                FROM albums al
+                JOIN artists ar ON al.artist_id = ar.id
+                WHERE al.id = new.album_id;
+        END;
+
+        CREATE TRIGGER song_search_delete AFTER DELETE ON songs BEGIN
+            DELETE FROM song_search WHERE rowid = old.id;
+        END;",
+    )
+}
+
+/// Compilations split into one album per artist get regrouped when the next scan re-reads their files.
+fn regroup_compilations(tx: &Transaction) -> rusqlite::Result<()> {
+    tx.execute_batch("UPDATE songs SET file_mtime = NULL, file_size = NULL;")
+}
+
+/// Gives each playlist entry its own id so a song can appear more than once,
+/// and renumbers positions from 1 without gaps.
+fn playlist_entries(tx: &Transaction) -> rusqlite::Result<()> {
+    tx.execute_batch(
+        "CREATE TABLE playlist_entries (
+            id                        INTEGER PRIMARY KEY,
+            playlist_id               INTEGER NOT NULL,
+            song_id                   INTEGER NOT NULL,
+            position                  INTEGER NOT NULL,
+            FOREIGN KEY(playlist_id) REFERENCES playlists(id),
+            FOREIGN KEY(song_id) REFERENCES songs(id)
+        );
+
+        INSERT INTO playlist_entries (playlist_id, song_id, position)
+            SELECT playlist_id, song_id, ROW_NUMBER() OVER (PARTITION BY playlist_id ORDER BY position, song_id)
+            FROM playlist_songs;
+
+        DROP TABLE playlist_songs;
+        ALTER TABLE playlist_entries RENAME TO playlist_songs;
+
+        CREATE INDEX idx_playlist_songs_position ON playlist_songs(playlist_id, position);
+        CREATE INDEX idx_playlist_songs_song ON playlist_songs(song_id);",
+    )
+}
+
+/// Rules of smart playlists as JSON, and when each song entered the library for "recently added" rules.
+/// Songs already in the library count as added when their file was last modified.
+fn smart_playlists(tx: &Transaction) -> rusqlite::Result<()> {
+    tx.execute_batch(
+        "ALTER TABLE playlists ADD COLUMN rules TEXT;
+        ALTER TABLE songs ADD COLUMN added_at INTEGER;
+        UPDATE songs SET added_at = COALESCE(file_mtime, CAST(strftime('%s', 'now') AS INTEGER));
+        CREATE INDEX IF NOT EXISTS idx_songs_added_at ON songs(added_at);",
+    )
+}
+
+/// One row per play, `counted` once enough of the song was heard to add to its play count.
+fn play_history(tx: &Transaction) -> rusqlite::Result<()> {
+    tx.execute_batch(
+        "CREATE TABLE IF NOT EXISTS plays (
+            id               INTEGER PRIMARY KEY,
+            song_id          INTEGER NOT NULL,
+            started_at       INTEGER NOT NULL,
+            listened_ms      INTEGER NOT NULL,
+            completed        INTEGER NOT NULL,
+            counted          INTEGER NOT NULL,
+            FOREIGN KEY(song_id) REFERENCES songs(id)
+        );
+
+        CREATE INDEX IF NOT EXISTS idx_plays_started_at ON plays(started_at);
+        CREATE INDEX IF NOT EXISTS idx_plays_song ON plays(song_id);",
+    )
+}
+
+/// Star rating from 0 (unrated) to 5, and whether the song is loved.
+fn ratings(tx: &Transaction) -> rusqlite::Result<()> {
+    tx.execute_batch(
+        "ALTER TABLE songs ADD COLUMN rating INTEGER NOT NULL DEFAULT 0;
+        ALTER TABLE songs ADD COLUMN loved INTEGER NOT NULL DEFAULT 0;",
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+
+    /// A database file in its own temp folder, removed again when dropped.
+    struct Fixture {
+        dir: PathBuf,
+    }
+
+    impl Fixture {
+        fn new(name: &str) -> Fixture {
+            let dir = std::env::temp_dir().join(format!("round-migrations-{}-{}", std::process::id(), name));
+            let _ = std::fs::remove_dir_all(&dir);
+            std::fs::create_dir_all(&dir).unwrap();
+            Fixture { dir }
+        }
+
+        fn db_path(&self) -> PathBuf {
+            self.dir.join("library.db")
+        }
+
+        fn backup_path(&self, version: i32) -> PathBuf {
+            self.dir.join(format!("library.v{}.bak", version))
+        }
+
+        fn open(&self) -> Connection {
+            Connection::open(self.db_path()).unwrap()
+        }
+    }
+
+    impl Drop for Fixture {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.dir);
+        }
+    }
+
+    /// Builds the schema a database of `version` had, released migrations are never edited so replaying them is exact.
+    fn create_schema(conn: &mut Connection, version: i32) {
+        let tx = conn.transaction().unwrap();
+        for migration in MIGRATIONS.iter().filter(|migration| migration.version <= version) {
+            (migration.apply)(&tx).unwrap();
+        }
+        tx.pragma_update(None, "user_version", version).unwrap();
+        tx.commit().unwrap();
+    }
+
+    /// Two songs on one album and a playlist holding them in reverse order, plus a setting once the table exists.
+    fn insert_rows(conn: &Connection, version: i32) {
+        conn.execute_batch(
+            "INSERT INTO artists (id, name) VALUES (1, 'Artist');
+            INSERT INTO albums (id, name, artist_id) VALUES (1, 'Album', 1);
+            INSERT INTO songs (id, path, title, album_id, track_number, duration_seconds, play_count)
+                VALUES (1, '/music/one.mp3', 'One', 1, 1, 180, 3);
+            INSERT INTO songs (id, path, title, album_id, track_number, duration_seconds, play_count)
+                VALUES (2, '/music/two.mp3', 'Two', 1, 2, 200, 0);
+            INSERT INTO playlists (id, name) VALUES (1, 'Mix');
+            INSERT INTO playlist_songs (playlist_id, song_id, position) VALUES (1, 2, 4);
+            INSERT INTO playlist_songs (playlist_id, song_id, position) VALUES (1, 1, 9);",
+        ).unwrap();
+        if version >= 3 {
+            conn.execute("INSERT INTO settings (key, value) VALUES ('volume', '0.5')", []).unwrap();
+        }
+    }
+
+    fn playlist_order(conn: &Connection) -> Vec<(i32, i32)> {
+        let mut stmt = conn.prepare("SELECT song_id, position FROM playlist_songs WHERE playlist_id = 1 ORDER BY position").unwrap();
+        stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?))).unwrap()
+            .collect::<rusqlite::Result<Vec<_>>>().unwrap()
+    }
+
+    #[test]
+    fn fresh_database_gets_every_migration_without_backup() {
+        let fixture = Fixture::new("fresh");
+        let mut conn = fixture.open();
+        migrate(&mut conn, &fixture.db_path()).unwrap();
+        assert_eq!(user_version(&conn).unwrap(), DB_STATE_VERSION);
+        assert!(std::fs::read_dir(&fixture.dir).unwrap().all(|entry| !entry.unwrap().path().to_string_lossy().ends_with(".bak")));
+    }
+
+    #[test]
+    fn every_version_keeps_user_data() {
+        for version in MIGRATIONS.iter().map(|migration| migration.version) {
+            let fixture = Fixture::new(&format!("v{}", version));
+            let mut conn = fixture.open();
+            create_schema(&mut conn, version);
+            insert_rows(&conn, version);
+
+            migrate(&mut conn, &fixture.db_path()).unwrap();
+
+            assert_eq!(user_version(&conn).unwrap(), DB_STATE_VERSION, "from version {}", version);
+            let songs: Vec<(i32, String, i32)> = conn.prepare("SELECT id, title, play_count FROM songs ORDER BY id").unwrap()
+                .query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?))).unwrap()
+                .collect::<rusqlite::Result<_>>().unwrap();
+            assert_eq!(songs, vec![(1, "One".to_string(), 3), (2, "Two".to_string(), 0)], "from version {}", version);
+            let playlist: String = conn.query_row("SELECT name FROM playlists WHERE id = 1", [], |row| row.get(0)).unwrap();
+            assert_eq!(playlist, "Mix");
+            let order = playlist_order(&conn);
+            if version < 9 {
+                //playlist entries got renumbered from 1 when they gained their own ids
+                assert_eq!(order, vec![(2, 1), (1, 2)], "from version {}", version);
+            } else {
+                assert_eq!(order, vec![(2, 4), (1, 9)], "from version {}", version);
+            }
+            if version >= 3 {
+                let volume: String = conn.query_row("SELECT value FROM settings WHERE key = 'volume'", [], |row| row.get(0)).unwrap();
+                assert_eq!(volume, "0.5");
+            }
+
+            if version == DB_STATE_VERSION {
+                assert!(!fixture.backup_path(version).exists());
+            } else {
+                let backup = Connection::open(fixture.backup_path(version)).unwrap();
+                assert_eq!(user_version(&backup).unwrap(), version);
+                let backed_up: i32 = backup.query_row("SELECT COUNT(*) FROM songs", [], |row| row.get(0)).unwrap();
+                assert_eq!(backed_up, 2);
+            }
+        }
+    }
+
+    /// Version 3 databases made before the migrations may already have the equalizer tables,
+    /// `Db::new` created them without bumping the version.
+    #[test]
+    fn version_3_with_equalizer_tables() {
+        let fixture = Fixture::new("v3-eq");
+        let mut conn = fixture.open();
+        create_schema(&mut conn, 3);
+        let tx = conn.transaction().unwrap();
+        equalizer_presets(&tx).unwrap();
+        tx.commit().unwrap();
+        conn.execute("INSERT INTO eq_presets (id, name, settings) VALUES (1, 'Loud', '{}')", []).unwrap();
+        insert_rows(&conn, 3);
+
+        migrate(&mut conn, &fixture.db_path()).unwrap();
+
+        assert_eq!(user_version(&conn).unwrap(), DB_STATE_VERSION);
+        let preset: String = conn.query_row("SELECT name FROM eq_presets WHERE id = 1", [], |row| row.get(0)).unwrap();
+        assert_eq!(preset, "Loud");
+    }
+
+    #[test]
+    fn search_index_covers_migrated_songs() {
+        let fixture = Fixture::new("search");
+        let mut conn = fixture.open();
+        create_schema(&mut conn, 5);
+        insert_rows(&conn, 5);
+
+        migrate(&mut conn, &fixture.db_path()).unwrap();
+
+        let found: i32 = conn.query_row("SELECT rowid FROM song_search WHERE song_search MATCH 'two'", [], |row| row.get(0)).unwrap();
+        assert_eq!(found, 2);
+    }
+
+    #[test]
+    fn legacy_database_is_rebuilt_after_backup() {
+        let fixture = Fixture::new("legacy");
+        let mut conn = fixture.open();
+        create_schema(&mut conn, 2);
+        insert_rows(&conn, 2);
+        conn.pragma_update(None, "user_version", 1).unwrap();
+
+        migrate(&mut conn, &fixture.db_path()).unwrap();
+
+        assert_eq!(user_version(&conn).unwrap(), DB_STATE_VERSION);
+        let songs: i32 = conn.query_row("SELECT COUNT(*) FROM songs", [], |row| row.get(0)).unwrap();
+        assert_eq!(songs, 0);
+        let backup = Connection::open(fixture.backup_path(1)).unwrap();
+        let backed_up: i32 = backup.query_row("SELECT COUNT(*) FROM songs", [], |row| row.get(0)).unwrap();
+        assert_eq!(backed_up, 2);
+    }
+
+    #[test]
+    fn newer_database_is_refused() {
+        let fixture = Fixture::new("newer");
+        let mut conn = fixture.open();
+        conn.pragma_update(None, "user_version", DB_STATE_VERSION + 1).unwrap();
+        assert!(migrate(&mut conn, &fixture.db_path()).is_err());
+    }
+}
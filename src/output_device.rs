@@ -0,0 +1,51 @@
+use std::sync::{Arc, atomic::{AtomicBool, Ordering}};
+
+use rodio::{OutputStream, OutputStreamBuilder, StreamError, cpal::{self, traits::{DeviceTrait, HostTrait}}};
+
+pub const OUTPUT_DEVICE_SETTING: &str = "output_device";
+
+/// Names of the output devices of the default host.
+pub fn list_output_devices() -> Vec<String> {
+    match cpal::default_host().output_devices() {
+        Ok(devices) => devices.filter_map(|device| device.name().ok()).collect(),
+        Err(e) => {
+            eprintln!("Failed to list output devices: {}", e);
+            Vec::new()
+        }
+    }
+}
+
+fn find_output_device(name: &str) -> Option<cpal::Device> {
+    cpal::default_host()
+        .output_devices()
+        .ok()?
+        .find(|device| device.name().is_ok_and(|device_name| device_name == name))
+}
+
+/// Opens the named device, falling back to the default one when it is missing or fails to open.
+/// `lost` is set when the stream reports an error, e.g. because the device was unplugged.
+pub fn open_output_stream(name: Option<&str>, lost: Arc<AtomicBool>) -> Result<OutputStream, StreamError> {
+    let on_error = move |e: cpal::StreamError| {
+        eprintln!("Output stream error: {}", e);
+        lost.store(true, Ordering::SeqCst);
+    };
+
+    if let Some(name) = name {
+        match find_output_device(name) {
+            Some(device) => {
+                let stream = OutputStreamBuilder::from_device(device)
+                    .map(|builder| builder.with_error_callback(on_error.clone()))
+                    .and_then(|builder| builder.open_stream_or_fallback());
+                match stream {
+                    Ok(stream) => return Ok(stream),
+                    Err(e) => eprintln!("Failed to open output device {}: {}, using the default device", name, e),
+                }
+            }
+            None => eprintln!("Output device {} not found, using the default device", name),
+        }
+    }
+
+    OutputStreamBuilder::from_default_device()
+        .map(|builder| builder.with_error_callback(on_error))
+        .and_then(|builder| builder.open_stream_or_fallback())
+}
@@ -0,0 +1,70 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::db::{Db, SongView};
+
+/// A play counts once half the track or this much of it was heard, whichever comes first.
+const PLAY_THRESHOLD_MS: u64 = 240_000;
+const MAX_PROGRESS_STEP_MS: u64 = 5_000; //bigger jumps are seeks, not listening
+const END_MARGIN_MS: u64 = 2_000; //a play stopped this close to the end still completed it
+
+pub fn unix_now() -> i64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|now| now.as_secs() as i64).unwrap_or(0)
+}
+
+/// The song being listened to, recorded in the `plays` table once it ends or another one starts.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PlayInProgress {
+    song_id: i32,
+    duration_ms: u64,
+    started_at: i64,
+    listened_ms: u64,
+    position_ms: Option<u64>,
+}
+
+impl PlayInProgress {
+    pub fn start(song: &SongView) -> PlayInProgress {
+        PlayInProgress {
+            song_id: song.id,
+            duration_ms: song.duration_seconds * 1000,
+            started_at: unix_now(),
+            listened_ms: 0,
+            position_ms: None,
+        }
+    }
+
+    pub fn song_id(&self) -> i32 {
+        self.song_id
+    }
+
+    /// Adds the time since the last reported position, seeks don't count as listening.
+    pub fn progress(&mut self, position_ms: u64) {
+        if let Some(last) = self.position_ms {
+            let step = position_ms.saturating_sub(last);
+            if step <= MAX_PROGRESS_STEP_MS {
+                self.listened_ms += step;
+            }
+        }
+        self.position_ms = Some(position_ms);
+    }
+
+    fn counts(&self) -> bool {
+        let threshold = match self.duration_ms {
+            0 => PLAY_THRESHOLD_MS,
+            duration_ms => PLAY_THRESHOLD_MS.min(duration_ms / 2),
+        };
+        self.listened_ms >= threshold
+    }
+
+    /// Records the play, `ended` when the track played out. A crossfade starts the next track
+    /// before this one ends, so reaching its last `crossfade_ms` counts as completing it too.
+    pub fn finish(self, db: &Db, ended: bool, crossfade_ms: u64) {
+        if self.listened_ms == 0 {
+            return;
+        }
+        let near_end = self.duration_ms > 0
+            && self.position_ms.unwrap_or(0) + crossfade_ms + END_MARGIN_MS >= self.duration_ms;
+        if let Err(e) = db.record_play(self.song_id, self.started_at, self.listened_ms, ended || near_end, self.counts()) {
+            eprintln!("Failed to record play: {:?}", e);
+        }
+    }
+}
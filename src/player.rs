@@ -2,16 +2,47 @@
 
 use dioxus::prelude::*;
 
-use crate::app_context::{PlaybackMode, PlayerContext};
+use crate::{app_context::{DatabaseContext, PlaybackMode, PlayerContext, RepeatMode}, queue_state::StopAfter, equalizer_panel::equalizer_panel, rating::{SongRating, MAX_STARS}, settings_dialog::settings_dialog, tempo::{MAX_SPEED, MIN_SPEED}};
 
+const CROSSFADE_STEPS_MS: [u64; 6] = [0, 2000, 4000, 6000, 8000, 12000];
 
 
 #[component]
 pub fn player_sidebar() -> Element {
+    let player_context = use_context::<PlayerContext>();
+    let mut show_settings = use_signal(|| false);
+    let mut show_equalizer = use_signal(|| false);
+
+    let playing_state = player_context.playing_state;
+    let current_album = use_memo(move || playing_state.read().current_song().map(|song| song.album_id));
+    use_effect(move || {
+        let album_id = current_album();
+        player_context.clone().apply_album_equalizer(album_id);
+    });
+
     rsx! {
         div { class: "now-playing-sidebar",
+                div { class: "sidebar-buttons",
+                    button {
+                        class: if show_equalizer() { "settings-button active" } else { "settings-button" },
+                        title: "Equalizer",
+                        onclick: move |_| show_equalizer.toggle(),
+                        "≋"
+                    }
+                    button {
+                        class: "settings-button",
+                        title: "Settings",
+                        onclick: move |_| show_settings.set(true),
+                        "⚙"
+                    }
+                }
+                settings_dialog { show: show_settings }
                 div { class: "content-section",
-                    song_metadata_view { }
+                    if show_equalizer() {
+                        equalizer_panel { }
+                    } else {
+                        song_metadata_view { }
+                    }
                 }
                 div { class: "song-view-container",
                         song_progress_bar { }
@@ -45,7 +76,7 @@ pub fn song_metadata_view() -> Element {
             rsx! {
                 div { class: "song-view",
                     div { class: "side-fadeout song-metadata",
-                        h2 { scrolling_text { text: song.title, chars_per_second: 10.0 } },
+                        h2 { scrolling_text { text: song.title.clone(), chars_per_second: 10.0 } },
                     }
                     div { class: "album-art",
                         { if let Some(art_path) = &song.album_art_path {
@@ -66,8 +97,9 @@ pub fn song_metadata_view() -> Element {
                         } }
                     }
                     div { class: "side-fadeout song-metadata",
-                        h4 { scrolling_text { text: song.artist, chars_per_second: 10.0 } },
+                        h4 { scrolling_text { text: song.display_artist().to_string(), chars_per_second: 10.0 } },
                     }
+                    song_rating { song_id: song.id }
                 }
             }
         } else {
@@ -90,11 +122,63 @@ pub fn song_metadata_view() -> Element {
 }
 
 
+/// Stars and a heart for the playing song, clicking the current rating clears it.
+#[component]
+pub fn song_rating(song_id: i32) -> Element {
+    let db = use_context::<DatabaseContext>();
+    let player_context = use_context::<PlayerContext>();
+    let library_update_counter = player_context.library_update_counter;
+    let mut hovered = use_signal(|| 0u8);
+
+    let rating = use_memo(use_reactive!(|song_id| {
+        let _ = library_update_counter();
+        db.get().get_song_rating(song_id).unwrap_or_default()
+    }));
+    let shown = if hovered() > 0 { hovered() } else { rating().stars };
+
+    rsx! {
+        div { class: "song-rating",
+            onmouseleave: move |_| hovered.set(0),
+            for stars in 1..=MAX_STARS {
+                {
+                    let mut player_context = player_context.clone();
+                    rsx! {
+                        button {
+                            key: "{stars}",
+                            class: if stars <= shown { "rating-star filled" } else { "rating-star" },
+                            title: SongRating::stars_label(stars),
+                            onmouseenter: move |_| hovered.set(stars),
+                            onclick: move |_| {
+                                let stars = if rating().stars == stars { 0 } else { stars };
+                                player_context.set_rating(song_id, stars);
+                            },
+                            "★"
+                        }
+                    }
+                }
+            }
+            {
+                let mut player_context = player_context.clone();
+                let loved = rating().loved;
+                rsx! {
+                    button {
+                        class: if loved { "rating-love loved" } else { "rating-love" },
+                        title: if loved { "Loved" } else { "Love" },
+                        onclick: move |_| player_context.set_loved(song_id, !loved),
+                        "♥"
+                    }
+                }
+            }
+        }
+    }
+}
+
 #[component]
 pub fn song_progress_bar() -> Element {
     let player_state = use_context::<PlayerContext>();
 
     let playing_state = player_state.playing_state.clone();
+    let speed = player_state.speed;
     let current_song = use_memo(move || playing_state.read().current_song());
     
     let progress_ms = use_memo(move || playing_state.read().progress());
@@ -141,19 +225,21 @@ pub fn song_progress_bar() -> Element {
                 },
                 disabled: playing_state.read().current_song().is_none().to_string(),
             }
-            song_length_marker{ len: song_len_ms,  prog: progress_ms }
+            song_length_marker{ len: song_len_ms,  prog: progress_ms, speed }
             playback_controls { }
+            speed_control { }
         }
     }
 }
 
 #[component]
-pub fn song_length_marker(len: ReadSignal<u64>, prog: ReadSignal<u64>) -> Element {
+pub fn song_length_marker(len: ReadSignal<u64>, prog: ReadSignal<u64>, speed: ReadSignal<f32>) -> Element {
     let remaining = use_memo(move || {
         let len = *len.read();
         let prog = *prog.read();
         let len =  if prog > len { prog } else { len }; //prevent overflow when len is not updated yet
         let remaining = len / 1000 - prog / 1000;       //happens for one frame only when song finished and another song is loaded 
+        let remaining = (remaining as f32 / *speed.read()).round() as u64; //time left at the current playback speed
         let minutes = remaining / 60;
         let seconds = remaining % 60;
         format!("-{:02}:{:02}", minutes, seconds)
@@ -214,14 +300,46 @@ pub fn playback_controls() -> Element {
                     *ps.mode.write() = PlaybackMode::Shuffle;
                 }
                 PlaybackMode::Shuffle => {
-                    *ps.mode.write() = PlaybackMode::Loop;
-                }
-                PlaybackMode::Loop => {
                     *ps.mode.write() = PlaybackMode::Normal;
                 }
             }
         }
     };
+
+    let mut cycle_repeat = {
+        let mut ps = player_state.clone();
+        move || {
+            let next = ps.repeat.peek().cycle();
+            ps.set_repeat(next);
+        }
+    };
+
+    let mut cycle_stop_after = {
+        let mut ps = player_state.clone();
+        move || {
+            let next = ps.stop_after.peek().cycle();
+            ps.set_stop_after(next);
+        }
+    };
+    
+    let mut cycle_crossfade = {
+        let mut ps = player_state.clone();
+        move || {
+            let current = *ps.crossfade_ms.read();
+            let next = CROSSFADE_STEPS_MS.iter()
+                .copied()
+                .find(|step| *step > current)
+                .unwrap_or(0);
+            ps.set_crossfade(next);
+        }
+    };
+
+    let crossfade_label = use_memo(move || {
+        match *player_state.crossfade_ms.read() {
+            0 => "⇌ off".to_string(),
+            ms => format!("⇌ {}s", ms / 1000),
+        }
+    });
     
     rsx! {
         div { class: "controls-container",
@@ -248,13 +366,49 @@ pub fn playback_controls() -> Element {
             }
             div { class: "right-controls",
                 button {
+                    class: "crossfade-button",
+                    title: "Crossfade",
                     onclick: move |_| {
-                        toggle_mode();
+                        cycle_crossfade();
                     },
-                    match *player_state.mode.read() {
-                        PlaybackMode::Normal => "→",
-                        PlaybackMode::Shuffle => "⚂",
-                        PlaybackMode::Loop => "↻",
+                    {crossfade_label}
+                }
+                div { class: "mode-buttons",
+                    button {
+                        title: "Shuffle",
+                        onclick: move |_| {
+                            toggle_mode();
+                        },
+                        match *player_state.mode.read() {
+                            PlaybackMode::Normal => "→",
+                            PlaybackMode::Shuffle => "⚂",
+                        }
+                    }
+                    button {
+                        class: if *player_state.repeat.read() != RepeatMode::Off { "active" } else { "" },
+                        title: match *player_state.repeat.read() {
+                            RepeatMode::Off => "Repeat off",
+                            RepeatMode::One => "Repeat current song",
+                            RepeatMode::All => "Repeat all",
+                        },
+                        onclick: move |_| {
+                            cycle_repeat();
+                        },
+                        match *player_state.repeat.read() {
+                            RepeatMode::One => "↻1",
+                            _ => "↻",
+                        }
+                    }
+                    button {
+                        class: if *player_state.stop_after.read() != StopAfter::Never { "active" } else { "" },
+                        title: player_state.stop_after.read().label(),
+                        onclick: move |_| {
+                            cycle_stop_after();
+                        },
+                        match *player_state.stop_after.read() {
+                            StopAfter::Queue => "⏹q",
+                            _ => "⏹",
+                        }
                     }
                 }
             }
@@ -263,6 +417,68 @@ pub fn playback_controls() -> Element {
 }
 
 
+#[component]
+pub fn speed_control() -> Element {
+    let player_state = use_context::<PlayerContext>();
+
+    let ps = player_state.clone();
+    let value = use_memo(move || ps.get_speed());
+
+    let style_str = use_memo(
+        move || {
+            let percentage = (value() - MIN_SPEED) / (MAX_SPEED - MIN_SPEED) * 100.0;
+            format!("--slider-progress: {}%;", percentage)
+        }
+    );
+
+    let ps = player_state.clone();
+    let preserve_pitch = use_memo(move || *ps.preserve_pitch.read());
+
+    let set_speed = {
+        let ps = player_state.clone();
+        move |speed: f32| ps.clone().set_speed(speed)
+    };
+
+    let mut toggle_pitch = {
+        let mut ps = player_state.clone();
+        move || ps.set_preserve_pitch(!preserve_pitch())
+    };
+
+    rsx! {
+        div { class: "speed-control",
+            button {
+                class: "speed-label",
+                title: "Reset speed",
+                onclick: {
+                    let set_speed = set_speed.clone();
+                    move |_| set_speed(1.0)
+                },
+                "{value():.2}x"
+            }
+            input {
+                class: "slider",
+                r#type: "range",
+                style: style_str,
+                min: "{MIN_SPEED}",
+                max: "{MAX_SPEED}",
+                step: "0.05",
+                value: value(),
+                oninput: move |e| {
+                    if let Ok(speed) = e.value().parse::<f32>() {
+                        set_speed(speed);
+                    }
+                },
+            }
+            button {
+                class: if preserve_pitch() { "pitch-toggle active" } else { "pitch-toggle" },
+                title: "Keep pitch when changing speed",
+                onclick: move |_| toggle_pitch(),
+                "♪"
+            }
+        }
+    }
+}
+
 pub fn volume_control() -> Element {
 
     let player_state = use_context::<PlayerContext>();
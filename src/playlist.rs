@@ -8,6 +8,8 @@ pub struct Playlist {
     id: i32,
     name: String,
     current_song_index: usize,
+    /// Songs of a smart playlist, evaluated once so positions stay put while it plays (random order included).
+    smart_songs: Option<Arc<Vec<SongView>>>,
     pub db: Arc<Db>,
 }
 
@@ -21,8 +23,23 @@ impl Playlist {
     pub fn get_playlist_handle(id: i32, db: Arc<Db>) -> Result<Playlist, rusqlite::Error> {
         let db = db.clone();
         let data = db.get_playlist_data(id)?;
+        let smart_songs = match db.get_playlist_rules(id)? {
+            Some(rules) => Some(Arc::new(db.get_smart_playlist_songs(&rules)?)),
+            None => None,
+        };
 
-        Ok(Playlist{id: data.0, name: data.1, current_song_index: 0, db: db.clone()})
+        Ok(Playlist{id: data.0, name: data.1, current_song_index: 0, smart_songs, db: db.clone()})
+    }
+
+    pub fn is_smart(&self) -> bool {
+        self.smart_songs.is_some()
+    }
+
+    fn nth_song(&self, n: usize) -> Option<SongView> {
+        match &self.smart_songs {
+            Some(songs) => songs.get(n).cloned(),
+            None => self.db.get_nth_playlist_song(self.id, n).ok()?,
+        }
     }
 
     pub fn id(&self) -> i32 {
@@ -34,7 +51,10 @@ impl Playlist {
     }
 
     pub fn songs(&self) -> Vec<SongView> {
-        self.db.get_songs_in_playlist(self.id).ok().unwrap_or(Vec::new())
+        match &self.smart_songs {
+            Some(songs) => songs.as_ref().clone(),
+            None => self.db.get_songs_in_playlist(self.id).ok().unwrap_or(Vec::new()),
+        }
     }
 
     pub fn current_index(&self) -> usize {
@@ -45,28 +65,55 @@ impl Playlist {
         self.current_song_index = index;
     }
 
+    /// Keeps the same song current after the entry at `from` moved to `to`.
+    pub fn entry_moved(&mut self, from: usize, to: usize) {
+        let current = self.current_song_index;
+        self.current_song_index = if current == from {
+            to
+        } else if from < current && current <= to {
+            current - 1
+        } else if to <= current && current < from {
+            current + 1
+        } else {
+            current
+        };
+    }
+
+    /// Keeps the same song current after the entry at `index` was removed.
+    pub fn entry_removed(&mut self, index: usize) {
+        if index < self.current_song_index {
+            self.current_song_index -= 1;
+        }
+    }
+
     pub fn song_count(&self) -> usize {
-        self.db.get_playlist_song_count(self.id).unwrap_or(0)
+        match &self.smart_songs {
+            Some(songs) => songs.len(),
+            None => self.db.get_playlist_song_count(self.id).unwrap_or(0),
+        }
     }
 
-    pub fn next_song(&mut self) -> Option<SongView> {
-        if let Some(song) = self.db.get_nth_playlist_song(self.id, self.current_song_index + 1).ok()? {
+    /// The song after the current one, or the first song again when `wrap` is set.
+    pub fn next_song(&mut self, wrap: bool) -> Option<SongView> {
+        if let Some(song) = self.nth_song(self.current_song_index + 1) {
             self.current_song_index += 1;
             Some(song)
+        } else if !wrap {
+            None
         } else {
-            let song = self.db.get_nth_playlist_song(self.id, 0).ok()?;
+            let song = self.nth_song(0);
             self.current_song_index = 0;
             song
         }
     }
     
     pub fn prev_song(&mut self) -> Option<SongView> {
-        if let Some(song) = self.db.get_nth_playlist_song(self.id, self.current_song_index - 1).ok()? {
+        if let Some(song) = self.nth_song(self.current_song_index - 1) {
             self.current_song_index -= 1;
             Some(song)
         } else {
             let len = self.song_count();
-            let song = self.db.get_nth_playlist_song(self.id, len - 1).ok()?;
+            let song = self.nth_song(len - 1);
             self.current_song_index = len - 1;
             song
         }
@@ -1,7 +1,7 @@
 use dioxus::prelude::*;
 
 use crate::{
-    app_context::{DatabaseContext, PlayerContext}, context_menu::{ContextMenuItem, context_menu}, file_browser::{FileEntry, song_file}, playlist::Playlist, queue_state::QueueFallbackMode
+    app_context::{DatabaseContext, PlayerContext}, context_menu::{ContextMenuItem, context_menu}, file_browser::{FileEntry, song_file}, playlist::Playlist, db::SongView, playlist_export_dialog::playlist_export_dialog, queue_state::QueueFallbackMode, smart_playlist_dialog::smart_playlist_dialog
 };
 
 #[derive(Clone, Debug, PartialEq)]
@@ -44,6 +44,7 @@ fn playlist_list(on_select: EventHandler<(i32, String)>) -> Element {
     let db = use_context::<DatabaseContext>();
     let player_context = use_context::<PlayerContext>();
     let mut show_create_dialog = use_signal(|| false);
+    let mut show_smart_dialog = use_signal(|| false);
     let mut refresh_trigger = player_context.playlist_update_counter.clone();
 
     let db_clone = db.clone();
@@ -64,8 +65,16 @@ fn playlist_list(on_select: EventHandler<(i32, String)>) -> Element {
             div { class: "content-section",
                 style: "margin-bottom: 300px;",
                 h2 { "Playlists" }
+                div { class: "common-button",
+                    style: "margin-bottom: 10px;
+                            justify-content: left;",
+                    button {
+                        onclick: move |_| show_smart_dialog.set(true),
+                        "New Smart Playlist..."
+                    }
+                }
                 div {  class: "item-grid",
-                    for (id, name) in playlists() {
+                    for (id, name, smart) in playlists() {
                         {
                             let id = id;
                             let name = name.clone();
@@ -73,6 +82,7 @@ fn playlist_list(on_select: EventHandler<(i32, String)>) -> Element {
                                 playlist_item {
                                     playlist_id: id,
                                     playlist_name: name.clone(),
+                                    smart,
                                     on_select: move |_| {
                                         on_select.call((id, name.clone()));
                                     },
@@ -85,6 +95,9 @@ fn playlist_list(on_select: EventHandler<(i32, String)>) -> Element {
                     }
                 }
             }
+            if show_smart_dialog() {
+                smart_playlist_dialog { show: show_smart_dialog }
+            }
         }
 }
 
@@ -92,11 +105,14 @@ fn playlist_list(on_select: EventHandler<(i32, String)>) -> Element {
 fn playlist_item(
     playlist_id: i32,
     playlist_name: String,
+    #[props(default)] smart: bool,
     on_select: EventHandler<()>,
     on_delete: EventHandler<()>,
 ) -> Element {
     let mut show_context_menu = use_signal(|| false);
     let mut context_menu_pos = use_signal(|| (0.0, 0.0));
+    let mut show_export_dialog = use_signal(|| false);
+    let mut show_smart_dialog = use_signal(|| false);
     let db = use_context::<DatabaseContext>();
 
     let db_clone = db.clone();
@@ -106,20 +122,36 @@ fn playlist_item(
         }
     };
 
-    let context_menu_items = vec![
+    let mut context_menu_items = vec![
         ContextMenuItem {
             title: "Open".to_string(),
             action: EventHandler::new(move |_| {
                 on_select.call(());
             }),
         },
+    ];
+    if smart {
+        context_menu_items.push(ContextMenuItem {
+            title: "Edit rules...".to_string(),
+            action: EventHandler::new(move |_| {
+                show_smart_dialog.set(true);
+            }),
+        });
+    }
+    context_menu_items.extend([
+        ContextMenuItem {
+            title: "Export...".to_string(),
+            action: EventHandler::new(move |_| {
+                show_export_dialog.set(true);
+            }),
+        },
         ContextMenuItem {
             title: "Delete Playlist".to_string(),
             action: EventHandler::new(move |_| {
                 delete_playlist();
             }),
         },
-    ];
+    ]);
 
     rsx! {
         button {
@@ -133,7 +165,7 @@ fn playlist_item(
                 context_menu_pos.set((evt.client_coordinates().x, evt.client_coordinates().y));
                 show_context_menu.set(true);
             },
-            div { class: "item-icon", "📋" }
+            div { class: "item-icon", if smart { "✨" } else { "📋" } }
             div { class: "item-name", "{playlist_name}" }
         }
         context_menu {
@@ -141,25 +173,72 @@ fn playlist_item(
             show_context_menu,
             items: context_menu_items
         }
+        if show_export_dialog() {
+            playlist_export_dialog { playlist_id, playlist_name: playlist_name.clone(), show: show_export_dialog }
+        }
+        if show_smart_dialog() {
+            smart_playlist_dialog { playlist_id, show: show_smart_dialog }
+        }
+    }
+}
+
+/// Applies `change` to the queue's playlist when it is the one being played.
+fn update_playing_playlist(player_context: &PlayerContext, playlist_id: i32, change: impl FnOnce(&mut Playlist)) {
+    let mut fallback = player_context.queue.peek().current_fallback_queue;
+    let mut fallback = fallback.write();
+    if let QueueFallbackMode::Playlist { playlist } = &mut *fallback {
+        if playlist.id() == playlist_id {
+            change(playlist);
+        }
+    }
+}
+
+/// Plays `song` and continues with the rest of the playlist after it.
+fn play_from_playlist(player_context: &mut PlayerContext, playlist: Option<Playlist>, index: usize, song: &SongView) {
+    let _ = player_context.queue.write().play_song_instant(song);
+
+    if let Some(mut playlist) = playlist {
+        playlist.set_current(index);
+        *player_context.queue.write().current_fallback_queue.write() =
+         QueueFallbackMode::Playlist { playlist: playlist };
     }
 }
 
 #[component]
 fn playlist_content(playlist_id: i32, playlist_name: String, on_back: EventHandler<()>) -> Element {
     let db = use_context::<DatabaseContext>();
-    let refresh_trigger = use_context::<PlayerContext>().playlist_update_counter.clone();
-    let mut player_context = use_context::<PlayerContext>();
+    let mut refresh_trigger = use_context::<PlayerContext>().playlist_update_counter.clone();
+    let player_context = use_context::<PlayerContext>();
+    let mut dragged = use_signal(|| None::<usize>);
+    let mut drop_target = use_signal(|| None::<usize>);
+    let mut show_smart_dialog = use_signal(|| false);
     let db_clone = db.clone();
-    let songs = use_memo(move || {
+    let entries = use_memo(move || {
         let _ = refresh_trigger();
-        db_clone.get().get_songs_in_playlist(playlist_id).unwrap_or_default()
+        db_clone.get().get_playlist_entries(playlist_id).unwrap_or_default()
     });
 
+    let db_clone = db.clone();
     let current_playlist = use_memo(move || {
         let _ = refresh_trigger();
-        Playlist::get_playlist_handle(playlist_id, db.arc().clone()).ok()
+        Playlist::get_playlist_handle(playlist_id, db_clone.arc().clone()).ok()
     });
 
+    let db_move = db.clone();
+    let ctx_move = player_context.clone();
+    let move_entry = move |entry_id: i32, from: usize, to: usize| {
+        if from == to {
+            return;
+        }
+        match db_move.get().move_playlist_entry(playlist_id, entry_id, to) {
+            Ok(()) => {
+                update_playing_playlist(&ctx_move, playlist_id, |playlist| playlist.entry_moved(from, to));
+                refresh_trigger += 1;
+            }
+            Err(e) => eprintln!("Failed to move playlist entry: {:?}", e),
+        }
+    };
+
     rsx! {
         div {
             class: "content-section",
@@ -173,22 +252,92 @@ fn playlist_content(playlist_id: i32, playlist_name: String, on_back: EventHandl
                     "← Back to Playlists"
                 }
             }
-            div {
-                class: "item-list",
-                for (idx, song) in songs().iter().cloned().enumerate() {
-                    {
-                        let song = song.clone();
-                        rsx! {
-                            song_file {
-                                file: FileEntry::from_song_view(&song),
-                                on_click: move |_path| {
-                                    player_context.queue.write().play_song_instant(&song);
-
-                                    if current_playlist.read().is_some() {
-                                        let mut playlist = current_playlist.read().as_ref().unwrap().clone();
-                                        playlist.set_current(idx);
-                                        *player_context.queue.write().current_fallback_queue.write() =
-                                         QueueFallbackMode::Playlist { playlist: playlist };
+            if let Some(playlist) = current_playlist().filter(Playlist::is_smart) {
+                div { class: "common-button",
+                    style: "margin-bottom: 10px;
+                            justify-content: left;",
+                    button {
+                        onclick: move |_| show_smart_dialog.set(true),
+                        "Edit rules..."
+                    }
+                }
+                //smart playlists are ordered by their rules, so no dragging or removing here
+                div {
+                    class: "item-list",
+                    for (idx, song) in playlist.songs().into_iter().enumerate() {
+                        {
+                            let file = FileEntry::from_song_view(&song);
+                            let mut player_context = player_context.clone();
+                            rsx! {
+                                song_file {
+                                    key: "{idx}",
+                                    file,
+                                    on_click: move |_path| {
+                                        play_from_playlist(&mut player_context, current_playlist(), idx, &song);
+                                    }
+                                }
+                            }
+                        }
+                    }
+                }
+            } else {
+                div {
+                    class: "item-list",
+                    for (idx, entry) in entries().into_iter().enumerate() {
+                        {
+                            let song = entry.song.clone();
+                            let entry_id = entry.id;
+                            let mut move_entry = move_entry.clone();
+                            let db_remove = db.clone();
+                            let ctx_remove = player_context.clone();
+                            let mut player_context = player_context.clone();
+                            let remove_item = ContextMenuItem {
+                                title: "Remove from playlist".to_string(),
+                                action: EventHandler::new(move |_| {
+                                    match db_remove.get().remove_playlist_entry(entry_id) {
+                                        Ok(()) => {
+                                            update_playing_playlist(&ctx_remove, playlist_id, |playlist| playlist.entry_removed(idx));
+                                            refresh_trigger += 1;
+                                        }
+                                        Err(e) => eprintln!("Failed to remove playlist entry: {:?}", e),
+                                    }
+                                }),
+                            };
+                            rsx! {
+                                div {
+                                    key: "{entry_id}",
+                                    //the dragged entry takes the target's place, landing below it when moving down
+                                    class: match (dragged(), drop_target()) {
+                                        (Some(from), Some(target)) if target == idx && from < idx => "playlist-entry drop-below",
+                                        (Some(from), Some(target)) if target == idx && from > idx => "playlist-entry drop-above",
+                                        _ => "playlist-entry",
+                                    },
+                                    draggable: true,
+                                    ondragstart: move |_| dragged.set(Some(idx)),
+                                    ondragover: move |evt: Event<DragData>| {
+                                        //dropping is only allowed where dragover is cancelled
+                                        evt.prevent_default();
+                                        drop_target.set(Some(idx));
+                                    },
+                                    ondragend: move |_| {
+                                        dragged.set(None);
+                                        drop_target.set(None);
+                                    },
+                                    ondrop: move |evt: Event<DragData>| {
+                                        evt.prevent_default();
+                                        if let Some(from) = dragged.take() {
+                                            if let Some(moved) = entries.peek().get(from) {
+                                                move_entry(moved.id, from, idx);
+                                            }
+                                        }
+                                        drop_target.set(None);
+                                    },
+                                    song_file {
+                                        file: FileEntry::from_song_view(&song),
+                                        menu_items: vec![remove_item],
+                                        on_click: move |_path| {
+                                            play_from_playlist(&mut player_context, current_playlist(), idx, &song);
+                                        }
                                     }
                                 }
                             }
@@ -196,6 +345,9 @@ fn playlist_content(playlist_id: i32, playlist_name: String, on_back: EventHandl
                     }
                 }
             }
+            if show_smart_dialog() {
+                smart_playlist_dialog { playlist_id, show: show_smart_dialog }
+            }
         }
     }
-}
\ No newline at end of file
+}
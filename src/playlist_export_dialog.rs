@@ -0,0 +1,105 @@
+use std::path::{Path, PathBuf};
+
+use directories::UserDirs;
+use dioxus::prelude::*;
+
+use crate::{
+    app_context::{DatabaseContext, PlayerContext},
+    playlist_file::{PlaylistFormat, export_playlist},
+};
+
+fn default_export_path(playlist_name: &str) -> String {
+    let folder = UserDirs::new()
+        .and_then(|dirs| dirs.audio_dir().map(Path::to_path_buf).or_else(|| Some(dirs.home_dir().to_path_buf())))
+        .unwrap_or_else(|| PathBuf::from("/"));
+    let file_name: String = playlist_name
+        .chars()
+        .map(|c| if std::path::is_separator(c) { '_' } else { c })
+        .collect();
+    folder.join(format!("{}.m3u8", file_name)).to_string_lossy().to_string()
+}
+
+/// `path` with its extension swapped for the one of `format`.
+fn with_format(path: &str, format: PlaylistFormat) -> String {
+    Path::new(path.trim()).with_extension(format.extension()).to_string_lossy().to_string()
+}
+
+#[component]
+pub fn playlist_export_dialog(playlist_id: i32, playlist_name: String, show: Signal<bool>) -> Element {
+    let db = use_context::<DatabaseContext>();
+    let mut player_context = use_context::<PlayerContext>();
+    let mut path = use_signal(|| default_export_path(&playlist_name));
+    //relative paths keep working when the music folder moves along with the playlist
+    let mut relative = use_signal(|| false);
+
+    let mut export = move || {
+        let target = path();
+        match export_playlist(db.get(), playlist_id, Path::new(target.trim()), relative()) {
+            Ok(()) => {
+                player_context.notify(format!("Exported playlist to {}", target.trim()));
+                show.set(false);
+            }
+            Err(e) => player_context.notify(format!("Failed to export playlist: {:#}", e)),
+        }
+    };
+
+    rsx! {
+        div {
+            class: "context-menu-overlay",
+            onclick: move |_| show.set(false),
+
+            div {
+                class: "context-menu settings-dialog",
+                onclick: move |evt: Event<MouseData>| evt.stop_propagation(),
+
+                h3 { class: "dialog-title", "EXPORT PLAYLIST" }
+                div { class: "settings-section",
+                    div { class: "settings-row",
+                        span { "File" }
+                        input {
+                            r#type: "text",
+                            value: "{path}",
+                            oninput: move |evt: Event<FormData>| path.set(evt.value()),
+                        }
+                    }
+                    div { class: "settings-row",
+                        span { "Format" }
+                        select {
+                            value: PlaylistFormat::from_path(Path::new(path().trim())).map(|format| format.extension()).unwrap_or_default(),
+                            onchange: move |evt: Event<FormData>| {
+                                let format = PlaylistFormat::ALL.into_iter().find(|format| format.extension() == evt.value());
+                                if let Some(format) = format {
+                                    path.set(with_format(&path(), format));
+                                }
+                            },
+                            for format in PlaylistFormat::ALL {
+                                option { key: "{format.extension()}", value: format.extension(), "{format.label()}" }
+                            }
+                        }
+                    }
+                    div { class: "settings-row",
+                        span { "Relative paths" }
+                        input {
+                            r#type: "checkbox",
+                            checked: relative(),
+                            onchange: move |evt: Event<FormData>| relative.set(evt.checked()),
+                        }
+                    }
+                }
+                div {
+                    class: "common-button",
+                    style: "display: flex; gap: 10px; margin-top: 10px;",
+                    button {
+                        disabled: path().trim().is_empty(),
+                        onclick: move |_| export(),
+                        "Export"
+                    }
+                    button {
+                        onclick: move |_| show.set(false),
+                        "Cancel"
+                    }
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,741 @@
+use std::path::{Component, Path, PathBuf};
+
+use anyhow::{anyhow, Context, Result};
+use quick_xml::{escape::escape, events::Event, Reader};
+
+use crate::db::{Db, SongView};
+
+pub const PLAYLIST_FILE_EXTENSIONS: [&str; 4] = ["m3u", "m3u8", "xspf", "pls"];
+const DURATION_TOLERANCE_SECONDS: u64 = 3; //taggers and encoders disagree on the length of the same track
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PlaylistFormat {
+    M3u,
+    Xspf,
+    Pls,
+}
+
+impl PlaylistFormat {
+    pub const ALL: [PlaylistFormat; 3] = [PlaylistFormat::M3u, PlaylistFormat::Xspf, PlaylistFormat::Pls];
+
+    pub fn from_path(path: &Path) -> Option<PlaylistFormat> {
+        let extension = path.extension()?.to_str()?.to_lowercase();
+        match extension.as_str() {
+            "m3u" | "m3u8" => Some(PlaylistFormat::M3u),
+            "xspf" => Some(PlaylistFormat::Xspf),
+            "pls" => Some(PlaylistFormat::Pls),
+            _ => None,
+        }
+    }
+
+    /// Extension given to exported files.
+    pub fn extension(&self) -> &'static str {
+        match self {
+            PlaylistFormat::M3u => "m3u8",
+            PlaylistFormat::Xspf => "xspf",
+            PlaylistFormat::Pls => "pls",
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            PlaylistFormat::M3u => "M3U8",
+            PlaylistFormat::Xspf => "XSPF",
+            PlaylistFormat::Pls => "PLS",
+        }
+    }
+}
+
+pub fn is_playlist_file(path: &Path) -> bool {
+    PlaylistFormat::from_path(path).is_some()
+}
+
+/// One entry of a playlist file, with whatever metadata the file carried alongside the location.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct PlaylistFileEntry {
+    pub location: String,
+    pub title: Option<String>,
+    pub artist: Option<String>,
+    pub album: Option<String>,
+    pub duration_seconds: Option<u64>,
+}
+
+impl PlaylistFileEntry {
+    /// "Artist - Title" when known, the location otherwise.
+    pub fn describe(&self) -> String {
+        match (&self.artist, &self.title) {
+            (Some(artist), Some(title)) => format!("{} - {}", artist, title),
+            (None, Some(title)) => title.clone(),
+            _ => self.location.clone(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct PlaylistFile {
+    pub name: Option<String>,
+    pub entries: Vec<PlaylistFileEntry>,
+}
+
+/// Playlist files predating M3U8 are usually Latin-1 rather than UTF-8.
+fn decode_text(bytes: Vec<u8>) -> String {
+    match String::from_utf8(bytes) {
+        Ok(text) => text.trim_start_matches('\u{feff}').to_string(),
+        Err(e) => e.into_bytes().iter().map(|&byte| byte as char).collect(),
+    }
+}
+
+/// Splits the "Artist - Title" display names of M3U and PLS entries.
+fn split_display_name(display: &str) -> (Option<String>, Option<String>) {
+    match display.split_once(" - ") {
+        Some((artist, title)) => (Some(artist.trim().to_string()), Some(title.trim().to_string())),
+        None => (None, Some(display.trim().to_string()).filter(|title| !title.is_empty())),
+    }
+}
+
+fn display_name(song: &SongView) -> String {
+    format!("{} - {}", song.display_artist(), song.title)
+}
+
+/// M3U and PLS both use -1 for an unknown duration.
+fn parse_seconds(text: &str) -> Option<u64> {
+    text.trim()
+        .parse::<f64>()
+        .ok()
+        .filter(|seconds| *seconds >= 0.0)
+        .map(|seconds| seconds.round() as u64)
+}
+
+pub fn parse_m3u(contents: &str) -> PlaylistFile {
+    let mut playlist = PlaylistFile::default();
+    let mut info: Option<PlaylistFileEntry> = None;
+    for line in contents.lines().map(str::trim).filter(|line| !line.is_empty()) {
+        if let Some(extinf) = line.strip_prefix("#EXTINF:") {
+            //#EXTINF:<seconds> [attributes],<artist> - <title>
+            //attribute values are quoted and may hold commas
+            let mut quoted = false;
+            let comma = extinf.find(|c: char| {
+                if c == '"' {
+                    quoted = !quoted;
+                }
+                c == ',' && !quoted
+            });
+            let (duration, display) = match comma {
+                Some(index) => (&extinf[..index], &extinf[index + 1..]),
+                None => (extinf, ""),
+            };
+            let duration_seconds = duration.split_whitespace().next().and_then(parse_seconds);
+            let (artist, title) = split_display_name(display);
+            info = Some(PlaylistFileEntry { title, artist, duration_seconds, ..Default::default() });
+        } else if let Some(name) = line.strip_prefix("#PLAYLIST:") {
+            playlist.name = Some(name.trim().to_string());
+        } else if !line.starts_with('#') {
+            let mut entry = info.take().unwrap_or_default();
+            entry.location = line.to_string();
+            playlist.entries.push(entry);
+        }
+    }
+    playlist
+}
+
+pub fn write_m3u(name: &str, songs: &[SongView], base_dir: Option<&Path>) -> String {
+    let mut contents = format!("#EXTM3U\n#PLAYLIST:{}\n", name);
+    for song in songs {
+        contents.push_str(&format!(
+            "#EXTINF:{},{}\n{}\n",
+            song.duration_seconds,
+            display_name(song),
+            song_location(song, base_dir)
+        ));
+    }
+    contents
+}
+
+/// Reads the `FileN`, `TitleN` and `LengthN` keys, ordering entries by their number.
+pub fn parse_pls(contents: &str) -> PlaylistFile {
+    let mut entries: Vec<(usize, PlaylistFileEntry)> = Vec::new();
+    for line in contents.lines().map(str::trim) {
+        let Some((key, value)) = line.split_once('=') else {
+            continue;
+        };
+        let key = key.trim().to_lowercase();
+        let split = key.find(|c: char| c.is_ascii_digit()).unwrap_or(key.len());
+        let Ok(number) = key[split..].parse::<usize>() else {
+            continue;
+        };
+        let index = match entries.iter().position(|(n, _)| *n == number) {
+            Some(index) => index,
+            None => {
+                entries.push((number, PlaylistFileEntry::default()));
+                entries.len() - 1
+            }
+        };
+        let entry = &mut entries[index].1;
+        match &key[..split] {
+            "file" => entry.location = value.trim().to_string(),
+            "title" => (entry.artist, entry.title) = split_display_name(value),
+            "length" => entry.duration_seconds = parse_seconds(value),
+            _ => {}
+        }
+    }
+    entries.sort_by_key(|(number, _)| *number);
+    PlaylistFile {
+        name: None,
+        entries: entries.into_iter().map(|(_, entry)| entry).filter(|entry| !entry.location.is_empty()).collect(),
+    }
+}
+
+pub fn write_pls(songs: &[SongView], base_dir: Option<&Path>) -> String {
+    let mut contents = String::from("[playlist]\n");
+    for (index, song) in songs.iter().enumerate() {
+        let number = index + 1;
+        contents.push_str(&format!(
+            "File{number}={}\nTitle{number}={}\nLength{number}={}\n",
+            song_location(song, base_dir),
+            display_name(song),
+            song.duration_seconds
+        ));
+    }
+    contents.push_str(&format!("NumberOfEntries={}\nVersion=2\n", songs.len()));
+    contents
+}
+
+pub fn parse_xspf(contents: &str) -> Result<PlaylistFile> {
+    let mut playlist = PlaylistFile::default();
+    let mut reader = Reader::from_str(contents);
+    reader.config_mut().trim_text(true);
+    let mut elements: Vec<String> = Vec::new();
+    let mut track: Option<PlaylistFileEntry> = None;
+    loop {
+        let text = match reader.read_event().context("Invalid XSPF playlist")? {
+            Event::Start(element) => {
+                let name = String::from_utf8_lossy(element.local_name().as_ref()).to_string();
+                if name == "track" {
+                    track = Some(PlaylistFileEntry::default());
+                }
+                elements.push(name);
+                continue;
+            }
+            Event::End(_) => {
+                if elements.pop().as_deref() == Some("track") {
+                    playlist.entries.extend(track.take().filter(|track| !track.location.is_empty()));
+                }
+                continue;
+            }
+            Event::Text(text) => text.unescape().context("Invalid XSPF playlist")?.to_string(),
+            Event::CData(data) => String::from_utf8_lossy(&data.into_inner()).to_string(),
+            Event::Eof => break,
+            _ => continue,
+        };
+        let parent = elements.len().checked_sub(2).map(|index| elements[index].as_str());
+        match (parent, elements.last().map(String::as_str), track.as_mut()) {
+            (Some("track"), Some(field), Some(track)) => match field {
+                //later locations are alternatives to the first one
+                "location" if track.location.is_empty() => track.location = xspf_location(&text),
+                "title" => track.title = Some(text),
+                "creator" => track.artist = Some(text),
+                "album" => track.album = Some(text),
+                "duration" => track.duration_seconds = text.parse::<u64>().ok().map(|ms| (ms + 500) / 1000),
+                _ => {}
+            },
+            (Some("playlist"), Some("title"), None) => playlist.name = Some(text),
+            _ => {}
+        }
+    }
+    Ok(playlist)
+}
+
+/// XSPF locations are URIs, relative ones being percent-encoded paths.
+fn xspf_location(uri: &str) -> String {
+    if uri.contains("://") {
+        uri.to_string()
+    } else {
+        percent_decode(uri)
+    }
+}
+
+pub fn write_xspf(name: &str, songs: &[SongView], base_dir: Option<&Path>) -> String {
+    let mut contents = format!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<playlist version=\"1\" xmlns=\"http://xspf.org/ns/0/\">\n  <title>{}</title>\n  <trackList>\n",
+        escape(name)
+    );
+    for song in songs {
+        let location = match base_dir.and_then(|base_dir| relative_path(base_dir, Path::new(&song.path))) {
+            Some(relative) => percent_encode(&relative.to_string_lossy()),
+            None => format!("file://{}", percent_encode(&song.path)),
+        };
+        contents.push_str(&format!(
+            "    <track>\n      <location>{}</location>\n      <title>{}</title>\n      <creator>{}</creator>\n      <album>{}</album>\n      <duration>{}</duration>\n    </track>\n",
+            escape(&location),
+            escape(&song.title),
+            escape(song.display_artist()),
+            escape(&song.album),
+            song.duration_seconds * 1000
+        ));
+    }
+    contents.push_str("  </trackList>\n</playlist>\n");
+    contents
+}
+
+pub fn read_playlist_file(path: &Path) -> Result<PlaylistFile> {
+    let format = PlaylistFormat::from_path(path).ok_or_else(|| anyhow!("{} is not a playlist file", path.display()))?;
+    let contents = decode_text(std::fs::read(path).with_context(|| format!("Failed to read {}", path.display()))?);
+    match format {
+        PlaylistFormat::M3u => Ok(parse_m3u(&contents)),
+        PlaylistFormat::Xspf => parse_xspf(&contents),
+        PlaylistFormat::Pls => Ok(parse_pls(&contents)),
+    }
+}
+
+fn percent_decode(text: &str) -> String {
+    let bytes = text.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = (bytes[i] == b'%')
+            .then(|| text.get(i + 1..i + 3))
+            .flatten()
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match hex {
+            Some(byte) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            None => {
+                decoded.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).to_string()
+}
+
+fn percent_encode(path: &str) -> String {
+    path.bytes()
+        .map(|byte| match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' | b'/' => (byte as char).to_string(),
+            byte => format!("%{:02X}", byte),
+        })
+        .collect()
+}
+
+/// Local path of an entry, relative ones resolve against the folder holding the playlist.
+/// `None` for remote streams.
+pub fn resolve_location(location: &str, base_dir: &Path) -> Option<PathBuf> {
+    let location = match location.strip_prefix("file://") {
+        Some(url) => percent_decode(url.strip_prefix("localhost").unwrap_or(url)),
+        None if location.contains("://") => return None,
+        None => location.to_string(),
+    };
+    let path = PathBuf::from(&location);
+    if path.is_absolute() {
+        return Some(normalize(&path));
+    }
+    //playlists written on Windows use backslashes
+    Some(normalize(&base_dir.join(location.replace('\\', "/"))))
+}
+
+/// Drops `.` and folds `..` without touching the filesystem, so paths match the ones the scanner stored.
+fn normalize(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                normalized.pop();
+            }
+            component => normalized.push(component),
+        }
+    }
+    normalized
+}
+
+/// `target` as seen from `base_dir`, `None` when they share no root (e.g. different drives).
+fn relative_path(base_dir: &Path, target: &Path) -> Option<PathBuf> {
+    let base: Vec<Component> = base_dir.components().collect();
+    let target: Vec<Component> = target.components().collect();
+    let common = base.iter().zip(&target).take_while(|(a, b)| a == b).count();
+    if common == 0 {
+        return None;
+    }
+    let mut relative = PathBuf::new();
+    for _ in common..base.len() {
+        relative.push("..");
+    }
+    for component in &target[common..] {
+        relative.push(component);
+    }
+    Some(relative)
+}
+
+/// The path written for a song, relative to `base_dir` when one is given.
+fn song_location(song: &SongView, base_dir: Option<&Path>) -> String {
+    base_dir
+        .and_then(|base_dir| relative_path(base_dir, Path::new(&song.path)))
+        .map(|path| path.to_string_lossy().to_string())
+        .unwrap_or_else(|| song.path.clone())
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EntryMatch {
+    Path,
+    FileName,
+    Metadata,
+}
+
+/// Finds the library song an entry points to. When its location no longer exists the song is looked up
+/// by file name, then by title and artist with the closest duration.
+pub fn match_entry(db: &Db, entry: &PlaylistFileEntry, base_dir: &Path) -> Option<(SongView, EntryMatch)> {
+    let path = resolve_location(&entry.location, base_dir);
+    if let Some(path) = path.as_ref().filter(|path| path.is_file()) {
+        if let Ok(song) = db.get_song_view_by_path(&path.to_string_lossy()) {
+            return Some((song, EntryMatch::Path));
+        }
+    }
+
+    let file_name = path.as_ref().and_then(|path| path.file_name()).map(|name| name.to_string_lossy().to_string());
+    if let Some(file_name) = file_name {
+        //only trust the name when a single song carries it
+        if let Ok([song]) = db.find_songs_by_file_name(&file_name).as_deref() {
+            return Some((song.clone(), EntryMatch::FileName));
+        }
+    }
+
+    let title = entry.title.as_deref()?;
+    let same_artist = |song: &SongView| match &entry.artist {
+        Some(artist) => [song.display_artist(), song.artist.as_str()].iter().any(|name| name.eq_ignore_ascii_case(artist)),
+        None => true,
+    };
+    let same_album = |song: &SongView| entry.album.as_ref().is_none_or(|album| song.album.eq_ignore_ascii_case(album));
+    let duration_gap = |song: &SongView| entry.duration_seconds.map_or(0, |seconds| song.duration_seconds.abs_diff(seconds));
+    db.find_songs_by_title(title)
+        .unwrap_or_default()
+        .into_iter()
+        .filter(|song| same_artist(song) && duration_gap(song) <= DURATION_TOLERANCE_SECONDS)
+        //prefer the same album, but a track may since have moved to a compilation or a remaster
+        .min_by_key(|song| (!same_album(song), duration_gap(song)))
+        .map(|song| (song, EntryMatch::Metadata))
+}
+
+fn match_entries(db: &Db, playlist: &PlaylistFile, path: &Path) -> (Vec<(SongView, EntryMatch)>, Vec<PlaylistFileEntry>) {
+    let base_dir = path.parent().unwrap_or(Path::new("/"));
+    let mut songs = Vec::new();
+    let mut missing = Vec::new();
+    for entry in &playlist.entries {
+        match match_entry(db, entry, base_dir) {
+            Some(song) => songs.push(song),
+            None => missing.push(entry.clone()),
+        }
+    }
+    (songs, missing)
+}
+
+/// Songs of a playlist file found in the library, and the entries that were not.
+pub fn load_playlist_songs(db: &Db, path: &Path) -> Result<(PlaylistFile, Vec<SongView>, Vec<PlaylistFileEntry>)> {
+    let playlist = read_playlist_file(path)?;
+    let (songs, missing) = match_entries(db, &playlist, path);
+    Ok((playlist, songs.into_iter().map(|(song, _)| song).collect(), missing))
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct PlaylistImport {
+    pub playlist_id: i32,
+    pub name: String,
+    pub added: usize,
+    /// Entries whose location was gone but were found by file name or metadata.
+    pub relocated: usize,
+    pub missing: Vec<PlaylistFileEntry>,
+}
+
+/// Creates a playlist from the file, named after the title it carries or the file name.
+pub fn import_playlist_file(db: &Db, path: &Path) -> Result<PlaylistImport> {
+    let playlist = read_playlist_file(path)?;
+    let (songs, missing) = match_entries(db, &playlist, path);
+    let name = playlist
+        .name
+        .filter(|name| !name.is_empty())
+        .or_else(|| path.file_stem().map(|stem| stem.to_string_lossy().to_string()))
+        .ok_or_else(|| anyhow!("Invalid playlist path {}", path.display()))?;
+
+    let playlist_id = db.create_playlist(&name)?;
+    let song_ids: Vec<i32> = songs.iter().map(|(song, _)| song.id).collect();
+    db.add_songs_to_playlist(playlist_id, &song_ids)?;
+    Ok(PlaylistImport {
+        playlist_id,
+        name,
+        added: songs.len(),
+        relocated: songs.iter().filter(|(_, found)| *found != EntryMatch::Path).count(),
+        missing,
+    })
+}
+
+/// Writes the playlist in the format matching the extension of `path`,
+/// with song paths relative to its folder when `relative` is set.
+pub fn export_playlist(db: &Db, playlist_id: i32, path: &Path, relative: bool) -> Result<()> {
+    let format = PlaylistFormat::from_path(path).ok_or_else(|| anyhow!("Unknown playlist format, use .m3u8, .xspf or .pls"))?;
+    let (_, name) = db.get_playlist_data(playlist_id)?;
+    let songs = db.get_songs_in_playlist(playlist_id)?;
+    let base_dir = relative.then(|| path.parent()).flatten();
+    let contents = match format {
+        PlaylistFormat::M3u => write_m3u(&name, &songs, base_dir),
+        PlaylistFormat::Xspf => write_xspf(&name, &songs, base_dir),
+        PlaylistFormat::Pls => write_pls(&songs, base_dir),
+    };
+    std::fs::write(path, contents).with_context(|| format!("Failed to write {}", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn song(path: &str, title: &str, artist: &str, duration_seconds: u64) -> SongView {
+        SongView {
+            id: 1,
+            path: path.to_string(),
+            title: title.to_string(),
+            artist: artist.to_string(),
+            album: "Album".to_string(),
+            album_art_path: None,
+            track_number: None,
+            duration_seconds,
+            play_count: 0,
+            replay_gain: Default::default(),
+            album_id: 1,
+            tags: Default::default(),
+            format: Default::default(),
+            rating: Default::default(),
+        }
+    }
+
+    #[test]
+    fn m3u_extinf_attributes_and_unknown_duration() {
+        let playlist = parse_m3u(
+            "#EXTM3U\n#PLAYLIST:Road trip\n#EXTINF:215 tvg-id=\"a\" group-title=\"Rock, Pop\",Band - Song\n/music/a.mp3\n\n#EXTINF:-1,Radio\nhttp://radio.example/stream\nplain.mp3\n",
+        );
+        assert_eq!(playlist.name.as_deref(), Some("Road trip"));
+        assert_eq!(
+            playlist.entries,
+            vec![
+                PlaylistFileEntry {
+                    location: "/music/a.mp3".to_string(),
+                    title: Some("Song".to_string()),
+                    artist: Some("Band".to_string()),
+                    album: None,
+                    duration_seconds: Some(215),
+                },
+                PlaylistFileEntry {
+                    location: "http://radio.example/stream".to_string(),
+                    title: Some("Radio".to_string()),
+                    ..Default::default()
+                },
+                PlaylistFileEntry { location: "plain.mp3".to_string(), ..Default::default() },
+            ]
+        );
+    }
+
+    #[test]
+    fn text_drops_bom_and_falls_back_to_latin_1() {
+        assert_eq!(decode_text(b"\xEF\xBB\xBF#EXTM3U".to_vec()), "#EXTM3U");
+        assert_eq!(decode_text(b"Caf\xE9 - D\xE9j\xE0 vu".to_vec()), "Café - Déjà vu");
+    }
+
+    #[test]
+    fn file_urls_resolve_to_local_paths() {
+        let base_dir = Path::new("/home/me/Playlists");
+        assert_eq!(resolve_location("file:///music/My%20Band/a%23b.mp3", base_dir), Some(PathBuf::from("/music/My Band/a#b.mp3")));
+        assert_eq!(resolve_location("file://localhost/music/a.mp3", base_dir), Some(PathBuf::from("/music/a.mp3")));
+        assert_eq!(resolve_location("https://radio.example/stream", base_dir), None);
+    }
+
+    #[test]
+    fn relative_locations_fold_parent_dirs() {
+        let base_dir = Path::new("/home/me/Playlists");
+        assert_eq!(resolve_location("../Music/./Band/a.mp3", base_dir), Some(PathBuf::from("/home/me/Music/Band/a.mp3")));
+        assert_eq!(resolve_location("..\\Music\\b.mp3", base_dir), Some(PathBuf::from("/home/me/Music/b.mp3")));
+        assert_eq!(resolve_location("/music/x/../a.mp3", base_dir), Some(PathBuf::from("/music/a.mp3")));
+    }
+
+    #[test]
+    fn export_writes_paths_relative_to_the_playlist() {
+        let songs = [song("/home/me/Music/Band/a.mp3", "Song", "Band", 215)];
+        let relative = write_m3u("Mix", &songs, Some(Path::new("/home/me/Playlists")));
+        assert_eq!(relative, "#EXTM3U\n#PLAYLIST:Mix\n#EXTINF:215,Band - Song\n../Music/Band/a.mp3\n");
+        let absolute = write_m3u("Mix", &songs, None);
+        assert!(absolute.ends_with("\n/home/me/Music/Band/a.mp3\n"));
+
+        let base_dir = Path::new("/home/me/Playlists");
+        let parsed = parse_m3u(&relative);
+        assert_eq!(resolve_location(&parsed.entries[0].location, base_dir), Some(PathBuf::from(&songs[0].path)));
+    }
+
+    /// A library in a temporary folder, with its songs under `Music` and playlist files under `Playlists`.
+    struct Library {
+        dir: PathBuf,
+        db: Db,
+    }
+
+    impl Library {
+        fn new(name: &str) -> Library {
+            let dir = std::env::temp_dir().join(format!("round-playlist-file-{}-{}", std::process::id(), name));
+            let _ = std::fs::remove_dir_all(&dir);
+            std::fs::create_dir_all(dir.join("Music")).unwrap();
+            std::fs::create_dir_all(dir.join("Playlists")).unwrap();
+            let db = Db::open(dir.to_string_lossy().to_string());
+            Library { dir, db }
+        }
+
+        /// Writes `seconds` of silence to `Music/<file_name>` and adds it to the library.
+        fn add_song(&self, file_name: &str, seconds: u32) -> SongView {
+            let path = self.dir.join("Music").join(file_name);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            let data_len = seconds * 8000 * 2;
+            let mut wav = Vec::new();
+            wav.extend_from_slice(b"RIFF");
+            wav.extend_from_slice(&(36 + data_len).to_le_bytes());
+            wav.extend_from_slice(b"WAVEfmt ");
+            wav.extend_from_slice(&16u32.to_le_bytes());
+            wav.extend_from_slice(&[1, 0, 1, 0]); //PCM, mono
+            wav.extend_from_slice(&8000u32.to_le_bytes());
+            wav.extend_from_slice(&16000u32.to_le_bytes());
+            wav.extend_from_slice(&[2, 0, 16, 0]); //16 bit samples
+            wav.extend_from_slice(b"data");
+            wav.extend_from_slice(&data_len.to_le_bytes());
+            wav.resize(wav.len() + data_len as usize, 0);
+            std::fs::write(&path, wav).unwrap();
+            let song = self.db.add_song_by_path(&path.to_string_lossy()).unwrap();
+            song.to_song_view(&self.db).unwrap()
+        }
+
+        fn playlist_path(&self, file_name: &str) -> PathBuf {
+            self.dir.join("Playlists").join(file_name)
+        }
+    }
+
+    impl Drop for Library {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.dir);
+        }
+    }
+
+    #[test]
+    fn import_keeps_file_order_and_reports_missing_entries() {
+        let library = Library::new("import");
+        let first = library.add_song("first.wav", 2);
+        let second = library.add_song("second.wav", 3);
+        let path = library.playlist_path("road trip.m3u8");
+        std::fs::write(&path, "#EXTM3U\n../Music/second.wav\n#EXTINF:4,Band - Gone\n../Music/gone.wav\n../Music/first.wav\n").unwrap();
+
+        let import = import_playlist_file(&library.db, &path).unwrap();
+        assert_eq!(import.name, "road trip");
+        assert_eq!((import.added, import.relocated), (2, 0));
+        assert_eq!(import.missing.iter().map(PlaylistFileEntry::describe).collect::<Vec<_>>(), ["Band - Gone"]);
+        let songs = library.db.get_songs_in_playlist(import.playlist_id).unwrap();
+        assert_eq!(songs.iter().map(|song| song.id).collect::<Vec<_>>(), [second.id, first.id]);
+    }
+
+    #[test]
+    fn xspf_round_trip() {
+        let songs = [song("/music/Band/a b.mp3", "Song & Dance", "Band", 215), song("/music/Other/<c>.mp3", "Intro", "Other", 61)];
+        for base_dir in [None, Some(Path::new("/music/Playlists"))] {
+            let playlist = parse_xspf(&write_xspf("Mix & Match", &songs, base_dir)).unwrap();
+            assert_eq!(playlist.name.as_deref(), Some("Mix & Match"));
+            assert_eq!(playlist.entries.len(), songs.len());
+            for (entry, song) in playlist.entries.iter().zip(&songs) {
+                assert_eq!(resolve_location(&entry.location, Path::new("/music/Playlists")), Some(PathBuf::from(&song.path)));
+                assert_eq!(entry.title.as_deref(), Some(song.title.as_str()));
+                assert_eq!(entry.artist.as_deref(), Some(song.artist.as_str()));
+                assert_eq!(entry.album.as_deref(), Some(song.album.as_str()));
+                assert_eq!(entry.duration_seconds, Some(song.duration_seconds));
+            }
+        }
+    }
+
+    #[test]
+    fn pls_round_trip() {
+        let songs = [song("/music/Band/a b.mp3", "Song - Live", "Band", 215), song("/music/Other/c=d.mp3", "Intro", "Other", 61)];
+        for base_dir in [None, Some(Path::new("/music/Playlists"))] {
+            let playlist = parse_pls(&write_pls(&songs, base_dir));
+            assert_eq!(playlist.entries.len(), songs.len());
+            for (entry, song) in playlist.entries.iter().zip(&songs) {
+                assert_eq!(resolve_location(&entry.location, Path::new("/music/Playlists")), Some(PathBuf::from(&song.path)));
+                assert_eq!(entry.title.as_deref(), Some(song.title.as_str()));
+                assert_eq!(entry.artist.as_deref(), Some(song.artist.as_str()));
+                assert_eq!(entry.duration_seconds, Some(song.duration_seconds));
+            }
+        }
+    }
+
+    #[test]
+    fn xspf_cdata_and_percent_encoded_locations() {
+        let playlist = parse_xspf(
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+            <playlist version="1" xmlns="http://xspf.org/ns/0/">
+              <title><![CDATA[Rock & Roll]]></title>
+              <trackList>
+                <track>
+                  <location><![CDATA[file:///music/Guns%20N%27%20Roses/Patience.mp3]]></location>
+                  <location>file:///elsewhere/Patience.mp3</location>
+                  <title><![CDATA[Patience <Live>]]></title>
+                  <duration>355499</duration>
+                </track>
+                <track><location>Caf%C3%A9/D%C3%A9j%C3%A0%20vu.mp3</location></track>
+                <track><title>No location</title></track>
+              </trackList>
+            </playlist>"#,
+        )
+        .unwrap();
+        assert_eq!(playlist.name.as_deref(), Some("Rock & Roll"));
+        assert_eq!(playlist.entries.len(), 2);
+        let base_dir = Path::new("/music");
+        assert_eq!(resolve_location(&playlist.entries[0].location, base_dir), Some(PathBuf::from("/music/Guns N' Roses/Patience.mp3")));
+        assert_eq!(playlist.entries[0].title.as_deref(), Some("Patience <Live>"));
+        assert_eq!(playlist.entries[0].duration_seconds, Some(355));
+        assert_eq!(resolve_location(&playlist.entries[1].location, base_dir), Some(PathBuf::from("/music/Café/Déjà vu.mp3")));
+    }
+
+    #[test]
+    fn pls_orders_entries_by_number() {
+        let playlist = parse_pls("[playlist]\nTitle2=Band - Second\nFile2=second.mp3\nFile10=tenth.mp3\nfile1=first.mp3\nLength1=-1\nLength2=184\nTitle3=No file\nNumberOfEntries=3\nVersion=2\n");
+        let locations: Vec<&str> = playlist.entries.iter().map(|entry| entry.location.as_str()).collect();
+        assert_eq!(locations, ["first.mp3", "second.mp3", "tenth.mp3"]);
+        assert_eq!(playlist.entries[0].duration_seconds, None);
+        assert_eq!(
+            playlist.entries[1],
+            PlaylistFileEntry {
+                location: "second.mp3".to_string(),
+                title: Some("Second".to_string()),
+                artist: Some("Band".to_string()),
+                album: None,
+                duration_seconds: Some(184),
+            }
+        );
+    }
+
+    #[test]
+    fn entries_match_by_path_then_file_name_then_metadata() {
+        let library = Library::new("match");
+        let intro = library.add_song("intro.wav", 2);
+        let outro = library.add_song("outro.wav", 5);
+        library.add_song("Live/encore.wav", 4);
+        library.add_song("Studio/encore.wav", 4);
+        let base_dir = library.dir.join("Playlists");
+        let matched = |location: &str, title: Option<&str>, duration_seconds: Option<u64>| {
+            let entry = PlaylistFileEntry {
+                location: location.to_string(),
+                title: title.map(str::to_string),
+                artist: title.map(|_| "Unknown Artist".to_string()),
+                album: None,
+                duration_seconds,
+            };
+            match_entry(&library.db, &entry, &base_dir).map(|(song, found)| (song.id, found))
+        };
+
+        assert_eq!(matched("../Music/intro.wav", Some("outro.wav"), None), Some((intro.id, EntryMatch::Path)));
+        assert_eq!(matched("/old/library/outro.wav", Some("intro.wav"), None), Some((outro.id, EntryMatch::FileName)));
+        assert_eq!(matched("/old/library/renamed.wav", Some("intro.wav"), Some(3)), Some((intro.id, EntryMatch::Metadata)));
+        //too far from the song's duration
+        assert_eq!(matched("/old/library/renamed.wav", Some("intro.wav"), Some(9)), None);
+        //two songs carry the name, and there is nothing else to go on
+        assert_eq!(matched("/old/library/encore.wav", None, None), None);
+    }
+}
@@ -0,0 +1,59 @@
+use dioxus::prelude::*;
+
+use crate::playlist_file::PlaylistImport;
+
+/// Summary of an imported playlist file, listing the entries that matched no library song.
+#[component]
+pub fn playlist_import_dialog(import: Signal<Option<PlaylistImport>>) -> Element {
+    let Some(summary) = import() else {
+        return rsx! {};
+    };
+
+    rsx! {
+        div {
+            class: "context-menu-overlay",
+            onclick: move |_| import.set(None),
+
+            div {
+                class: "context-menu settings-dialog",
+                onclick: move |evt: Event<MouseData>| evt.stop_propagation(),
+
+                h3 { class: "dialog-title", "IMPORTED {summary.name}" }
+                div { class: "settings-section",
+                    div { class: "settings-row",
+                        span { class: "song-info-label", "Added" }
+                        span { "{summary.added} songs" }
+                    }
+                    if summary.relocated > 0 {
+                        div { class: "settings-row",
+                            span { class: "song-info-label", "Relocated" }
+                            span { "{summary.relocated} moved songs matched by name or tags" }
+                        }
+                    }
+                }
+                if !summary.missing.is_empty() {
+                    div { class: "settings-section",
+                        h4 { "Not found ({summary.missing.len()})" }
+                        div { class: "import-missing-list",
+                            for (index, entry) in summary.missing.iter().enumerate() {
+                                div { class: "import-missing-entry",
+                                    key: "{index}",
+                                    title: "{entry.location}",
+                                    "{entry.describe()}"
+                                }
+                            }
+                        }
+                    }
+                }
+                div {
+                    class: "common-button",
+                    style: "display: flex; gap: 10px; margin-top: 10px;",
+                    button {
+                        onclick: move |_| import.set(None),
+                        "Close"
+                    }
+                }
+            }
+        }
+    }
+}
@@ -2,17 +2,28 @@ use dioxus::prelude::*;
 
 
 
-use crate::{app_context::PlayerContext, player::scrolling_text};
+use crate::{app_context::{PlaybackMode, PlayerContext}, player::scrolling_text, queue_panel::queue_panel};
 
 #[component]
 pub fn queue_bar() -> Element {
     let player_ctx = use_context::<PlayerContext>();
+    let mut show_panel = use_signal(|| false);
 
+    //shuffle ahead of time so the order shows up here before the first shuffled song plays
+    let mut queue = player_ctx.queue;
+    let mode = player_ctx.mode;
+    let shuffle_strategy = player_ctx.shuffle_strategy;
+    use_effect(move || {
+        if mode() == PlaybackMode::Shuffle {
+            let _ = (shuffle_strategy(), queue.peek().current_fallback_queue.read());
+            queue.write().prepare_shuffle();
+        }
+    });
     let player_context = player_ctx.clone();
     let queue_update = use_effect( move || {
         let player_ctx = player_ctx.clone();
         if player_ctx.clone().is_finished() {
-            player_ctx.clone().queue.write().next_song();
+            player_ctx.clone().queue.write().next_song(false);
         }
     });
     let queue_items = use_memo( move || {
@@ -23,7 +34,13 @@ pub fn queue_bar() -> Element {
     });
 
     rsx! {
+        queue_panel { show: show_panel }
         div { class: "queue-bar",
+            button { class: if show_panel() { "queue-toggle active" } else { "queue-toggle" },
+                title: "Show the whole queue",
+                onclick: move |_| show_panel.toggle(),
+                "☰"
+            }
             h3 { class: "up-next-label",
                 "up next:"
             },
@@ -0,0 +1,154 @@
+use dioxus::prelude::*;
+
+use crate::{
+    app_context::{DatabaseContext, PlayerContext},
+    context_menu::ContextMenuItem,
+    create_playlist_dialog::create_playlist_dialog,
+    file_browser::{FileEntry, song_file},
+};
+
+const HISTORY_LISTED: usize = 50;
+//a shuffled library would be thousands of rows
+const UPCOMING_LISTED: usize = 200;
+
+/// The whole queue: history, the current song, the play next queue and what the fallback plays after it.
+#[component]
+pub fn queue_panel(show: Signal<bool>) -> Element {
+    let db = use_context::<DatabaseContext>();
+    let player_context = use_context::<PlayerContext>();
+    let mut queue = player_context.queue;
+    let playing_state = player_context.playing_state;
+    let mut dragged = use_signal(|| None::<usize>);
+    let mut drop_target = use_signal(|| None::<usize>);
+    let mut show_create_dialog = use_signal(|| false);
+
+    //oldest first, so the list reads down towards the current song
+    let history = use_memo(move || {
+        let queue = queue.read();
+        let last_played = queue.last_played.read();
+        let skipped = last_played.len().saturating_sub(HISTORY_LISTED);
+        last_played.iter().cloned().enumerate().skip(skipped).collect::<Vec<_>>()
+    });
+    let current = use_memo(move || playing_state.read().current_song());
+    let queued = use_memo(move || queue.read().queued_songs());
+    let upcoming = use_memo(move || queue.read().upcoming_songs(UPCOMING_LISTED));
+
+    if !show() {
+        return rsx! {};
+    }
+
+    rsx! {
+        div { class: "queue-panel",
+            div { class: "queue-panel-header",
+                h3 { "Queue" }
+                div { class: "common-button",
+                    button {
+                        onclick: move |_| queue.write().clear_queue(),
+                        "Clear"
+                    }
+                    button {
+                        onclick: move |_| show_create_dialog.set(true),
+                        "Save as playlist"
+                    }
+                    button {
+                        onclick: move |_| show.set(false),
+                        "Close"
+                    }
+                }
+            }
+
+            if !history().is_empty() {
+                h4 { class: "queue-section-label", "History" }
+                div { class: "item-list queue-history",
+                    for (idx, song) in history() {
+                        song_file {
+                            key: "history-{idx}",
+                            file: FileEntry::from_song_view(&song),
+                            on_click: move |_path| queue.write().play_from_history(idx),
+                        }
+                    }
+                }
+            }
+
+            if let Some(song) = current() {
+                h4 { class: "queue-section-label", "Now playing" }
+                div { class: "item-list queue-current",
+                    song_file {
+                        file: FileEntry::from_song_view(&song),
+                        on_click: move |_path| {},
+                    }
+                }
+            }
+
+            if !queued().is_empty() {
+                h4 { class: "queue-section-label", "Playing next" }
+                div { class: "item-list",
+                    for (idx, song) in queued().into_iter().enumerate() {
+                        {
+                            let remove_item = ContextMenuItem {
+                                title: "Remove from queue".to_string(),
+                                action: EventHandler::new(move |_| queue.write().remove_queued(idx)),
+                            };
+                            rsx! {
+                                div {
+                                    key: "queued-{idx}-{song.id}",
+                                    class: match (dragged(), drop_target()) {
+                                        (Some(from), Some(target)) if target == idx && from < idx => "playlist-entry drop-below",
+                                        (Some(from), Some(target)) if target == idx && from > idx => "playlist-entry drop-above",
+                                        _ => "playlist-entry",
+                                    },
+                                    draggable: true,
+                                    ondragstart: move |_| dragged.set(Some(idx)),
+                                    ondragover: move |evt: Event<DragData>| {
+                                        evt.prevent_default();
+                                        drop_target.set(Some(idx));
+                                    },
+                                    ondragend: move |_| {
+                                        dragged.set(None);
+                                        drop_target.set(None);
+                                    },
+                                    ondrop: move |evt: Event<DragData>| {
+                                        evt.prevent_default();
+                                        if let Some(from) = dragged.take() {
+                                            queue.write().move_queued(from, idx);
+                                        }
+                                        drop_target.set(None);
+                                    },
+                                    song_file {
+                                        file: FileEntry::from_song_view(&song),
+                                        menu_items: vec![remove_item],
+                                        on_click: move |_path| queue.write().play_queued(idx),
+                                    }
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+
+            if !upcoming().is_empty() {
+                h4 { class: "queue-section-label", "Up next" }
+                div { class: "item-list",
+                    for (idx, song) in upcoming().into_iter().enumerate() {
+                        song_file {
+                            key: "upcoming-{idx}-{song.id}",
+                            file: FileEntry::from_song_view(&song),
+                            on_click: move |_path| queue.write().play_upcoming(idx),
+                        }
+                    }
+                }
+            }
+        }
+
+        create_playlist_dialog {
+            show: show_create_dialog,
+            on_created: move |playlist_id| {
+                //what the panel lists, not the whole shuffled library behind it
+                let song_ids: Vec<i32> = queue.peek().queue_songs(UPCOMING_LISTED).iter().map(|song| song.id).collect();
+                if let Err(e) = db.get().add_songs_to_playlist(playlist_id, &song_ids) {
+                    eprintln!("Failed to add songs to playlist: {:?}", e);
+                }
+            }
+        }
+    }
+}
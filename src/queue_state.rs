@@ -1,46 +1,110 @@
-use std::collections::VecDeque;
+use std::{collections::VecDeque, path::Path, sync::Arc};
 
 use dioxus::prelude::*;
-use rand::{random, rng, seq::SliceRandom};
+use rand::random;
 use tokio::sync::mpsc::Sender;
 
 use anyhow::{anyhow, Result};
 
-use crate::{app_context::PlaybackMode, audio_controller_command::AudioControllerCommand, db::{Db, SongView}, file_browser::{ScanResult, SongFileData, scan_dir}, player_playing_state::PlayerPlayingState, playlist::{self}};
+use crate::{app_context::{PlaybackMode, RepeatMode}, audio_controller_command::AudioControllerCommand, db::{Db, SongView}, file_browser::{ScanResult, SongFileData, scan_dir}, player_playing_state::PlayerPlayingState, playlist::{self, Playlist}, playlist_file::load_playlist_songs, session::{Session, SessionFallback}, shuffle::{ShuffleOrder, ShuffleStrategy}};
 
 const HISTORY_MAX_SIZE: usize = 9999;
+const UPCOMING_SHOWN: usize = 20;
 
 pub enum QueueFallbackMode {
     Playlist {playlist: playlist::Playlist},
     Folder {path: String, current_item: usize, entries: ScanResult},
+    Album {album_id: i32, current_item: usize, songs: Vec<SongView>},
+    PlaylistFile {path: String, current_item: usize, songs: Vec<SongView>},
     None
 }
 
 impl QueueFallbackMode {
-    pub fn next(&mut self) -> Option<SongView> {
+    /// Moves on to the following song, starting over from the first one when `wrap` is set.
+    pub fn next(&mut self, wrap: bool) -> Option<SongView> {
         match self {
             QueueFallbackMode::Playlist { playlist } => {
-                playlist.next_song()
+                playlist.next_song(wrap)
             },
             QueueFallbackMode::Folder { path: _, current_item, entries } => {
-                if *current_item + 1 < entries.entries.len() {
+                //subfolders are listed along with the songs, skip over them
+                let song_at = |index: usize| match &entries.entries[index].song_data {
+                    SongFileData::Song { song_view } => Some(song_view.clone()),
+                    SongFileData::NotIndexed {} | SongFileData::NotSong {} => None,
+                };
+                let after = (*current_item + 1..entries.entries.len()).find_map(|index| song_at(index).map(|song| (index, song)));
+                let (index, song) = match after {
+                    Some(next) => next,
+                    None if wrap => (0..entries.entries.len()).find_map(|index| song_at(index).map(|song| (index, song)))?,
+                    None => return None,
+                };
+                *current_item = index;
+                Some(song)
+            },
+            QueueFallbackMode::Album { album_id: _, current_item, songs } |
+            QueueFallbackMode::PlaylistFile { path: _, current_item, songs } => {
+                if *current_item + 1 < songs.len() {
                     *current_item += 1;
-                    let file_data = &entries.entries[*current_item].song_data;
-                    if let SongFileData::Song { song_view } = file_data {
-                        Some(song_view.clone())
-                    } else {
-                        None
-                    }
+                } else if wrap && !songs.is_empty() {
+                    *current_item = 0;
                 } else {
-                    None
+                    return None;
                 }
+                Some(songs[*current_item].clone())
             },
             QueueFallbackMode::None => None,
         }
     }
 
-    pub fn next_shuffle(&mut self, history: &VecDeque<SongView>) -> Option<SongView> {
-        let songs = match self {
+    pub fn current_item(&self) -> usize {
+        match self {
+            QueueFallbackMode::Playlist { playlist } => playlist.current_index(),
+            QueueFallbackMode::Folder { path: _, current_item, entries: _ } |
+            QueueFallbackMode::Album { album_id: _, current_item, songs: _ } |
+            QueueFallbackMode::PlaylistFile { path: _, current_item, songs: _ } => *current_item,
+            QueueFallbackMode::None => 0,
+        }
+    }
+
+    /// Goes back to `index`, undoing a `next` whose song did not get played.
+    pub fn set_current_item(&mut self, index: usize) {
+        match self {
+            QueueFallbackMode::Playlist { playlist } => {
+                playlist.set_current(index);
+            },
+            QueueFallbackMode::Folder { path: _, current_item, entries: _ } |
+            QueueFallbackMode::Album { album_id: _, current_item, songs: _ } |
+            QueueFallbackMode::PlaylistFile { path: _, current_item, songs: _ } => {
+                *current_item = index;
+            },
+            QueueFallbackMode::None => {},
+        }
+    }
+
+    /// The songs after the current one, as far as `limit`, without wrapping around.
+    pub fn upcoming(&self, limit: usize) -> Vec<SongView> {
+        let after = self.current_item() + 1;
+        match self {
+            QueueFallbackMode::Playlist { playlist } => {
+                playlist.songs().into_iter().skip(after).take(limit).collect()
+            },
+            QueueFallbackMode::Folder { path: _, current_item: _, entries } => {
+                entries.entries.iter().skip(after).filter_map(|entry| match &entry.song_data {
+                    SongFileData::Song { song_view } => Some(song_view.clone()),
+                    SongFileData::NotIndexed {} | SongFileData::NotSong {} => None,
+                }).take(limit).collect()
+            },
+            QueueFallbackMode::Album { album_id: _, current_item: _, songs } |
+            QueueFallbackMode::PlaylistFile { path: _, current_item: _, songs } => {
+                songs.iter().skip(after).take(limit).cloned().collect()
+            },
+            QueueFallbackMode::None => Vec::new(),
+        }
+    }
+
+    /// Every song the fallback plays, in its own order.
+    pub fn songs(&self) -> Vec<SongView> {
+        match self {
             QueueFallbackMode::Playlist { playlist } => {
                 playlist.songs()
             },
@@ -53,107 +117,298 @@ impl QueueFallbackMode {
                     }
                 }).collect()
             },
+            QueueFallbackMode::Album { album_id: _, current_item: _, songs } |
+            QueueFallbackMode::PlaylistFile { path: _, current_item: _, songs } => songs.clone(),
             QueueFallbackMode::None => Vec::new(),
-        };
-        if songs.is_empty() {
-            return None;
-        } 
-        let mut contender = None;
-        let mut max_appeared: usize = 0;
-        let max_checking = songs.len() * 90 / 100;
-
-        let mut songs = songs.clone();
-        songs.shuffle(&mut rng()); 
-        for song in songs.iter() {
-            let mut last_appeared: usize = 0;
-            if !history.iter().any(|s| s.id == song.id) {
-                contender = Some(song.clone());
-                break;
-            }
-            for played_song in history.iter().rev() {
-                if played_song.id == song.id {
-                    last_appeared += 1;
-                }
-                if max_appeared < last_appeared {
-                    max_appeared = last_appeared;
-                    contender = Some(song.clone());
-                }
-            }
-            if last_appeared >= max_checking || 
-            (history.len() < max_checking) {
-                break;
-            }
         }
-        contender
     }
 }
 
+/// When playback stops on its own, besides running out of songs. The next song stays cued, paused.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum StopAfter {
+    #[default]
+    Never,
+    CurrentTrack,
+    Queue,
+}
+
+impl StopAfter {
+    pub fn cycle(&self) -> StopAfter {
+        match self {
+            StopAfter::Never => StopAfter::CurrentTrack,
+            StopAfter::CurrentTrack => StopAfter::Queue,
+            StopAfter::Queue => StopAfter::Never,
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            StopAfter::Never => "Keep playing",
+            StopAfter::CurrentTrack => "Stop after current track",
+            StopAfter::Queue => "Stop after queue",
+        }
+    }
+}
+
+/// Playback settings the queue follows, the same signals the player controls change.
+#[derive(Clone, Copy)]
+pub struct QueueSettings {
+    pub mode: Signal<PlaybackMode>,
+    pub repeat: Signal<RepeatMode>,
+    pub stop_after: Signal<StopAfter>,
+    pub shuffle_strategy: Signal<ShuffleStrategy>,
+}
+
+enum PreloadOrigin {
+    PlayNext,
+    Fallback { previous_item: usize },
+    Shuffle,
+    Repeat,
+}
+
+struct PreloadedSong {
+    song: SongView,
+    origin: PreloadOrigin,
+}
+
 pub struct QueueState {
     pub play_next_queue: Signal<VecDeque<SongView>>,
     pub current_fallback_queue: Signal<QueueFallbackMode>,
     pub last_played: Signal<VecDeque<SongView>>,
     pub playing_state: Signal<PlayerPlayingState>,
-    pub mode: Signal<PlaybackMode>,
+    pub settings: QueueSettings,
+    shuffle: Option<ShuffleOrder>,
+    preloaded: Option<PreloadedSong>,
     command_sender: Sender<AudioControllerCommand> 
 }
 
 impl QueueState {
-    pub fn new(playing_state: Signal<PlayerPlayingState>, command_sender: Sender<AudioControllerCommand>, mode: Signal<PlaybackMode>) -> Self {
-        //todo need to load from db here
-        QueueState {
-            play_next_queue: Signal::new(VecDeque::new()),
-            current_fallback_queue: Signal::new(QueueFallbackMode::None),
-            last_played: Signal::new(VecDeque::new()),
+    /// Restores the queue from the saved session, cueing the song that was playing paused at its position.
+    pub fn new(mut playing_state: Signal<PlayerPlayingState>, command_sender: Sender<AudioControllerCommand>, settings: QueueSettings, session: &Session, db: &Arc<Db>) -> Self {
+        let song = |id: &i32| db.get_song_view(*id).ok().flatten();
+        let fallback = match &session.fallback {
+            SessionFallback::Playlist { playlist_id, index } => match Playlist::get_playlist_handle(*playlist_id, db.clone()) {
+                Ok(mut playlist) => {
+                    playlist.set_current(*index);
+                    QueueFallbackMode::Playlist { playlist }
+                },
+                Err(_) => QueueFallbackMode::None,
+            },
+            SessionFallback::Folder { path, item } => QueueFallbackMode::Folder {
+                path: path.clone(),
+                current_item: *item,
+                entries: scan_dir(path, db),
+            },
+            SessionFallback::Album { album_id, item } => QueueFallbackMode::Album {
+                album_id: *album_id,
+                current_item: *item,
+                songs: db.get_album_songs(*album_id).unwrap_or_default(),
+            },
+            SessionFallback::PlaylistFile { path, item } => match load_playlist_songs(db, Path::new(path)) {
+                Ok((_, songs, _)) => QueueFallbackMode::PlaylistFile { path: path.clone(), current_item: *item, songs },
+                Err(_) => QueueFallbackMode::None,
+            },
+            SessionFallback::None => QueueFallbackMode::None,
+        };
+        let queue = QueueState {
+            play_next_queue: Signal::new(session.play_next.iter().filter_map(song).collect()),
+            current_fallback_queue: Signal::new(fallback),
+            last_played: Signal::new(session.history.iter().filter_map(song).collect()),
             playing_state,
-            mode,
+            settings,
+            shuffle: None,
+            preloaded: None,
             command_sender
+        };
+        if let Some(current) = session.current_song.as_ref().and_then(song) {
+            if std::fs::File::open(&current.path).is_ok() {
+                queue.send_cmd(AudioControllerCommand::LoadPaused(current.clone(), session.position_ms));
+                playing_state.set(PlayerPlayingState::Paused { song: current, progress_ms: session.position_ms });
+            }
         }
+        queue
     }
 
     pub fn get_names(&self) -> Vec<String> {
-        let play_next = self.play_next_queue.clone();
-        let fallback = self.current_fallback_queue.clone(); 
-        play_next.cloned().iter()
-            .map(|item| item.title.clone()).collect()
+        self.queued_songs().into_iter()
+            .chain(self.upcoming_songs(UPCOMING_SHOWN))
+            .map(|song| song.title)
+            .collect()
     }
 
-    pub fn next_song(&mut self) {
-        loop {
-            let next_opt = if *self.mode.clone().read() == PlaybackMode::Shuffle {
-            if !self.play_next_queue.read().is_empty() {
-                let rand = random::<u32>();
-                let index = rand as usize % self.play_next_queue.read().len();
-                Some(self.play_next_queue.write().remove(index).unwrap())
-            } else {
-                self.current_fallback_queue.write().next_shuffle(&self.last_played.read())
+    /// The play next queue, counting a song from it that is already preloaded.
+    /// Indices match the ones `move_queued`, `remove_queued` and `play_queued` take.
+    pub fn queued_songs(&self) -> Vec<SongView> {
+        let mut songs: Vec<SongView> = self.preloaded.iter()
+            .filter(|next| matches!(next.origin, PreloadOrigin::PlayNext))
+            .map(|next| next.song.clone())
+            .collect();
+        songs.extend(self.play_next_queue.read().iter().cloned());
+        songs
+    }
+
+    /// Songs the shuffle or the fallback plays once the play next queue is empty, as far as `limit`.
+    /// Indices match the ones `play_upcoming` takes.
+    pub fn upcoming_songs(&self, limit: usize) -> Vec<SongView> {
+        let mut songs: Vec<SongView> = self.preloaded.iter()
+            .filter(|next| matches!(next.origin, PreloadOrigin::Fallback { .. } | PreloadOrigin::Shuffle))
+            .map(|next| next.song.clone())
+            .take(limit)
+            .collect();
+        let rest = limit - songs.len();
+        if *self.settings.mode.read() == PlaybackMode::Shuffle {
+            songs.extend(self.upcoming_shuffle().iter().take(rest).cloned());
+        } else {
+            songs.extend(self.current_fallback_queue.read().upcoming(rest));
+        }
+        songs
+    }
+
+    /// The current song, the play next queue and as far as `upcoming_limit` of the songs after them,
+    /// as they would play without repeating.
+    pub fn queue_songs(&self, upcoming_limit: usize) -> Vec<SongView> {
+        let mut songs: Vec<SongView> = self.playing_state.read().current_song().into_iter().collect();
+        songs.extend(self.queued_songs());
+        songs.extend(self.upcoming_songs(upcoming_limit));
+        songs
+    }
+
+    fn pick_next(&mut self, manual: bool) -> Option<PreloadedSong> {
+        let repeat = *self.settings.repeat.read();
+        if repeat == RepeatMode::One && !manual {
+            if let Some(current_song) = self.playing_state.read().current_song() {
+                return Some(PreloadedSong { song: current_song, origin: PreloadOrigin::Repeat });
             }
-            } else if *self.mode.clone().read() == PlaybackMode::Loop{
-                if let Some(current_song) = self.playing_state.read().current_song() {
-                    Some(current_song)
-                } else if let Some(next_song) = self.play_next_queue.write().pop_front() {
-                    Some(next_song)
-                } else {
-                    self.current_fallback_queue.write().next()
-                }
-            } else if let Some(next_song) = self.play_next_queue.write().pop_front() {
-                Some(next_song)
-            } else {
-                self.current_fallback_queue.write().next()
-            };
+        }
+        //songs queued by hand keep their order, even in shuffle
+        let queued = self.play_next_queue.write().pop_front();
+        if let Some(next_song) = queued {
+            return Some(PreloadedSong { song: next_song, origin: PreloadOrigin::PlayNext });
+        }
+        let wrap = repeat == RepeatMode::All;
+        if *self.settings.mode.read() == PlaybackMode::Shuffle {
+            self.prepare_shuffle();
+            self.shuffle.as_mut().and_then(|order| order.next(wrap))
+                .map(|song| PreloadedSong { song, origin: PreloadOrigin::Shuffle })
+        } else {
+            let previous_item = self.current_fallback_queue.peek().current_item();
+            self.current_fallback_queue.write().next(wrap)
+                .map(|song| PreloadedSong { song, origin: PreloadOrigin::Fallback { previous_item } })
+        }
+    }
+
+    /// Whether `stop_after` ends playback instead of moving on to the next song.
+    fn stops_before_next(&self) -> bool {
+        match *self.settings.stop_after.peek() {
+            StopAfter::Never => false,
+            StopAfter::CurrentTrack => true,
+            StopAfter::Queue => self.play_next_queue.peek().is_empty(),
+        }
+    }
+
+    /// Called when `stop_after` changes, so the sink doesn't run on into a song it shouldn't play.
+    pub fn discard_preloaded_past_stop(&mut self) {
+        if self.stops_before_next() {
+            self.discard_preloaded();
+        }
+    }
+
+    /// Shuffles the fallback songs, unless the current order was made from the same songs and strategy.
+    pub fn prepare_shuffle(&mut self) {
+        let songs = self.current_fallback_queue.peek().songs();
+        if songs.is_empty() {
+            self.shuffle = None;
+            return;
+        }
+        let strategy = *self.settings.shuffle_strategy.peek();
+        let current = self.playing_state.peek().current_song().map(|song| song.id);
+        if self.shuffle.as_ref().is_some_and(|order| order.is_for(&songs, strategy) && !order.is_over(current)) {
+            return;
+        }
+        self.shuffle = Some(ShuffleOrder::new(songs, strategy, random(), current));
+    }
+
+    /// Songs the shuffle will play once the play next queue is empty.
+    pub fn upcoming_shuffle(&self) -> &[SongView] {
+        self.shuffle.as_ref().map(|order| order.upcoming()).unwrap_or_default()
+    }
+
+    /// Moves on to the next song. `manual` when the user skips, which leaves a song repeating on its own.
+    pub fn next_song(&mut self, manual: bool) {
+        //a skip isn't the current track finishing, so it plays on and keeps the stop setting
+        let stop = !manual && self.stops_before_next();
+        if stop {
+            self.settings.stop_after.set(StopAfter::Never);
+        }
+        loop {
+            let preloaded = self.preloaded.take().filter(|next| !(manual && matches!(next.origin, PreloadOrigin::Repeat)));
+            let next_opt = preloaded.or_else(|| self.pick_next(manual));
             if let Some(song) = self.playing_state.read().current_song() {
                 self.last_played.write().push_back(song.clone());
             }
-            if next_opt.is_none() {
+            let Some(next) = next_opt else {
                 eprintln!("Queue empty");
                 self.send_cmd(AudioControllerCommand::Stop);
                 *self.playing_state.write() = PlayerPlayingState::NoSongSelected;
                 return;
-            }
-            if self.play_song_instant(&next_opt.unwrap()).is_ok() { return }
+            };
+            let result = if stop { self.cue_song(&next.song) } else { self.play_song_instant(&next.song) };
+            //repeating a song that fails to open would try it forever
+            if result.is_ok() || matches!(next.origin, PreloadOrigin::Repeat) { return }
         } 
     }
 
+    /// Picks the upcoming song ahead of time, so the audio controller can append it to the sink
+    /// before the current one ends. Returns `None` when a song is already preloaded or nothing is left.
+    pub fn preload_next(&mut self) -> Option<SongView> {
+        if self.preloaded.is_some() || self.stops_before_next() {
+            return None;
+        }
+        let next = self.pick_next(false)?;
+        let song = next.song.clone();
+        self.preloaded = Some(next);
+        Some(song)
+    }
+
+    pub fn is_preloaded(&self, song: &SongView) -> bool {
+        self.preloaded.as_ref().is_some_and(|next| next.song.id == song.id)
+    }
+
+    /// Forgets the preloaded song without giving it back, used when it could not be decoded.
+    pub fn skip_preloaded(&mut self) {
+        self.preloaded = None;
+    }
+
+    /// Called once the sink has moved on to the preloaded song.
+    pub fn advance_to_preloaded(&mut self) {
+        if let Some(next) = self.preloaded.take() {
+            if let Some(song) = self.playing_state.read().current_song() {
+                self.last_played.write().push_back(song.clone());
+            }
+            *self.playing_state.write() = PlayerPlayingState::Playing { song: next.song, progress_ms: 0 };
+        }
+    }
+
+    /// Drops the preloaded song from the sink, giving it back to the play next queue if it came from there.
+    pub fn discard_preloaded(&mut self) {
+        if let Some(next) = self.preloaded.take() {
+            self.send_cmd(AudioControllerCommand::CancelPreloaded);
+            match next.origin {
+                PreloadOrigin::PlayNext => self.play_next_queue.write().push_front(next.song),
+                PreloadOrigin::Fallback { previous_item } => self.current_fallback_queue.write().set_current_item(previous_item),
+                PreloadOrigin::Shuffle => {
+                    if let Some(order) = self.shuffle.as_mut() {
+                        order.step_back();
+                    }
+                },
+                PreloadOrigin::Repeat => {},
+            }
+        }
+    }
+
     pub fn previous_song(&mut self) {
+        self.discard_preloaded();
         let prev_opt = self.last_played.write().pop_back();
         if let Some(song) = self.playing_state.read().current_song() {
             self.play_next_queue.write().push_front(song.clone());
@@ -173,69 +428,175 @@ impl QueueState {
             eprintln!("Failed to open song file: {}", song.path);
             return Err(anyhow!("Failed to open song file: {}", song.path));
         }
-        self.send_cmd(AudioControllerCommand::Load(song.path.clone()));
+        self.discard_preloaded();
+        self.send_cmd(AudioControllerCommand::Load(song.clone()));
         self.send_cmd(AudioControllerCommand::Play);    
         *self.playing_state.write() = PlayerPlayingState::Playing { song: song.clone(), progress_ms: 0};    
         Ok(())
     }
 
+    /// Loads the song paused at its start, for when playback stops before it.
+    fn cue_song(&mut self, song: &SongView) -> Result<()> {
+        if std::fs::File::open(&song.path).is_err() {
+            eprintln!("Failed to open song file: {}", song.path);
+            return Err(anyhow!("Failed to open song file: {}", song.path));
+        }
+        self.discard_preloaded();
+        self.send_cmd(AudioControllerCommand::LoadPaused(song.clone(), 0));
+        *self.playing_state.write() = PlayerPlayingState::Paused { song: song.clone(), progress_ms: 0 };
+        Ok(())
+    }
+
     pub fn play_song_next(&mut self, song: &SongView) {
         self.play_next_queue.write().push_back(song.clone());
         if !self.playing_state.read().is_playing() {
-            self.next_song();
+            self.next_song(true);
         }
     }
 
     pub fn add_song_to_queue(&mut self, song: &SongView) {
         self.play_next_queue.write().push_back(song.clone());
         if !self.playing_state.read().is_playing() {
-            self.next_song();
+            self.next_song(true);
+        }
+    }
+
+    pub fn move_queued(&mut self, from: usize, to: usize) {
+        self.discard_preloaded();
+        let mut queue = self.play_next_queue.write();
+        if from == to || from >= queue.len() || to >= queue.len() {
+            return;
+        }
+        if let Some(song) = queue.remove(from) {
+            queue.insert(to, song);
         }
     }
 
+    pub fn remove_queued(&mut self, index: usize) {
+        self.discard_preloaded();
+        self.play_next_queue.write().remove(index);
+    }
+
+    /// Plays the queued song at `index` right away, dropping the ones queued before it.
+    pub fn play_queued(&mut self, index: usize) {
+        self.discard_preloaded();
+        let song = {
+            let mut queue = self.play_next_queue.write();
+            let skipped = index.min(queue.len());
+            queue.drain(..skipped);
+            queue.pop_front()
+        };
+        if let Some(song) = song {
+            self.skip_to(&song);
+        }
+    }
+
+    /// Jumps ahead in the shuffle or the fallback to the upcoming song at `index`, the play next queue stays.
+    pub fn play_upcoming(&mut self, index: usize) {
+        self.discard_preloaded();
+        let shuffled = *self.settings.mode.peek() == PlaybackMode::Shuffle;
+        if shuffled {
+            self.prepare_shuffle();
+        }
+        let mut song = None;
+        for _ in 0..=index {
+            song = if shuffled {
+                self.shuffle.as_mut().and_then(|order| order.next(false))
+            } else {
+                self.current_fallback_queue.write().next(false)
+            };
+            if song.is_none() {
+                break;
+            }
+        }
+        if let Some(song) = song {
+            self.skip_to(&song);
+        }
+    }
+
+    /// Plays a song from the history again, the history itself is kept.
+    pub fn play_from_history(&mut self, index: usize) {
+        let song = self.last_played.peek().get(index).cloned();
+        if let Some(song) = song {
+            self.skip_to(&song);
+        }
+    }
+
+    /// Plays `song` instead of the current one, which goes to the history like it would when it ends.
+    fn skip_to(&mut self, song: &SongView) {
+        let current = self.playing_state.peek().current_song();
+        if let Some(current) = current {
+            self.last_played.write().push_back(current);
+        }
+        let _ = self.play_song_instant(song);
+    }
+
     pub fn clear_queue(&mut self) {
+        self.discard_preloaded();
         self.play_next_queue.write().clear();
         *self.current_fallback_queue.write() = QueueFallbackMode::None;
     }
 
-    pub fn play_folder_next(&mut self, path: &str, db: &Db) -> bool {
-        let files = scan_dir(path, db);
-        let mut added_any = false;
-        for file in files.entries.into_iter().rev() {
-            if let SongFileData::Song { song_view } = file.song_data {
-                self.play_next_queue.write().push_front(song_view);
-                added_any = true;
-            }
+    pub fn play_songs_next(&mut self, songs: Vec<SongView>) -> bool {
+        self.discard_preloaded();
+        let added_any = !songs.is_empty();
+        for song in songs.into_iter().rev() {
+            self.play_next_queue.write().push_front(song);
         }
         added_any
     }
 
-
-    pub fn play_folder_now(&mut self, path: &str, db: &Db) -> bool {
-        let added_any = self.play_folder_next(path, db);
+    pub fn play_songs_now(&mut self, songs: Vec<SongView>) -> bool {
+        let added_any = self.play_songs_next(songs);
         if added_any {
-            self.next_song();
+            self.next_song(true);
         }
         added_any
     }
 
-    pub fn add_entire_path_to_queue(&mut self, path: &str, db: &Db) -> bool {
-        let files = scan_dir(path, db);
-
-        let mut added_any = false;
-
-        for file in files.entries {
-            if let SongFileData::Song { song_view } = file.song_data {
-                self.play_next_queue.write().push_back(song_view);
-                added_any = true;
-            }
-        }
+    pub fn add_songs_to_queue(&mut self, songs: Vec<SongView>) -> bool {
+        let added_any = !songs.is_empty();
+        self.play_next_queue.write().extend(songs);
         if added_any && self.playing_state.read().current_song().is_none() {
-            self.next_song();
+            self.next_song(true);
         }
         added_any
     }
 
+    fn folder_songs(path: &str, db: &Db) -> Vec<SongView> {
+        scan_dir(path, db).entries.into_iter()
+            .filter_map(|file| match file.song_data {
+                SongFileData::Song { song_view } => Some(song_view),
+                SongFileData::NotIndexed {} | SongFileData::NotSong {} => None,
+            })
+            .collect()
+    }
+
+    pub fn play_folder_next(&mut self, path: &str, db: &Db) -> bool {
+        self.play_songs_next(Self::folder_songs(path, db))
+    }
+
+    pub fn play_folder_now(&mut self, path: &str, db: &Db) -> bool {
+        self.play_songs_now(Self::folder_songs(path, db))
+    }
+
+    pub fn add_entire_path_to_queue(&mut self, path: &str, db: &Db) -> bool {
+        self.add_songs_to_queue(Self::folder_songs(path, db))
+    }
+
+    /// Plays a playlist file from its first song without importing it, returns how many entries were not found.
+    pub fn play_playlist_file(&mut self, path: &str, db: &Db) -> Result<usize> {
+        let (_, songs, missing) = load_playlist_songs(db, Path::new(path))?;
+        let first = songs.first().ok_or_else(|| anyhow!("No playable songs in {}", path))?;
+        self.play_song_instant(first)?;
+        self.current_fallback_queue.set(QueueFallbackMode::PlaylistFile {
+            path: path.to_string(),
+            current_item: 0,
+            songs,
+        });
+        Ok(missing.len())
+    }
+
     pub fn send_cmd(&self, cmd: AudioControllerCommand) {
         let sender = self.command_sender.clone();
         tokio::spawn(async move {
@@ -244,4 +605,78 @@ impl QueueState {
             }
         });
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn song(id: i32, path: &Path) -> SongView {
+        SongView {
+            id,
+            path: path.to_string_lossy().to_string(),
+            title: format!("Song {}", id),
+            artist: "Artist".to_string(),
+            album: "Album".to_string(),
+            album_art_path: None,
+            track_number: None,
+            duration_seconds: 60,
+            play_count: 0,
+            replay_gain: Default::default(),
+            album_id: 1,
+            tags: Default::default(),
+            format: Default::default(),
+            rating: Default::default(),
+        }
+    }
+
+    /// Plays `current` with `next` queued and stop after current track on, then moves on.
+    fn next_with_stop_after(name: &str, manual: bool) -> (PlayerPlayingState, StopAfter, SongView) {
+        let dir = std::env::temp_dir().join(format!("round-queue-state-{}-{}", std::process::id(), name));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("current.mp3"), b"").unwrap();
+        std::fs::write(dir.join("next.mp3"), b"").unwrap();
+        let current = song(1, &dir.join("current.mp3"));
+        let next = song(2, &dir.join("next.mp3"));
+
+        let dom = VirtualDom::new(|| rsx! {});
+        let (sender, _receiver) = tokio::sync::mpsc::channel(16);
+        let (state, stop_after) = dom.in_scope(ScopeId::ROOT, || {
+            let settings = QueueSettings {
+                mode: Signal::new(PlaybackMode::Normal),
+                repeat: Signal::new(RepeatMode::Off),
+                stop_after: Signal::new(StopAfter::CurrentTrack),
+                shuffle_strategy: Signal::new(ShuffleStrategy::default()),
+            };
+            let mut queue = QueueState {
+                play_next_queue: Signal::new(VecDeque::from([next.clone()])),
+                current_fallback_queue: Signal::new(QueueFallbackMode::None),
+                last_played: Signal::new(VecDeque::new()),
+                playing_state: Signal::new(PlayerPlayingState::Playing { song: current, progress_ms: 0 }),
+                settings,
+                shuffle: None,
+                preloaded: None,
+                command_sender: sender,
+            };
+            queue.next_song(manual);
+            let state = queue.playing_state.peek().clone();
+            let stop_after = *settings.stop_after.peek();
+            (state, stop_after)
+        });
+        let _ = std::fs::remove_dir_all(&dir);
+        (state, stop_after, next)
+    }
+
+    #[tokio::test]
+    async fn skipping_ignores_stop_after_current_track() {
+        let (state, stop_after, next) = next_with_stop_after("skip", true);
+        assert_eq!(state, PlayerPlayingState::Playing { song: next, progress_ms: 0 });
+        assert_eq!(stop_after, StopAfter::CurrentTrack);
+    }
+
+    #[tokio::test]
+    async fn finished_track_stops_after_current_track() {
+        let (state, stop_after, next) = next_with_stop_after("finished", false);
+        assert_eq!(state, PlayerPlayingState::Paused { song: next, progress_ms: 0 });
+        assert_eq!(stop_after, StopAfter::Never);
+    }
+}
@@ -0,0 +1,42 @@
+pub const MAX_STARS: u8 = 5;
+
+/// How much a song is liked, `stars` is 0 while unrated.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct SongRating {
+    pub stars: u8,
+    pub loved: bool,
+}
+
+impl SongRating {
+    pub fn is_rated(&self) -> bool {
+        self.stars > 0
+    }
+
+    /// "★★★☆☆", or "Unrated".
+    pub fn stars_label(stars: u8) -> String {
+        match stars {
+            0 => "Unrated".to_string(),
+            stars => format!("{}{}", "★".repeat(stars as usize), "☆".repeat((MAX_STARS - stars) as usize)),
+        }
+    }
+
+    /// POPM rating byte, using the steps Windows Media Player reads and writes.
+    pub fn popm_value(stars: u8) -> u8 {
+        match stars {
+            0 => 0,
+            1 => 1,
+            2 => 64,
+            3 => 128,
+            4 => 196,
+            _ => 255,
+        }
+    }
+
+    /// How likely shuffle is to pick the song, unrated songs count as three stars
+    /// and every star doubles the odds. Loved songs come up twice as often again.
+    pub fn shuffle_weight(&self) -> f64 {
+        let stars = if self.is_rated() { self.stars } else { 3 };
+        let weight = 2f64.powi(stars as i32 - 3);
+        if self.loved { weight * 2.0 } else { weight }
+    }
+}
@@ -0,0 +1,216 @@
+use std::{hash::{Hash, Hasher}, str::FromStr, sync::{Arc, atomic::{AtomicU32, Ordering}}, time::Duration};
+
+use rodio::{ChannelCount, SampleRate, Source, source::SeekError};
+
+use crate::{custom_tags::read_custom_tags, db::Db};
+
+pub const REPLAY_GAIN_MODE_SETTING: &str = "replaygain_mode";
+pub const REPLAY_GAIN_PREAMP_SETTING: &str = "replaygain_preamp_db";
+pub const REPLAY_GAIN_CLIPPING_SETTING: &str = "replaygain_prevent_clipping";
+pub const LOUDNESS_ANALYSIS_SETTING: &str = "loudness_analysis";
+
+/// Loudness of ReplayGain 2.0 reference playback, in LUFS.
+pub const REFERENCE_LOUDNESS: f32 = -18.0;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct ReplayGain {
+    pub track_gain_db: Option<f32>,
+    pub track_peak: Option<f32>,
+    pub album_gain_db: Option<f32>,
+    pub album_peak: Option<f32>,
+}
+
+impl Eq for ReplayGain {}
+
+impl Hash for ReplayGain {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        for value in [self.track_gain_db, self.track_peak, self.album_gain_db, self.album_peak] {
+            value.map(f32::to_bits).hash(state);
+        }
+    }
+}
+
+impl ReplayGain {
+    pub fn from_file(path: &str) -> ReplayGain {
+        let tags = read_custom_tags(path);
+        let field = |key: &str| tags.get(key).and_then(|value| parse_gain_value(value));
+        ReplayGain {
+            track_gain_db: field("REPLAYGAIN_TRACK_GAIN"),
+            track_peak: field("REPLAYGAIN_TRACK_PEAK"),
+            album_gain_db: field("REPLAYGAIN_ALBUM_GAIN"),
+            album_peak: field("REPLAYGAIN_ALBUM_PEAK"),
+        }
+    }
+}
+
+fn parse_gain_value(value: &str) -> Option<f32> {
+    let value = value.trim();
+    let value = value
+        .strip_suffix("dB")
+        .or_else(|| value.strip_suffix("db"))
+        .or_else(|| value.strip_suffix("DB"))
+        .unwrap_or(value);
+    value.trim().parse().ok()
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ReplayGainMode {
+    Off,
+    Track,
+    Album,
+}
+
+impl ReplayGainMode {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ReplayGainMode::Off => "off",
+            ReplayGainMode::Track => "track",
+            ReplayGainMode::Album => "album",
+        }
+    }
+}
+
+impl FromStr for ReplayGainMode {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<ReplayGainMode, String> {
+        match value {
+            "off" => Ok(ReplayGainMode::Off),
+            "track" => Ok(ReplayGainMode::Track),
+            "album" => Ok(ReplayGainMode::Album),
+            _ => Err(format!("Unknown replay gain mode: {}", value)),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ReplayGainSettings {
+    pub mode: ReplayGainMode,
+    pub preamp_db: f32,
+    pub prevent_clipping: bool,
+    pub analyze_untagged: bool,
+}
+
+impl Default for ReplayGainSettings {
+    fn default() -> Self {
+        ReplayGainSettings {
+            mode: ReplayGainMode::Off,
+            preamp_db: 0.0,
+            prevent_clipping: true,
+            analyze_untagged: false,
+        }
+    }
+}
+
+impl ReplayGainSettings {
+    pub fn load(db: &Db) -> ReplayGainSettings {
+        let defaults = ReplayGainSettings::default();
+        let setting = |key: &str| db.get_setting(key).ok().flatten();
+        ReplayGainSettings {
+            mode: setting(REPLAY_GAIN_MODE_SETTING)
+                .and_then(|value| value.parse().ok())
+                .unwrap_or(defaults.mode),
+            preamp_db: setting(REPLAY_GAIN_PREAMP_SETTING)
+                .and_then(|value| value.parse().ok())
+                .unwrap_or(defaults.preamp_db),
+            prevent_clipping: setting(REPLAY_GAIN_CLIPPING_SETTING)
+                .map_or(defaults.prevent_clipping, |value| value == "true"),
+            analyze_untagged: setting(LOUDNESS_ANALYSIS_SETTING)
+                .map_or(defaults.analyze_untagged, |value| value == "true"),
+        }
+    }
+
+    pub fn save(&self, db: &Db) -> rusqlite::Result<()> {
+        db.set_setting(REPLAY_GAIN_MODE_SETTING, self.mode.as_str())?;
+        db.set_setting(REPLAY_GAIN_PREAMP_SETTING, &self.preamp_db.to_string())?;
+        db.set_setting(REPLAY_GAIN_CLIPPING_SETTING, &self.prevent_clipping.to_string())?;
+        db.set_setting(LOUDNESS_ANALYSIS_SETTING, &self.analyze_untagged.to_string())
+    }
+
+    /// Linear gain factor for a song, falling back to the other gain type when the chosen one is missing.
+    pub fn factor(&self, gain: &ReplayGain) -> f32 {
+        let (gain_db, peak) = match self.mode {
+            ReplayGainMode::Off => return 1.0,
+            ReplayGainMode::Track => (
+                gain.track_gain_db.or(gain.album_gain_db),
+                gain.track_peak.or(gain.album_peak),
+            ),
+            ReplayGainMode::Album => (
+                gain.album_gain_db.or(gain.track_gain_db),
+                gain.album_peak.or(gain.track_peak),
+            ),
+        };
+        let Some(gain_db) = gain_db else {
+            return 1.0;
+        };
+        let mut factor = 10f32.powf((gain_db + self.preamp_db) / 20.0);
+        if self.prevent_clipping {
+            if let Some(peak) = peak.filter(|peak| *peak > 0.0) {
+                factor = factor.min(1.0 / peak);
+            }
+        }
+        factor
+    }
+}
+
+/// Gain applied to a single track, which can be changed while it plays.
+#[derive(Clone)]
+pub struct TrackGain {
+    pub replay_gain: ReplayGain,
+    factor: Arc<AtomicU32>,
+}
+
+impl TrackGain {
+    pub fn new(replay_gain: ReplayGain, settings: &ReplayGainSettings) -> Self {
+        let gain = TrackGain {
+            replay_gain,
+            factor: Arc::new(AtomicU32::new(1.0f32.to_bits())),
+        };
+        gain.apply(settings);
+        gain
+    }
+
+    pub fn apply(&self, settings: &ReplayGainSettings) {
+        self.factor.store(settings.factor(&self.replay_gain).to_bits(), Ordering::Relaxed);
+    }
+
+    pub fn wrap<S: Source>(&self, input: S) -> GainSource<S> {
+        GainSource { input, factor: self.factor.clone() }
+    }
+}
+
+pub struct GainSource<S: Source> {
+    input: S,
+    factor: Arc<AtomicU32>,
+}
+
+impl<S: Source> Iterator for GainSource<S> {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        let factor = f32::from_bits(self.factor.load(Ordering::Relaxed));
+        self.input.next().map(|sample| sample * factor)
+    }
+}
+
+impl<S: Source> Source for GainSource<S> {
+    fn current_span_len(&self) -> Option<usize> {
+        self.input.current_span_len()
+    }
+
+    fn channels(&self) -> ChannelCount {
+        self.input.channels()
+    }
+
+    fn sample_rate(&self) -> SampleRate {
+        self.input.sample_rate()
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        self.input.total_duration()
+    }
+
+    fn try_seek(&mut self, pos: Duration) -> Result<(), SeekError> {
+        self.input.try_seek(pos)
+    }
+}
@@ -0,0 +1,176 @@
+use dioxus::prelude::*;
+
+use crate::{
+    app_context::{DatabaseContext, PlayerContext},
+    db::{AlbumView, ArtistView, SongView},
+    file_browser::{FileEntry, song_file},
+    library_browser::{SongGroup, song_group_item},
+};
+
+pub const SEARCH_RESULT_LIMIT: usize = 50;
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SearchResults {
+    pub artists: Vec<ArtistView>,
+    pub albums: Vec<AlbumView>,
+    pub songs: Vec<SongView>,
+}
+
+impl SearchResults {
+    pub fn is_empty(&self) -> bool {
+        self.artists.is_empty() && self.albums.is_empty() && self.songs.is_empty()
+    }
+}
+
+/// Turns user input into an FTS5 query matching every word as a prefix, limited to `columns` when given.
+/// Words are quoted so operators and punctuation typed by the user are taken literally.
+pub fn fts_query(input: &str, columns: Option<&str>) -> Option<String> {
+    let terms: Vec<String> = input
+        .split_whitespace()
+        .map(|word| {
+            let phrase = format!("\"{}\"*", word.replace('"', "\"\""));
+            match columns {
+                Some(columns) => format!("{{{}}} : {}", columns, phrase),
+                None => phrase,
+            }
+        })
+        .collect();
+    (!terms.is_empty()).then(|| terms.join(" AND "))
+}
+
+fn search_terms(query: &str) -> Vec<String> {
+    query
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|term| !term.is_empty())
+        .map(|term| term.to_lowercase())
+        .collect()
+}
+
+/// Splits `text` into runs, flagging the words that start with one of the search terms.
+fn highlight(text: &str, terms: &[String]) -> Vec<(String, bool)> {
+    let mut segments: Vec<(String, bool)> = Vec::new();
+    let mut push = |part: &str, matched: bool| match segments.last_mut() {
+        Some((last, last_matched)) if *last_matched == matched => last.push_str(part),
+        _ => segments.push((part.to_string(), matched)),
+    };
+    let mut word_start = None;
+    for (index, c) in text.char_indices().chain(std::iter::once((text.len(), ' '))) {
+        match (word_start, c.is_alphanumeric()) {
+            (None, true) => word_start = Some(index),
+            (Some(start), false) => {
+                let word = &text[start..index];
+                let lowercase = word.to_lowercase();
+                push(word, terms.iter().any(|term| lowercase.starts_with(term.as_str())));
+                word_start = None;
+                if index < text.len() {
+                    push(&text[index..index + c.len_utf8()], false);
+                }
+            }
+            (None, false) if index < text.len() => push(&text[index..index + c.len_utf8()], false),
+            _ => {}
+        }
+    }
+    segments
+}
+
+#[component]
+fn highlighted_text(text: String, terms: Vec<String>) -> Element {
+    rsx! {
+        for (part, matched) in highlight(&text, &terms) {
+            if matched {
+                mark { "{part}" }
+            } else {
+                "{part}"
+            }
+        }
+    }
+}
+
+#[component]
+pub fn search_results(query: String) -> Element {
+    let db = use_context::<DatabaseContext>();
+    let mut player_context = use_context::<PlayerContext>();
+    let _ = (player_context.library_update_counter)();
+
+    let results = db.get().search(&query, SEARCH_RESULT_LIMIT).unwrap_or_else(|e| {
+        eprintln!("Search failed: {:?}", e);
+        SearchResults::default()
+    });
+    let terms = search_terms(&query);
+
+    if results.is_empty() {
+        return rsx! {
+            p { class: "search-empty", "No results for \"{query}\"" }
+        };
+    }
+
+    rsx! {
+        div { class: "search-results",
+            if !results.artists.is_empty() {
+                h3 { "Artists" }
+                div { class: "item-list",
+                    for artist in results.artists.iter().cloned() {
+                        song_group_item {
+                            key: "artist-{artist.id}",
+                            group: SongGroup::Artist(artist.id),
+                            icon: rsx! { "🎤" },
+                            label: rsx! {
+                                highlighted_text { text: artist.name.clone(), terms: terms.clone() }
+                                span { class: "search-detail",
+                                    if artist.album_count == 1 { " · 1 album" } else { " · {artist.album_count} albums" }
+                                }
+                            },
+                        }
+                    }
+                }
+            }
+            if !results.albums.is_empty() {
+                h3 { "Albums" }
+                div { class: "item-list",
+                    for album in results.albums.iter().cloned() {
+                        song_group_item {
+                            key: "album-{album.id}",
+                            group: SongGroup::Album(album.id),
+                            icon: match album.cover_art_path.clone() {
+                                Some(art) => rsx! { img { src: art, alt: "Album Art", class: "art" } },
+                                None => rsx! { "💿" },
+                            },
+                            label: rsx! {
+                                highlighted_text { text: album.name.clone(), terms: terms.clone() }
+                                span { class: "search-detail",
+                                    " · "
+                                    highlighted_text { text: album.artist.clone(), terms: terms.clone() }
+                                }
+                            },
+                        }
+                    }
+                }
+            }
+            if !results.songs.is_empty() {
+                h3 { "Songs" }
+                div { class: "item-list",
+                    for song in results.songs.iter().cloned() {
+                        song_file {
+                            key: "song-{song.id}",
+                            file: FileEntry::from_song_view(&song),
+                            label: rsx! {
+                                highlighted_text { text: song.title.clone(), terms: terms.clone() }
+                                span { class: "search-detail",
+                                    " · "
+                                    highlighted_text { text: song.display_artist().to_string(), terms: terms.clone() }
+                                    " · "
+                                    highlighted_text { text: song.album.clone(), terms: terms.clone() }
+                                }
+                            },
+                            on_click: move |_| {
+                                if let Err(e) = player_context.queue.write().play_song_instant(&song) {
+                                    eprintln!("Failed to play song: {:?}", e);
+                                }
+                            },
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
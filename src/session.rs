@@ -0,0 +1,60 @@
+use serde::{Deserialize, Serialize};
+
+use crate::{app_context::{PlaybackMode, RepeatMode}, db::Db};
+
+pub const SESSION_SETTING: &str = "session";
+pub const SESSION_SAVE_INTERVAL_MS: u64 = 10000; //saved periodically too, in case the app doesn't exit cleanly
+pub const SAVED_HISTORY_LEN: usize = 500;
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum SessionFallback {
+    None,
+    Playlist { playlist_id: i32, index: usize },
+    Folder { path: String, item: usize },
+    Album { album_id: i32, item: usize },
+    PlaylistFile { path: String, item: usize },
+}
+
+/// Everything needed to reopen the player where it was left, songs are stored by id.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Session {
+    pub play_next: Vec<i32>,
+    pub history: Vec<i32>,
+    pub fallback: SessionFallback,
+    pub current_song: Option<i32>,
+    pub position_ms: u64,
+    pub volume: f32,
+    pub mode: PlaybackMode,
+    pub repeat: RepeatMode,
+}
+
+impl Default for Session {
+    fn default() -> Self {
+        Session {
+            play_next: Vec::new(),
+            history: Vec::new(),
+            fallback: SessionFallback::None,
+            current_song: None,
+            position_ms: 0,
+            volume: 1.0,
+            mode: PlaybackMode::Normal,
+            repeat: RepeatMode::Off,
+        }
+    }
+}
+
+impl Session {
+    pub fn load(db: &Db) -> Session {
+        db.get_setting(SESSION_SETTING)
+            .ok()
+            .flatten()
+            .and_then(|value| serde_json::from_str(&value).ok())
+            .unwrap_or_default()
+    }
+
+    pub fn save(&self, db: &Db) -> rusqlite::Result<()> {
+        db.set_setting(SESSION_SETTING, &serde_json::to_string(self).unwrap_or_default())
+    }
+}
@@ -0,0 +1,273 @@
+use dioxus::prelude::*;
+
+use crate::{app_context::PlayerContext, output_device::list_output_devices, replay_gain::{ReplayGainMode, ReplayGainSettings}, shuffle::ShuffleStrategy};
+
+#[component]
+pub fn settings_dialog(show: Signal<bool>) -> Element {
+    if !show() {
+        return rsx! {};
+    }
+
+    rsx! {
+        div {
+            class: "context-menu-overlay",
+            onclick: move |_| show.set(false),
+
+            div {
+                class: "context-menu settings-dialog",
+                onclick: move |evt: Event<MouseData>| evt.stop_propagation(),
+
+                h3 { class: "dialog-title", "SETTINGS" }
+                library_settings { }
+                output_settings { }
+                playback_settings { }
+                replay_gain_settings { }
+                div {
+                    class: "common-button",
+                    style: "margin-top: 10px;",
+                    button {
+                        onclick: move |_| show.set(false),
+                        "Close"
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[component]
+fn library_settings() -> Element {
+    let player_context = use_context::<PlayerContext>();
+    let roots = player_context.library_roots;
+    let scan_progress = player_context.scan_progress;
+    let mut new_root = use_signal(String::new);
+
+    let add_root = {
+        let ctx = player_context.clone();
+        move || {
+            let root = new_root().trim().to_string();
+            if root.is_empty() || roots.peek().contains(&root) {
+                return;
+            }
+            if !std::path::Path::new(&root).is_dir() {
+                ctx.clone().notify(format!("{} is not a folder", root));
+                return;
+            }
+            let mut new_roots = roots();
+            new_roots.push(root);
+            ctx.clone().set_library_roots(new_roots);
+            new_root.set(String::new());
+        }
+    };
+
+    rsx! {
+        div { class: "settings-section",
+            h4 { "library" }
+            for root in roots() {
+                div { class: "settings-row", key: "{root}",
+                    span { class: "library-root", title: "{root}", "{root}" }
+                    button {
+                        title: "Remove folder",
+                        onclick: {
+                            let ctx = player_context.clone();
+                            let root = root.clone();
+                            move |_| {
+                                let new_roots = roots().into_iter().filter(|r| *r != root).collect();
+                                ctx.clone().set_library_roots(new_roots);
+                            }
+                        },
+                        "✕"
+                    }
+                }
+            }
+            div { class: "settings-row",
+                input {
+                    r#type: "text",
+                    placeholder: "/path/to/music",
+                    value: new_root(),
+                    oninput: move |evt: Event<FormData>| new_root.set(evt.value()),
+                    onkeydown: {
+                        let mut add_root = add_root.clone();
+                        move |evt: Event<KeyboardData>| {
+                            if evt.key() == Key::Enter {
+                                add_root();
+                            }
+                        }
+                    },
+                }
+                button {
+                    disabled: new_root().trim().is_empty(),
+                    onclick: {
+                        let mut add_root = add_root.clone();
+                        move |_| add_root()
+                    },
+                    "Add"
+                }
+            }
+            div { class: "common-button",
+                if scan_progress().is_some() {
+                    button {
+                        onclick: {
+                            let ctx = player_context.clone();
+                            move |_| ctx.cancel_library_scan()
+                        },
+                        "Cancel scan"
+                    }
+                } else {
+                    button {
+                        disabled: roots().is_empty(),
+                        onclick: {
+                            let ctx = player_context.clone();
+                            move |_| ctx.clone().start_library_scan()
+                        },
+                        "Scan now"
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[component]
+fn output_settings() -> Element {
+    let player_context = use_context::<PlayerContext>();
+    let selected = player_context.output_device;
+
+    //enumerating devices can take a while on some backends
+    let devices = use_resource(|| async {
+        tokio::task::spawn_blocking(list_output_devices).await.unwrap_or_default()
+    });
+
+    let mut device_names = devices().unwrap_or_default();
+    if let Some(name) = selected() {
+        if devices().is_some() && !device_names.contains(&name) {
+            device_names.push(name);
+        }
+    }
+
+    rsx! {
+        div { class: "settings-section",
+            h4 { "output" }
+            div { class: "settings-row",
+                span { "Device" }
+                select {
+                    value: selected().unwrap_or_default(),
+                    onchange: move |evt: Event<FormData>| {
+                        let value = evt.value();
+                        player_context.clone().set_output_device((!value.is_empty()).then_some(value));
+                    },
+                    option { value: "", "System default" }
+                    for name in device_names {
+                        option { key: "{name}", value: "{name}", "{name}" }
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[component]
+fn replay_gain_settings() -> Element {
+    let player_context = use_context::<PlayerContext>();
+    let settings = player_context.replay_gain;
+
+    let update = move |change: &dyn Fn(&mut ReplayGainSettings)| {
+        let mut new_settings = settings();
+        change(&mut new_settings);
+        player_context.clone().set_replay_gain(new_settings);
+    };
+
+    rsx! {
+        div { class: "settings-section",
+            h4 { "loudness" }
+            div { class: "settings-row",
+                span { "ReplayGain" }
+                select {
+                    value: settings().mode.as_str(),
+                    onchange: {
+                        let update = update.clone();
+                        move |evt: Event<FormData>| {
+                            if let Ok(mode) = evt.value().parse::<ReplayGainMode>() {
+                                update(&|s| s.mode = mode);
+                            }
+                        }
+                    },
+                    option { value: "off", "Off" }
+                    option { value: "track", "Track" }
+                    option { value: "album", "Album" }
+                }
+            }
+            div { class: "settings-row",
+                span { "Pre-amp: {settings().preamp_db:+.1} dB" }
+                input {
+                    class: "slider",
+                    r#type: "range",
+                    min: "-15",
+                    max: "15",
+                    step: "0.5",
+                    value: settings().preamp_db,
+                    style: format!("--slider-progress: {}%;", (settings().preamp_db + 15.0) / 30.0 * 100.0),
+                    oninput: {
+                        let update = update.clone();
+                        move |evt: Event<FormData>| {
+                            if let Ok(preamp_db) = evt.value().parse::<f32>() {
+                                update(&|s| s.preamp_db = preamp_db);
+                            }
+                        }
+                    },
+                }
+            }
+            label { class: "settings-row",
+                span { "Prevent clipping" }
+                input {
+                    r#type: "checkbox",
+                    checked: settings().prevent_clipping,
+                    onchange: {
+                        let update = update.clone();
+                        move |evt: Event<FormData>| {
+                            let checked = evt.checked();
+                            update(&|s| s.prevent_clipping = checked);
+                        }
+                    },
+                }
+            }
+            label { class: "settings-row",
+                span { "Analyze untagged files in the background" }
+                input {
+                    r#type: "checkbox",
+                    checked: settings().analyze_untagged,
+                    onchange: move |evt: Event<FormData>| {
+                        let checked = evt.checked();
+                        update(&|s| s.analyze_untagged = checked);
+                    },
+                }
+            }
+        }
+    }
+}
+
+#[component]
+fn playback_settings() -> Element {
+    let player_context = use_context::<PlayerContext>();
+    let strategy = player_context.shuffle_strategy;
+
+    rsx! {
+        div { class: "settings-section",
+            h4 { "playback" }
+            div { class: "settings-row",
+                span { "Shuffle" }
+                select {
+                    value: strategy().as_str(),
+                    onchange: move |evt: Event<FormData>| {
+                        if let Ok(strategy) = evt.value().parse::<ShuffleStrategy>() {
+                            player_context.clone().set_shuffle_strategy(strategy);
+                        }
+                    },
+                    for choice in ShuffleStrategy::ALL {
+                        option { key: "{choice.as_str()}", value: choice.as_str(), "{choice.label()}" }
+                    }
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,365 @@
+use std::str::FromStr;
+
+use rand::{Rng, SeedableRng, rngs::StdRng};
+
+use crate::db::{Db, SongView};
+
+pub const SHUFFLE_STRATEGY_SETTING: &str = "shuffle_strategy";
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ShuffleStrategy {
+    #[default]
+    Tracks,
+    Albums,
+    ArtistSpread,
+    Weighted,
+}
+
+impl ShuffleStrategy {
+    pub const ALL: [ShuffleStrategy; 4] = [
+        ShuffleStrategy::Tracks,
+        ShuffleStrategy::Albums,
+        ShuffleStrategy::ArtistSpread,
+        ShuffleStrategy::Weighted,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ShuffleStrategy::Tracks => "tracks",
+            ShuffleStrategy::Albums => "albums",
+            ShuffleStrategy::ArtistSpread => "artist_spread",
+            ShuffleStrategy::Weighted => "weighted",
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            ShuffleStrategy::Tracks => "Tracks",
+            ShuffleStrategy::Albums => "Albums, in track order",
+            ShuffleStrategy::ArtistSpread => "Tracks, spreading out artists",
+            ShuffleStrategy::Weighted => "Favor high ratings and fewer plays",
+        }
+    }
+
+    pub fn load(db: &Db) -> ShuffleStrategy {
+        db.get_setting(SHUFFLE_STRATEGY_SETTING).ok().flatten()
+            .and_then(|value| value.parse().ok())
+            .unwrap_or_default()
+    }
+}
+
+impl FromStr for ShuffleStrategy {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<ShuffleStrategy, String> {
+        ShuffleStrategy::ALL
+            .into_iter()
+            .find(|strategy| strategy.as_str() == value)
+            .ok_or_else(|| format!("Unknown shuffle strategy: {}", value))
+    }
+}
+
+/// Puts `songs` in shuffled order, the same seed always gives the same order.
+pub fn shuffle(mut songs: Vec<SongView>, strategy: ShuffleStrategy, seed: u64) -> Vec<SongView> {
+    let mut rng = StdRng::seed_from_u64(seed);
+    match strategy {
+        ShuffleStrategy::Tracks => {
+            fisher_yates(&mut songs, &mut rng);
+            songs
+        }
+        ShuffleStrategy::Albums => shuffle_albums(songs, &mut rng),
+        ShuffleStrategy::ArtistSpread => spread_artists(songs, &mut rng),
+        ShuffleStrategy::Weighted => shuffle_weighted(songs, &mut rng),
+    }
+}
+
+//spelled out instead of `SliceRandom::shuffle` so orders only depend on the seed and the generator
+fn fisher_yates<T>(items: &mut [T], rng: &mut StdRng) {
+    for i in (1..items.len()).rev() {
+        let j = rng.random_range(0..=i);
+        items.swap(i, j);
+    }
+}
+
+/// Albums in random order, each one played from its first track.
+fn shuffle_albums(songs: Vec<SongView>, rng: &mut StdRng) -> Vec<SongView> {
+    let mut albums: Vec<Vec<SongView>> = Vec::new();
+    for song in songs {
+        match albums.iter_mut().find(|album| album[0].album_id == song.album_id) {
+            Some(album) => album.push(song),
+            None => albums.push(vec![song]),
+        }
+    }
+    for album in albums.iter_mut() {
+        album.sort_by(|a, b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
+    }
+    fisher_yates(&mut albums, rng);
+    albums.into_iter().flatten().collect()
+}
+
+/// Random order where the same artist never plays twice in a row, unless one artist
+/// has more than half of the songs and it can't be helped.
+fn spread_artists(mut songs: Vec<SongView>, rng: &mut StdRng) -> Vec<SongView> {
+    fisher_yates(&mut songs, rng);
+    let mut artists: Vec<(String, Vec<SongView>)> = Vec::new();
+    for song in songs {
+        let artist = song.display_artist().to_lowercase();
+        match artists.iter_mut().find(|(name, _)| *name == artist) {
+            Some((_, artist_songs)) => artist_songs.push(song),
+            None => artists.push((artist, vec![song])),
+        }
+    }
+
+    let mut remaining: usize = artists.iter().map(|(_, songs)| songs.len()).sum();
+    let mut order = Vec::with_capacity(remaining);
+    let mut last = None;
+    while remaining > 0 {
+        let candidates: Vec<usize> = (0..artists.len())
+            .filter(|index| Some(*index) != last && !artists[*index].1.is_empty())
+            .collect();
+        let largest = candidates.iter().copied().max_by_key(|index| artists[*index].1.len());
+        let pick = match largest {
+            None => last.unwrap_or(0),
+            //an artist with more than half of what's left has to go now to stay apart
+            Some(largest) if artists[largest].1.len() * 2 > remaining => largest,
+            Some(_) => {
+                let total: usize = candidates.iter().map(|index| artists[*index].1.len()).sum();
+                let mut draw = rng.random_range(0..total);
+                candidates.into_iter()
+                    .find(|index| {
+                        let count = artists[*index].1.len();
+                        if draw < count {
+                            true
+                        } else {
+                            draw -= count;
+                            false
+                        }
+                    })
+                    .unwrap_or(0)
+            }
+        };
+        if let Some(song) = artists[pick].1.pop() {
+            order.push(song);
+        }
+        last = Some(pick);
+        remaining -= 1;
+    }
+    order
+}
+
+/// Random order favoring higher rated and less played songs.
+fn shuffle_weighted(songs: Vec<SongView>, rng: &mut StdRng) -> Vec<SongView> {
+    //every song draws an exponential wait with its weight as rate, the shortest waits go first
+    let mut keyed: Vec<(f64, SongView)> = songs.into_iter()
+        .map(|song| {
+            let weight = song.rating.shuffle_weight() / (1.0 + song.play_count.max(0) as f64).sqrt();
+            let draw: f64 = rng.random();
+            (-(1.0 - draw).ln() / weight, song)
+        })
+        .collect();
+    keyed.sort_by(|a, b| a.0.total_cmp(&b.0));
+    keyed.into_iter().map(|(_, song)| song).collect()
+}
+
+/// A shuffled order of the fallback songs, kept until they or the strategy change.
+/// When repeating, the next round is shuffled with the following seed once every song has played.
+pub struct ShuffleOrder {
+    strategy: ShuffleStrategy,
+    seed: u64,
+    source: Vec<SongView>,
+    songs: Vec<SongView>,
+    position: usize,
+}
+
+impl ShuffleOrder {
+    /// Starts right after `current` when it is one of the songs, so it doesn't come up again this round.
+    pub fn new(source: Vec<SongView>, strategy: ShuffleStrategy, seed: u64, current: Option<i32>) -> ShuffleOrder {
+        let mut songs = shuffle(source.clone(), strategy, seed);
+        let mut position = 0;
+        if let Some(index) = songs.iter().position(|song| Some(song.id) == current) {
+            songs.rotate_left(index);
+            position = 1;
+        }
+        ShuffleOrder { strategy, seed, source, songs, position }
+    }
+
+    pub fn is_for(&self, songs: &[SongView], strategy: ShuffleStrategy) -> bool {
+        self.strategy == strategy
+            && self.source.len() == songs.len()
+            && self.source.iter().zip(songs).all(|(a, b)| a.id == b.id)
+    }
+
+    pub fn next(&mut self, repeat: bool) -> Option<SongView> {
+        if self.position >= self.songs.len() {
+            if !repeat {
+                return None;
+            }
+            let last_id = self.songs.last()?.id;
+            self.seed = self.seed.wrapping_add(1);
+            self.songs = shuffle(self.source.clone(), self.strategy, self.seed);
+            if self.songs.len() > 1 && self.songs[0].id == last_id {
+                self.songs.rotate_left(1);
+            }
+            self.position = 0;
+        }
+        let song = self.songs.get(self.position).cloned();
+        self.position += 1;
+        song
+    }
+
+    /// Whether the round ended and another song was picked since, which a new round should start from.
+    pub fn is_over(&self, current: Option<i32>) -> bool {
+        self.position >= self.songs.len() && self.songs.last().map(|song| song.id) != current
+    }
+
+    pub fn step_back(&mut self) {
+        self.position = self.position.saturating_sub(1);
+    }
+
+    /// Songs still to come this round, in the order they will play.
+    pub fn upcoming(&self) -> &[SongView] {
+        &self.songs[self.position.min(self.songs.len())..]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{rating::SongRating, song_tags::SongTags};
+
+    fn song(id: i32, album_id: i32, artist: &str, disc: u16, track: u16) -> SongView {
+        SongView {
+            id,
+            path: format!("/music/{}.mp3", id),
+            title: format!("Song {}", id),
+            artist: artist.to_string(),
+            album: format!("Album {}", album_id),
+            album_art_path: None,
+            track_number: Some(track),
+            duration_seconds: 180,
+            play_count: id % 4,
+            replay_gain: Default::default(),
+            album_id,
+            tags: SongTags { disc_number: Some(disc), ..Default::default() },
+            format: Default::default(),
+            rating: SongRating { stars: (id % 6) as u8, loved: id % 5 == 0 },
+        }
+    }
+
+    /// Three albums by three artists, the two discs of album 2 listed out of order.
+    fn library() -> Vec<SongView> {
+        let mut songs = Vec::new();
+        for track in 1..=4 {
+            songs.push(song(track, 1, "Alpha", 1, track as u16));
+        }
+        for (id, disc, track) in [(5, 2, 2), (6, 1, 3), (7, 2, 1), (8, 1, 1), (9, 1, 2)] {
+            songs.push(song(id, 2, "Beta", disc, track));
+        }
+        for track in 1..=3 {
+            songs.push(song(9 + track, 3, "gamma", 1, track as u16));
+        }
+        songs
+    }
+
+    fn ids(songs: &[SongView]) -> Vec<i32> {
+        songs.iter().map(|song| song.id).collect()
+    }
+
+    #[test]
+    fn same_seed_same_order() {
+        for strategy in ShuffleStrategy::ALL {
+            for seed in 0..20 {
+                assert_eq!(ids(&shuffle(library(), strategy, seed)), ids(&shuffle(library(), strategy, seed)), "{:?}", strategy);
+            }
+        }
+    }
+
+    #[test]
+    fn orders_are_permutations() {
+        let mut expected = ids(&library());
+        expected.sort();
+        for strategy in ShuffleStrategy::ALL {
+            for seed in 0..20 {
+                let mut order = ids(&shuffle(library(), strategy, seed));
+                order.sort();
+                assert_eq!(order, expected, "{:?}", strategy);
+            }
+        }
+    }
+
+    #[test]
+    fn albums_keep_disc_and_track_order() {
+        for seed in 0..20 {
+            let order = shuffle(library(), ShuffleStrategy::Albums, seed);
+            let mut albums: Vec<i32> = order.iter().map(|song| song.album_id).collect();
+            albums.dedup();
+            assert_eq!(albums.len(), 3, "albums are played whole");
+            for album in order.chunk_by(|a, b| a.album_id == b.album_id) {
+                let positions: Vec<(Option<u16>, Option<u16>)> = album.iter().map(|song| (song.tags.disc_number, song.track_number)).collect();
+                assert!(positions.is_sorted(), "{:?}", positions);
+            }
+        }
+    }
+
+    #[test]
+    fn artist_spread_keeps_artists_apart() {
+        let mut songs = library();
+        //"Gamma" and "gamma" are the same artist
+        songs.push(song(13, 4, "Gamma", 1, 1));
+        for seed in 0..50 {
+            let order = shuffle(songs.clone(), ShuffleStrategy::ArtistSpread, seed);
+            for pair in order.windows(2) {
+                assert!(!pair[0].display_artist().eq_ignore_ascii_case(pair[1].display_artist()), "seed {}: {:?}", seed, ids(&order));
+            }
+        }
+    }
+
+    #[test]
+    fn artist_spread_fits_an_artist_with_half_the_songs() {
+        //4 of 7 songs only stay apart on every other slot, starting with the first
+        let songs: Vec<SongView> = (1..=4)
+            .map(|id| song(id, 1, "Alpha", 1, id as u16))
+            .chain([song(5, 2, "Beta", 1, 1), song(6, 2, "Beta", 1, 2), song(7, 3, "Gamma", 1, 1)])
+            .collect();
+        for seed in 0..20 {
+            let order = shuffle(songs.clone(), ShuffleStrategy::ArtistSpread, seed);
+            for pair in order.windows(2) {
+                assert_ne!(pair[0].artist, pair[1].artist, "seed {}: {:?}", seed, ids(&order));
+            }
+        }
+    }
+
+    #[test]
+    fn order_ends_after_one_round_without_repeat() {
+        let mut order = ShuffleOrder::new(library(), ShuffleStrategy::Tracks, 3, None);
+        let mut played: Vec<i32> = std::iter::from_fn(|| order.next(false)).map(|song| song.id).collect();
+        played.sort();
+        let mut expected = ids(&library());
+        expected.sort();
+        assert_eq!(played, expected);
+        assert!(order.next(false).is_none());
+        assert!(order.upcoming().is_empty());
+    }
+
+    #[test]
+    fn order_starts_after_the_current_song() {
+        let mut order = ShuffleOrder::new(library(), ShuffleStrategy::Tracks, 3, Some(6));
+        assert_eq!(order.upcoming().len(), library().len() - 1);
+        assert!(std::iter::from_fn(|| order.next(false)).all(|song| song.id != 6));
+    }
+
+    #[test]
+    fn repeat_reshuffles_without_playing_the_last_song_twice() {
+        for songs in [library(), vec![song(1, 1, "Alpha", 1, 1), song(2, 1, "Alpha", 1, 2)]] {
+            for seed in 0..20 {
+                let mut order = ShuffleOrder::new(songs.clone(), ShuffleStrategy::Tracks, seed, None);
+                let mut previous = None;
+                for _ in 0..songs.len() * 4 {
+                    let song = order.next(true).expect("repeat never runs out");
+                    assert_ne!(Some(song.id), previous, "seed {}", seed);
+                    previous = Some(song.id);
+                }
+            }
+        }
+    }
+}
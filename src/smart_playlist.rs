@@ -0,0 +1,465 @@
+use std::str::FromStr;
+
+use rusqlite::types::Value;
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RuleField {
+    Artist,
+    Album,
+    Genre,
+    Year,
+    PlayCount,
+    NeverPlayed,
+    AddedWithinDays,
+    Duration,
+    Rating,
+    Loved,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum FieldKind {
+    Text,
+    Number,
+    Condition,
+}
+
+impl RuleField {
+    pub const ALL: [RuleField; 10] = [
+        RuleField::Artist,
+        RuleField::Album,
+        RuleField::Genre,
+        RuleField::Year,
+        RuleField::PlayCount,
+        RuleField::NeverPlayed,
+        RuleField::AddedWithinDays,
+        RuleField::Duration,
+        RuleField::Rating,
+        RuleField::Loved,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RuleField::Artist => "artist",
+            RuleField::Album => "album",
+            RuleField::Genre => "genre",
+            RuleField::Year => "year",
+            RuleField::PlayCount => "play_count",
+            RuleField::NeverPlayed => "never_played",
+            RuleField::AddedWithinDays => "added_within_days",
+            RuleField::Duration => "duration",
+            RuleField::Rating => "rating",
+            RuleField::Loved => "loved",
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            RuleField::Artist => "Artist",
+            RuleField::Album => "Album",
+            RuleField::Genre => "Genre",
+            RuleField::Year => "Year",
+            RuleField::PlayCount => "Play count",
+            RuleField::NeverPlayed => "Never played",
+            RuleField::AddedWithinDays => "Added in the last days",
+            RuleField::Duration => "Duration (minutes)",
+            RuleField::Rating => "Rating (stars)",
+            RuleField::Loved => "Loved",
+        }
+    }
+
+    fn kind(&self) -> FieldKind {
+        match self {
+            RuleField::Artist | RuleField::Album | RuleField::Genre => FieldKind::Text,
+            RuleField::Year | RuleField::PlayCount | RuleField::Duration | RuleField::Rating => FieldKind::Number,
+            RuleField::NeverPlayed | RuleField::AddedWithinDays | RuleField::Loved => FieldKind::Condition,
+        }
+    }
+
+    /// Operators offered for the field, empty when the field is a condition on its own.
+    pub fn operators(&self) -> &'static [RuleOperator] {
+        match self.kind() {
+            FieldKind::Text => &[RuleOperator::Is, RuleOperator::IsNot, RuleOperator::Contains],
+            FieldKind::Number => &[RuleOperator::Is, RuleOperator::IsNot, RuleOperator::AtLeast, RuleOperator::AtMost],
+            FieldKind::Condition => &[],
+        }
+    }
+
+    pub fn takes_value(&self) -> bool {
+        !matches!(self, RuleField::NeverPlayed | RuleField::Loved)
+    }
+
+    /// Columns compared for text fields, an artist matches either the album or the track artist.
+    fn text_columns(&self) -> &'static [&'static str] {
+        match self {
+            RuleField::Artist => &["ar.name", "s.track_artist"],
+            RuleField::Album => &["al.name"],
+            RuleField::Genre => &["s.genre"],
+            _ => &[],
+        }
+    }
+
+    fn number_column(&self) -> &'static str {
+        match self {
+            RuleField::Year => "s.year",
+            RuleField::PlayCount => "s.play_count",
+            RuleField::Rating => "s.rating",
+            _ => "s.duration_seconds",
+        }
+    }
+}
+
+impl FromStr for RuleField {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<RuleField, String> {
+        RuleField::ALL
+            .into_iter()
+            .find(|field| field.as_str() == value)
+            .ok_or_else(|| format!("Unknown rule field: {}", value))
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RuleOperator {
+    Is,
+    IsNot,
+    Contains,
+    AtLeast,
+    AtMost,
+}
+
+impl RuleOperator {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RuleOperator::Is => "is",
+            RuleOperator::IsNot => "is_not",
+            RuleOperator::Contains => "contains",
+            RuleOperator::AtLeast => "at_least",
+            RuleOperator::AtMost => "at_most",
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            RuleOperator::Is => "is",
+            RuleOperator::IsNot => "is not",
+            RuleOperator::Contains => "contains",
+            RuleOperator::AtLeast => "at least",
+            RuleOperator::AtMost => "at most",
+        }
+    }
+}
+
+impl FromStr for RuleOperator {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<RuleOperator, String> {
+        [RuleOperator::Is, RuleOperator::IsNot, RuleOperator::Contains, RuleOperator::AtLeast, RuleOperator::AtMost]
+            .into_iter()
+            .find(|operator| operator.as_str() == value)
+            .ok_or_else(|| format!("Unknown rule operator: {}", value))
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct SmartRule {
+    pub field: RuleField,
+    pub operator: RuleOperator,
+    pub value: String,
+}
+
+impl Default for SmartRule {
+    fn default() -> Self {
+        SmartRule { field: RuleField::Artist, operator: RuleOperator::Is, value: String::new() }
+    }
+}
+
+impl SmartRule {
+    /// Switches the field, keeping the operator when the new field supports it.
+    pub fn set_field(&mut self, field: RuleField) {
+        self.field = field;
+        if !field.operators().contains(&self.operator) {
+            self.operator = field.operators().first().copied().unwrap_or(RuleOperator::Is);
+        }
+    }
+
+    /// SQL condition over `songs s`, `albums al` and `artists ar`, with its parameters.
+    fn condition(&self) -> Result<(String, Vec<Value>), String> {
+        let value = self.value.trim();
+        match self.field.kind() {
+            FieldKind::Text => {
+                let (comparison, value) = match self.operator {
+                    RuleOperator::Contains => {
+                        let escaped = value.replace('\\', "\\\\").replace('%', "\\%").replace('_', "\\_");
+                        ("LIKE ? ESCAPE '\\'", format!("%{}%", escaped))
+                    }
+                    _ => ("= ? COLLATE NOCASE", value.to_string()),
+                };
+                let columns = self.field.text_columns();
+                let any_column = columns
+                    .iter()
+                    .map(|column| format!("COALESCE({}, '') {}", column, comparison))
+                    .collect::<Vec<_>>()
+                    .join(" OR ");
+                let params = columns.iter().map(|_| Value::Text(value.clone())).collect();
+                match self.operator {
+                    RuleOperator::IsNot => Ok((format!("NOT ({})", any_column), params)),
+                    _ => Ok((format!("({})", any_column), params)),
+                }
+            }
+            FieldKind::Number => {
+                let number: f64 = value
+                    .parse()
+                    .map_err(|_| format!("{} needs a number, got \"{}\"", self.field.label(), value))?;
+                let number = match self.field {
+                    RuleField::Duration => (number * 60.0).round() as i64,
+                    _ => number.round() as i64,
+                };
+                let comparison = match self.operator {
+                    RuleOperator::IsNot => "IS NOT",
+                    RuleOperator::AtLeast => ">=",
+                    RuleOperator::AtMost => "<=",
+                    _ => "=",
+                };
+                Ok((format!("{} {} ?", self.field.number_column(), comparison), vec![Value::Integer(number)]))
+            }
+            FieldKind::Condition => match self.field {
+                RuleField::NeverPlayed => Ok(("s.play_count = 0".to_string(), Vec::new())),
+                RuleField::Loved => Ok(("s.loved = 1".to_string(), Vec::new())),
+                _ => {
+                    let days: u32 = value
+                        .parse()
+                        .map_err(|_| format!("{} needs a number of days, got \"{}\"", self.field.label(), value))?;
+                    Ok((
+                        "s.added_at >= CAST(strftime('%s', 'now') AS INTEGER) - ? * 86400".to_string(),
+                        vec![Value::Integer(days as i64)],
+                    ))
+                }
+            },
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RuleMatch {
+    #[default]
+    All,
+    Any,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SmartSort {
+    #[default]
+    Artist,
+    Album,
+    Title,
+    Year,
+    PlayCount,
+    Added,
+    Duration,
+    Rating,
+    Random,
+}
+
+impl SmartSort {
+    pub const ALL: [SmartSort; 9] = [
+        SmartSort::Artist,
+        SmartSort::Album,
+        SmartSort::Title,
+        SmartSort::Year,
+        SmartSort::PlayCount,
+        SmartSort::Added,
+        SmartSort::Duration,
+        SmartSort::Rating,
+        SmartSort::Random,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SmartSort::Artist => "artist",
+            SmartSort::Album => "album",
+            SmartSort::Title => "title",
+            SmartSort::Year => "year",
+            SmartSort::PlayCount => "play_count",
+            SmartSort::Added => "added",
+            SmartSort::Duration => "duration",
+            SmartSort::Rating => "rating",
+            SmartSort::Random => "random",
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            SmartSort::Artist => "Artist",
+            SmartSort::Album => "Album",
+            SmartSort::Title => "Title",
+            SmartSort::Year => "Year",
+            SmartSort::PlayCount => "Play count",
+            SmartSort::Added => "Date added",
+            SmartSort::Duration => "Duration",
+            SmartSort::Rating => "Rating",
+            SmartSort::Random => "Random",
+        }
+    }
+
+    /// The key `descending` applies to, followed by the tie-breakers keeping albums in track order.
+    fn order_by(&self, descending: bool) -> String {
+        let (key, tie_break) = match self {
+            SmartSort::Artist => ("ar.name COLLATE NOCASE", "al.name COLLATE NOCASE, s.disc_number, s.track_number"),
+            SmartSort::Album => ("al.name COLLATE NOCASE", "s.disc_number, s.track_number"),
+            SmartSort::Title => ("s.title COLLATE NOCASE", "ar.name COLLATE NOCASE"),
+            SmartSort::Year => ("s.year", "al.name COLLATE NOCASE, s.disc_number, s.track_number"),
+            SmartSort::PlayCount => ("s.play_count", "s.title COLLATE NOCASE"),
+            SmartSort::Added => ("s.added_at", "al.name COLLATE NOCASE, s.disc_number, s.track_number"),
+            SmartSort::Duration => ("s.duration_seconds", "s.title COLLATE NOCASE"),
+            SmartSort::Rating => ("s.rating", "s.loved DESC, s.title COLLATE NOCASE"),
+            SmartSort::Random => return "RANDOM()".to_string(),
+        };
+        format!("{} {}, {}", key, if descending { "DESC" } else { "ASC" }, tie_break)
+    }
+}
+
+impl FromStr for SmartSort {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<SmartSort, String> {
+        SmartSort::ALL
+            .into_iter()
+            .find(|sort| sort.as_str() == value)
+            .ok_or_else(|| format!("Unknown sort: {}", value))
+    }
+}
+
+/// Definition of a smart playlist, stored as JSON in `playlists.rules`.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SmartPlaylistRules {
+    pub match_mode: RuleMatch,
+    pub rules: Vec<SmartRule>,
+    pub sort: SmartSort,
+    pub descending: bool,
+    pub limit: Option<u32>,
+}
+
+impl SmartPlaylistRules {
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).unwrap_or_default()
+    }
+
+    pub fn from_json(value: &str) -> Option<SmartPlaylistRules> {
+        serde_json::from_str(value).ok()
+    }
+
+    /// `WHERE`, `ORDER BY` and `LIMIT` clauses selecting the matching songs, with their parameters.
+    /// Fails on rules whose value doesn't fit the field.
+    pub fn to_sql(&self) -> Result<(String, Vec<Value>), String> {
+        let mut conditions = Vec::new();
+        let mut params = Vec::new();
+        for rule in &self.rules {
+            let (condition, rule_params) = rule.condition()?;
+            conditions.push(condition);
+            params.extend(rule_params);
+        }
+
+        let mut sql = String::new();
+        if !conditions.is_empty() {
+            let joiner = match self.match_mode {
+                RuleMatch::All => " AND ",
+                RuleMatch::Any => " OR ",
+            };
+            sql.push_str(&format!("WHERE {}\n", conditions.join(joiner)));
+        }
+        sql.push_str(&format!("ORDER BY {}", self.sort.order_by(self.descending)));
+        if let Some(limit) = self.limit {
+            sql.push_str(" LIMIT ?");
+            params.push(Value::Integer(limit as i64));
+        }
+        Ok((sql, params))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rule(field: RuleField, operator: RuleOperator, value: &str) -> SmartRule {
+        SmartRule { field, operator, value: value.to_string() }
+    }
+
+    fn text(value: &str) -> Value {
+        Value::Text(value.to_string())
+    }
+
+    #[test]
+    fn rules_join_with_the_match_mode() {
+        let mut rules = SmartPlaylistRules {
+            rules: vec![rule(RuleField::Genre, RuleOperator::Is, " Jazz "), rule(RuleField::Year, RuleOperator::AtLeast, "1990")],
+            limit: Some(25),
+            ..Default::default()
+        };
+        let (sql, params) = rules.to_sql().unwrap();
+        assert_eq!(
+            sql,
+            format!("WHERE (COALESCE(s.genre, '') = ? COLLATE NOCASE) AND s.year >= ?\nORDER BY {} LIMIT ?", SmartSort::Artist.order_by(false))
+        );
+        assert_eq!(params, [text("Jazz"), Value::Integer(1990), Value::Integer(25)]);
+
+        rules.match_mode = RuleMatch::Any;
+        rules.limit = None;
+        let (sql, params) = rules.to_sql().unwrap();
+        assert!(sql.starts_with("WHERE (COALESCE(s.genre, '') = ? COLLATE NOCASE) OR s.year >= ?\nORDER BY "));
+        assert_eq!(params, [text("Jazz"), Value::Integer(1990)]);
+    }
+
+    #[test]
+    fn no_rules_only_sort() {
+        let rules = SmartPlaylistRules { sort: SmartSort::Random, ..Default::default() };
+        assert_eq!(rules.to_sql().unwrap(), ("ORDER BY RANDOM()".to_string(), Vec::new()));
+    }
+
+    #[test]
+    fn artist_is_not_excludes_both_artist_columns() {
+        let (condition, params) = rule(RuleField::Artist, RuleOperator::IsNot, "Band").condition().unwrap();
+        assert_eq!(condition, "NOT (COALESCE(ar.name, '') = ? COLLATE NOCASE OR COALESCE(s.track_artist, '') = ? COLLATE NOCASE)");
+        assert_eq!(params, [text("Band"), text("Band")]);
+    }
+
+    #[test]
+    fn contains_escapes_like_wildcards() {
+        let (condition, params) = rule(RuleField::Album, RuleOperator::Contains, "100%_a\\b").condition().unwrap();
+        assert_eq!(condition, "(COALESCE(al.name, '') LIKE ? ESCAPE '\\')");
+        assert_eq!(params, [text("%100\\%\\_a\\\\b%")]);
+    }
+
+    #[test]
+    fn duration_is_given_in_minutes() {
+        let (condition, params) = rule(RuleField::Duration, RuleOperator::AtMost, "3.5").condition().unwrap();
+        assert_eq!(condition, "s.duration_seconds <= ?");
+        assert_eq!(params, [Value::Integer(210)]);
+    }
+
+    #[test]
+    fn non_numeric_values_are_refused() {
+        assert_eq!(rule(RuleField::Year, RuleOperator::Is, "nineties").condition(), Err("Year needs a number, got \"nineties\"".to_string()));
+        assert!(rule(RuleField::AddedWithinDays, RuleOperator::Is, "-3").condition().is_err());
+
+        let rules = SmartPlaylistRules {
+            rules: vec![rule(RuleField::Genre, RuleOperator::Is, "Jazz"), rule(RuleField::PlayCount, RuleOperator::AtLeast, "")],
+            ..Default::default()
+        };
+        assert_eq!(rules.to_sql(), Err("Play count needs a number, got \"\"".to_string()));
+    }
+
+    #[test]
+    fn select_values_parse_back() {
+        assert!(RuleField::ALL.iter().all(|field| field.as_str().parse() == Ok(*field)));
+        assert!(SmartSort::ALL.iter().all(|sort| sort.as_str().parse() == Ok(*sort)));
+        assert_eq!("at_most".parse(), Ok(RuleOperator::AtMost));
+        assert!("sometimes".parse::<RuleOperator>().is_err());
+    }
+}
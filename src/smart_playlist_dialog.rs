@@ -0,0 +1,196 @@
+use dioxus::prelude::*;
+
+use crate::{
+    app_context::{DatabaseContext, PlayerContext},
+    smart_playlist::{RuleField, RuleMatch, RuleOperator, SmartPlaylistRules, SmartRule, SmartSort},
+};
+
+/// Creates a smart playlist, or edits the rules of `playlist_id` when given.
+#[component]
+pub fn smart_playlist_dialog(playlist_id: Option<i32>, show: Signal<bool>) -> Element {
+    let db = use_context::<DatabaseContext>();
+    let mut player_context = use_context::<PlayerContext>();
+
+    let (initial_name, initial_rules) = use_hook(|| match playlist_id {
+        Some(id) => (
+            db.get().get_playlist_data(id).map(|(_, name)| name).unwrap_or_default(),
+            db.get().get_playlist_rules(id).ok().flatten().unwrap_or_default(),
+        ),
+        None => (
+            "New Smart Playlist".to_string(),
+            SmartPlaylistRules { rules: vec![SmartRule::default()], ..Default::default() },
+        ),
+    });
+    let mut name = use_signal(|| initial_name);
+    let mut rules = use_signal(|| initial_rules);
+    let mut limit_text = use_signal(|| rules.peek().limit.map(|limit| limit.to_string()).unwrap_or_default());
+
+    let db_preview = db.clone();
+    let preview = use_memo(move || {
+        let rules = rules();
+        rules.to_sql()?;
+        db_preview.get().get_smart_playlist_songs(&rules).map(|songs| songs.len()).map_err(|e| e.to_string())
+    });
+
+    let mut save = move || {
+        let rules = rules();
+        if let Err(e) = rules.to_sql() {
+            player_context.notify(e);
+            return;
+        }
+        let name = name().trim().to_string();
+        let result = match playlist_id {
+            Some(id) => db.get().update_smart_playlist(id, &name, &rules),
+            None => db.get().create_smart_playlist(&name, &rules).map(|_| ()),
+        };
+        match result {
+            Ok(()) => {
+                player_context.playlist_update_counter += 1;
+                show.set(false);
+            }
+            Err(e) => player_context.notify(format!("Failed to save smart playlist: {}", e)),
+        }
+    };
+
+    rsx! {
+        div {
+            class: "context-menu-overlay",
+            onclick: move |_| show.set(false),
+
+            div {
+                class: "context-menu settings-dialog",
+                onclick: move |evt: Event<MouseData>| evt.stop_propagation(),
+
+                h3 { class: "dialog-title", "SMART PLAYLIST" }
+                div { class: "settings-section",
+                    div { class: "settings-row",
+                        span { "Name" }
+                        input {
+                            r#type: "text",
+                            value: "{name}",
+                            oninput: move |evt: Event<FormData>| name.set(evt.value()),
+                        }
+                    }
+                    div { class: "settings-row",
+                        span { "Match" }
+                        select {
+                            value: if rules().match_mode == RuleMatch::All { "all" } else { "any" },
+                            onchange: move |evt: Event<FormData>| {
+                                rules.write().match_mode = if evt.value() == "any" { RuleMatch::Any } else { RuleMatch::All };
+                            },
+                            option { value: "all", "All rules" }
+                            option { value: "any", "Any rule" }
+                        }
+                    }
+                }
+                div { class: "settings-section",
+                    h4 { "rules" }
+                    for (index, rule) in rules().rules.into_iter().enumerate() {
+                        div { class: "smart-rule-row",
+                            key: "{index}",
+                            select {
+                                value: rule.field.as_str(),
+                                onchange: move |evt: Event<FormData>| {
+                                    if let Ok(field) = evt.value().parse::<RuleField>() {
+                                        rules.write().rules[index].set_field(field);
+                                    }
+                                },
+                                for field in RuleField::ALL {
+                                    option { key: "{field.as_str()}", value: field.as_str(), "{field.label()}" }
+                                }
+                            }
+                            if !rule.field.operators().is_empty() {
+                                select {
+                                    value: rule.operator.as_str(),
+                                    onchange: move |evt: Event<FormData>| {
+                                        if let Ok(operator) = evt.value().parse::<RuleOperator>() {
+                                            rules.write().rules[index].operator = operator;
+                                        }
+                                    },
+                                    for operator in rule.field.operators() {
+                                        option { key: "{operator.as_str()}", value: operator.as_str(), "{operator.label()}" }
+                                    }
+                                }
+                            }
+                            if rule.field.takes_value() {
+                                input {
+                                    r#type: "text",
+                                    value: "{rule.value}",
+                                    oninput: move |evt: Event<FormData>| rules.write().rules[index].value = evt.value(),
+                                }
+                            }
+                            button {
+                                class: "smart-rule-remove",
+                                onclick: move |_| {
+                                    rules.write().rules.remove(index);
+                                },
+                                "✕"
+                            }
+                        }
+                    }
+                    div { class: "common-button",
+                        button {
+                            onclick: move |_| rules.write().rules.push(SmartRule::default()),
+                            "Add rule"
+                        }
+                    }
+                }
+                div { class: "settings-section",
+                    div { class: "settings-row",
+                        span { "Sort by" }
+                        select {
+                            value: rules().sort.as_str(),
+                            onchange: move |evt: Event<FormData>| {
+                                if let Ok(sort) = evt.value().parse::<SmartSort>() {
+                                    rules.write().sort = sort;
+                                }
+                            },
+                            for sort in SmartSort::ALL {
+                                option { key: "{sort.as_str()}", value: sort.as_str(), "{sort.label()}" }
+                            }
+                        }
+                    }
+                    if rules().sort != SmartSort::Random {
+                        div { class: "settings-row",
+                            span { "Descending" }
+                            input {
+                                r#type: "checkbox",
+                                checked: rules().descending,
+                                onchange: move |evt: Event<FormData>| rules.write().descending = evt.checked(),
+                            }
+                        }
+                    }
+                    div { class: "settings-row",
+                        span { "Limit" }
+                        input {
+                            r#type: "text",
+                            value: "{limit_text}",
+                            placeholder: "No limit",
+                            oninput: move |evt: Event<FormData>| {
+                                rules.write().limit = evt.value().trim().parse::<u32>().ok().filter(|limit| *limit > 0);
+                                limit_text.set(evt.value());
+                            },
+                        }
+                    }
+                }
+                match preview() {
+                    Ok(count) => rsx! { p { class: "smart-playlist-preview", "{count} songs match" } },
+                    Err(e) => rsx! { p { class: "smart-playlist-preview", "{e}" } },
+                }
+                div {
+                    class: "common-button",
+                    style: "display: flex; gap: 10px; margin-top: 10px;",
+                    button {
+                        disabled: name().trim().is_empty() || preview().is_err(),
+                        onclick: move |_| save(),
+                        "Save"
+                    }
+                    button {
+                        onclick: move |_| show.set(false),
+                        "Cancel"
+                    }
+                }
+            }
+        }
+    }
+}
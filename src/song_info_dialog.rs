@@ -0,0 +1,92 @@
+use dioxus::prelude::*;
+
+use crate::db::SongView;
+
+fn format_duration(seconds: u64) -> String {
+    format!("{}:{:02}", seconds / 60, seconds % 60)
+}
+
+/// "3" or "3 / 12" when the total is known.
+fn format_position(number: Option<u16>, total: Option<u16>) -> Option<String> {
+    match (number, total) {
+        (Some(number), Some(total)) => Some(format!("{} / {}", number, total)),
+        (Some(number), None) => Some(number.to_string()),
+        _ => None,
+    }
+}
+
+fn format_channels(channels: u16) -> String {
+    match channels {
+        1 => "Mono".to_string(),
+        2 => "Stereo".to_string(),
+        channels => format!("{} channels", channels),
+    }
+}
+
+#[component]
+fn info_row(label: String, value: Option<String>) -> Element {
+    match value {
+        Some(value) => rsx! {
+            div { class: "settings-row song-info-row",
+                span { class: "song-info-label", "{label}" }
+                span { "{value}" }
+            }
+        },
+        None => rsx! {},
+    }
+}
+
+#[component]
+pub fn song_info_dialog(song: SongView, show: Signal<bool>) -> Element {
+    if !show() {
+        return rsx! {};
+    }
+
+    let tags = &song.tags;
+    let format = song.format;
+
+    rsx! {
+        div {
+            class: "context-menu-overlay",
+            onclick: move |_| show.set(false),
+
+            div {
+                class: "context-menu settings-dialog",
+                onclick: move |evt: Event<MouseData>| evt.stop_propagation(),
+
+                h3 { class: "dialog-title", "{song.title}" }
+                div { class: "settings-section",
+                    h4 { "Tags" }
+                    info_row { label: "Artist", value: Some(song.display_artist().to_string()) }
+                    if tags.track_artist.is_some() {
+                        info_row { label: "Album artist", value: Some(song.artist.clone()) }
+                    }
+                    info_row { label: "Album", value: Some(song.album.clone()) }
+                    info_row { label: "Track", value: format_position(song.track_number, tags.track_total) }
+                    info_row { label: "Disc", value: format_position(tags.disc_number, tags.disc_total) }
+                    info_row { label: "Year", value: tags.year.map(|year| year.to_string()) }
+                    info_row { label: "Genre", value: tags.genre.clone() }
+                    info_row { label: "Composer", value: tags.composer.clone() }
+                    info_row { label: "Comment", value: tags.comment.clone() }
+                }
+                div { class: "settings-section",
+                    h4 { "File" }
+                    info_row { label: "Duration", value: Some(format_duration(song.duration_seconds)) }
+                    info_row { label: "Bitrate", value: format.bitrate_kbps.map(|bitrate| format!("{} kbps", bitrate)) }
+                    info_row { label: "Sample rate", value: format.sample_rate.map(|rate| format!("{} Hz", rate)) }
+                    info_row { label: "Channels", value: format.channels.map(format_channels) }
+                    info_row { label: "Plays", value: Some(song.play_count.to_string()) }
+                    info_row { label: "Path", value: Some(song.path.clone()) }
+                }
+                div {
+                    class: "common-button",
+                    style: "margin-top: 10px;",
+                    button {
+                        onclick: move |_| show.set(false),
+                        "Close"
+                    }
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,61 @@
+use std::time::Duration;
+
+use audiotags::AudioTag;
+
+/// Tags beyond the ones the library is organized by, read when a song is added.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct SongTags {
+    pub track_artist: Option<String>,
+    pub genre: Option<String>,
+    pub year: Option<i32>,
+    pub disc_number: Option<u16>,
+    pub disc_total: Option<u16>,
+    pub track_total: Option<u16>,
+    pub composer: Option<String>,
+    pub comment: Option<String>,
+}
+
+fn non_empty(value: Option<&str>) -> Option<String> {
+    value.map(str::trim).filter(|value| !value.is_empty()).map(str::to_string)
+}
+
+impl SongTags {
+    pub fn from_tag(tag: &dyn AudioTag) -> SongTags {
+        let (disc_number, disc_total) = tag.disc();
+        SongTags {
+            track_artist: non_empty(tag.artist()),
+            genre: non_empty(tag.genre()),
+            //id3v2.4 files often only carry a full recording date
+            year: tag.year().or_else(|| tag.date().map(|date| date.year)),
+            disc_number,
+            disc_total,
+            track_total: tag.total_tracks(),
+            composer: non_empty(tag.composer()),
+            comment: non_empty(tag.comment()),
+        }
+    }
+}
+
+/// Properties of the encoded stream, the bitrate is averaged over the whole file.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct AudioFormat {
+    pub bitrate_kbps: Option<u32>,
+    pub sample_rate: Option<u32>,
+    pub channels: Option<u16>,
+}
+
+impl AudioFormat {
+    pub fn new(sample_rate: u32, channels: u16, duration: Option<Duration>, file_size: Option<u64>) -> AudioFormat {
+        let bitrate_kbps = match (duration, file_size) {
+            (Some(duration), Some(size)) if duration.as_secs_f64() > 0.0 => {
+                Some((size as f64 * 8.0 / duration.as_secs_f64() / 1000.0).round() as u32)
+            }
+            _ => None,
+        };
+        AudioFormat {
+            bitrate_kbps,
+            sample_rate: Some(sample_rate),
+            channels: Some(channels),
+        }
+    }
+}
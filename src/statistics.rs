@@ -0,0 +1,75 @@
+use crate::play_history::unix_now;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StatsPeriod {
+    Week,
+    Month,
+    Year,
+    AllTime,
+}
+
+impl StatsPeriod {
+    pub const ALL: [StatsPeriod; 4] = [StatsPeriod::Week, StatsPeriod::Month, StatsPeriod::Year, StatsPeriod::AllTime];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            StatsPeriod::Week => "Past week",
+            StatsPeriod::Month => "Past month",
+            StatsPeriod::Year => "Past year",
+            StatsPeriod::AllTime => "All time",
+        }
+    }
+
+    /// Unix time the period starts at, counting back from now.
+    pub fn since(&self) -> i64 {
+        let days = match self {
+            StatsPeriod::Week => 7,
+            StatsPeriod::Month => 30,
+            StatsPeriod::Year => 365,
+            StatsPeriod::AllTime => return 0,
+        };
+        unix_now() - days * 86_400
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct ListeningTotals {
+    pub plays: usize,
+    pub listened_ms: u64,
+    pub skips: usize,
+}
+
+/// Runs of consecutive days with at least one play.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Streaks {
+    /// Days up to today, or up to yesterday when nothing was played yet today.
+    pub current: usize,
+    pub longest: usize,
+}
+
+impl Streaks {
+    /// `days` are ascending, distinct day numbers.
+    pub fn from_days(days: &[i64], today: i64) -> Streaks {
+        let mut streaks = Streaks::default();
+        let mut run = 0;
+        let mut previous = None;
+        for &day in days {
+            run = if previous == Some(day - 1) { run + 1 } else { 1 };
+            streaks.longest = streaks.longest.max(run);
+            previous = Some(day);
+        }
+        if previous.is_some_and(|last| last >= today - 1) {
+            streaks.current = run;
+        }
+        streaks
+    }
+}
+
+/// "3 h 25 min", or "25 min" under an hour.
+pub fn format_listening_time(listened_ms: u64) -> String {
+    let minutes = listened_ms / 60_000;
+    match minutes / 60 {
+        0 => format!("{} min", minutes),
+        hours => format!("{} h {} min", hours, minutes % 60),
+    }
+}
@@ -0,0 +1,125 @@
+use dioxus::prelude::*;
+
+use crate::{
+    app_context::{DatabaseContext, PlayerContext},
+    file_browser::{FileEntry, song_file},
+    statistics::{StatsPeriod, Streaks, format_listening_time},
+};
+
+const TOP_LIMIT: usize = 10;
+
+fn plays_label(plays: usize) -> String {
+    if plays == 1 { "1 play".to_string() } else { format!("{} plays", plays) }
+}
+
+#[component]
+pub fn statistics_page() -> Element {
+    let db = use_context::<DatabaseContext>();
+    let player_context = use_context::<PlayerContext>();
+    let mut period = use_signal(|| StatsPeriod::Month);
+    let play_history_counter = player_context.play_history_counter;
+    let library_update_counter = player_context.library_update_counter;
+
+    let db_clone = db.clone();
+    let stats = use_memo(move || {
+        let _ = (play_history_counter(), library_update_counter());
+        let db = db_clone.get();
+        let since = period().since();
+        (
+            db.get_listening_totals(since).unwrap_or_default(),
+            db.get_top_artists(since, TOP_LIMIT).unwrap_or_default(),
+            db.get_top_albums(since, TOP_LIMIT).unwrap_or_default(),
+            db.get_top_songs(since, TOP_LIMIT).unwrap_or_default(),
+        )
+    });
+    let streaks = use_memo(move || {
+        let _ = play_history_counter();
+        db.get().get_listening_days().map(|(days, today)| Streaks::from_days(&days, today)).unwrap_or_default()
+    });
+
+    let (totals, artists, albums, songs) = stats();
+
+    rsx! {
+        div { class: "content-section",
+            h2 { "Statistics" }
+            div { class: "common-button library-tabs",
+                for option in StatsPeriod::ALL {
+                    button {
+                        key: "{option.label()}",
+                        class: if period() == option { "active" } else { "" },
+                        onclick: move |_| period.set(option),
+                        "{option.label()}"
+                    }
+                }
+            }
+            div { class: "stats-summary",
+                div { class: "stats-figure",
+                    span { class: "stats-value", "{format_listening_time(totals.listened_ms)}" }
+                    span { class: "stats-label", "listened" }
+                }
+                div { class: "stats-figure",
+                    span { class: "stats-value", "{totals.plays}" }
+                    span { class: "stats-label", "plays" }
+                }
+                div { class: "stats-figure",
+                    span { class: "stats-value", "{totals.skips}" }
+                    span { class: "stats-label", "skips" }
+                }
+                div { class: "stats-figure",
+                    span { class: "stats-value", "{streaks().current}" }
+                    span { class: "stats-label", "day streak" }
+                }
+                div { class: "stats-figure",
+                    span { class: "stats-value", "{streaks().longest}" }
+                    span { class: "stats-label", "longest streak" }
+                }
+            }
+            if totals.plays == 0 {
+                p { class: "stats-empty", "Nothing played in this period yet." }
+            } else {
+                div { class: "stats-columns",
+                    div { class: "stats-column",
+                        h3 { "Top artists" }
+                        for (index, (artist, plays)) in artists.into_iter().enumerate() {
+                            div { class: "stats-row", key: "{index}",
+                                span { "{index + 1}. {artist}" }
+                                span { class: "search-detail", "{plays_label(plays)}" }
+                            }
+                        }
+                    }
+                    div { class: "stats-column",
+                        h3 { "Top albums" }
+                        for (index, (album, plays)) in albums.into_iter().enumerate() {
+                            div { class: "stats-row", key: "{album.id}",
+                                span { "{index + 1}. {album.name} · {album.artist}" }
+                                span { class: "search-detail", "{plays_label(plays)}" }
+                            }
+                        }
+                    }
+                }
+                h3 { "Top songs" }
+                div { class: "item-list",
+                    for (song, plays) in songs {
+                        {
+                            let mut player_context = player_context.clone();
+                            let song_clone = song.clone();
+                            rsx! {
+                                song_file {
+                                    key: "{song.id}",
+                                    file: FileEntry::from_song_view(&song),
+                                    label: rsx! {
+                                        "{song.title}"
+                                        span { class: "search-detail", " · {song.display_artist()} · {plays_label(plays)}" }
+                                    },
+                                    on_click: move |_path| {
+                                        let _ = player_context.queue.write().play_song_instant(&song_clone);
+                                    }
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
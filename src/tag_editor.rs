@@ -0,0 +1,197 @@
+use std::path::Path;
+
+use audiotags::{AudioTag, Id3v2Tag, MimeType, Mp4Tag, Picture, Tag};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum TagField {
+    Title,
+    Artist,
+    AlbumArtist,
+    Album,
+    TrackNumber,
+    TrackTotal,
+    DiscNumber,
+    DiscTotal,
+    Year,
+    Genre,
+}
+
+pub const TAG_FIELDS: [TagField; 10] = [
+    TagField::Title,
+    TagField::Artist,
+    TagField::AlbumArtist,
+    TagField::Album,
+    TagField::TrackNumber,
+    TagField::TrackTotal,
+    TagField::DiscNumber,
+    TagField::DiscTotal,
+    TagField::Year,
+    TagField::Genre,
+];
+
+impl TagField {
+    pub fn label(&self) -> &'static str {
+        match self {
+            TagField::Title => "Title",
+            TagField::Artist => "Artist",
+            TagField::AlbumArtist => "Album artist",
+            TagField::Album => "Album",
+            TagField::TrackNumber => "Track",
+            TagField::TrackTotal => "Track total",
+            TagField::DiscNumber => "Disc",
+            TagField::DiscTotal => "Disc total",
+            TagField::Year => "Year",
+            TagField::Genre => "Genre",
+        }
+    }
+
+    /// Fields that differ between the songs of an album, not offered when editing several files at once.
+    pub fn is_per_track(&self) -> bool {
+        matches!(self, TagField::Title | TagField::TrackNumber)
+    }
+
+    pub fn read(&self, tag: &dyn AudioTag) -> Option<String> {
+        match self {
+            TagField::Title => tag.title().map(str::to_string),
+            TagField::Artist => tag.artist().map(str::to_string),
+            TagField::AlbumArtist => tag.album_artist().map(str::to_string),
+            TagField::Album => tag.album_title().map(str::to_string),
+            TagField::TrackNumber => tag.track_number().map(|n| n.to_string()),
+            TagField::TrackTotal => tag.total_tracks().map(|n| n.to_string()),
+            TagField::DiscNumber => tag.disc_number().map(|n| n.to_string()),
+            TagField::DiscTotal => tag.total_discs().map(|n| n.to_string()),
+            TagField::Year => tag.year().or_else(|| tag.date().map(|date| date.year)).map(|year| year.to_string()),
+            TagField::Genre => tag.genre().map(str::to_string),
+        }
+    }
+
+    /// Sets the field, or removes it when `value` is blank.
+    pub fn write(&self, tag: &mut dyn AudioTag, value: &str) -> Result<(), String> {
+        let value = value.trim();
+        let number = || value.parse::<u16>().map_err(|_| format!("{} must be a number", self.label()));
+        if value.is_empty() {
+            match self {
+                TagField::Title => tag.remove_title(),
+                TagField::Artist => tag.remove_artist(),
+                TagField::AlbumArtist => tag.remove_album_artist(),
+                TagField::Album => tag.remove_album_title(),
+                TagField::TrackNumber => tag.remove_track_number(),
+                TagField::TrackTotal => tag.remove_total_tracks(),
+                TagField::DiscNumber => tag.remove_disc_number(),
+                TagField::DiscTotal => tag.remove_total_discs(),
+                TagField::Year => {
+                    tag.remove_year();
+                    tag.remove_date();
+                }
+                TagField::Genre => tag.remove_genre(),
+            }
+            return Ok(());
+        }
+        match self {
+            TagField::Title => tag.set_title(value),
+            TagField::Artist => tag.set_artist(value),
+            TagField::AlbumArtist => tag.set_album_artist(value),
+            TagField::Album => tag.set_album_title(value),
+            TagField::TrackNumber => tag.set_track_number(number()?),
+            TagField::TrackTotal => tag.set_total_tracks(number()?),
+            TagField::DiscNumber => tag.set_disc_number(number()?),
+            TagField::DiscTotal => tag.set_total_discs(number()?),
+            TagField::Year => {
+                //a stale recording date would win over the new year when read back
+                tag.remove_date();
+                tag.set_year(value.parse().map_err(|_| "Year must be a number".to_string())?);
+            }
+            TagField::Genre => tag.set_genre(value),
+        }
+        Ok(())
+    }
+}
+
+/// An image file to embed as the album cover.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CoverImage {
+    pub data: Vec<u8>,
+    pub mime_type: MimeType,
+}
+
+impl CoverImage {
+    pub fn load(path: &str) -> Result<CoverImage, String> {
+        let mime_type = match extension(path).as_str() {
+            "jpg" | "jpeg" => MimeType::Jpeg,
+            "png" => MimeType::Png,
+            _ => return Err(format!("{} is not a JPEG or PNG image", path)),
+        };
+        let data = std::fs::read(path).map_err(|e| format!("Failed to read {}: {}", path, e))?;
+        Ok(CoverImage { data, mime_type })
+    }
+}
+
+fn extension(path: &str) -> String {
+    Path::new(path)
+        .extension()
+        .and_then(|e| e.to_str())
+        .unwrap_or("")
+        .to_lowercase()
+}
+
+/// Whether `write_tags` can write to the file, there is no tag writer for Ogg files.
+pub fn can_edit_tags(path: &str) -> bool {
+    matches!(extension(path).as_str(), "mp3" | "aac" | "wav" | "flac" | "m4a" | "mp4" | "m4b")
+}
+
+/// The file's tag, or an empty one when it has none yet. audiotags only opens ID3 in .mp3 files,
+/// so ID3 tags are read with the id3 crate and wrapped.
+fn read_tag(path: &str) -> Result<Box<dyn AudioTag + Send + Sync>, String> {
+    match extension(path).as_str() {
+        "mp3" | "aac" | "wav" => {
+            let tag = id3::no_tag_ok(id3::Tag::read_from_path(path)).map_err(|e| e.to_string())?;
+            Ok(Box::new(Id3v2Tag::from(tag.unwrap_or_default())))
+        }
+        "flac" => Tag::new().read_from_path(path).map_err(|e| e.to_string()),
+        "m4a" | "mp4" | "m4b" => match Tag::new().read_from_path(path) {
+            Err(audiotags::Error::Mp4TagError(e)) if matches!(e.kind, mp4ameta::ErrorKind::NoTag) => Ok(Box::new(Mp4Tag::new())),
+            result => result.map_err(|e| e.to_string()),
+        },
+        extension => Err(format!("Tags of .{} files can't be edited", extension)),
+    }
+}
+
+fn write_tag(path: &str, mut tag: Box<dyn AudioTag + Send + Sync>) -> Result<(), String> {
+    match extension(path).as_str() {
+        "mp3" | "aac" | "wav" => {
+            //id3 puts the tag in a chunk of WAV files and in front of the others, keeping the version it was read with
+            let tag: id3::Tag = tag.into();
+            tag.write_to_path(path, tag.version()).map_err(|e| e.to_string())
+        }
+        _ => tag.write_to_path(path).map_err(|e| e.to_string()),
+    }
+}
+
+/// Field values shared by every file, `None` where they differ or a file could not be read.
+pub fn read_common_values(paths: &[String]) -> Vec<(TagField, Option<String>)> {
+    let tags: Vec<_> = paths.iter().map(|path| read_tag(path).ok()).collect();
+    TAG_FIELDS
+        .iter()
+        .map(|field| {
+            let mut values = tags.iter().map(|tag| tag.as_ref().map(|tag| field.read(tag.as_ref()).unwrap_or_default()));
+            let first = values.next().flatten();
+            let common = match first {
+                Some(first) if values.all(|value| value.as_ref() == Some(&first)) => Some(first),
+                _ => None,
+            };
+            (*field, common)
+        })
+        .collect()
+}
+
+/// Writes the changed fields, and the cover when given, into the file's tag.
+pub fn write_tags(path: &str, changes: &[(TagField, String)], cover: Option<&CoverImage>) -> Result<(), String> {
+    let mut tag = read_tag(path)?;
+    for (field, value) in changes {
+        field.write(tag.as_mut(), value)?;
+    }
+    if let Some(cover) = cover {
+        tag.set_album_cover(Picture::new(&cover.data, cover.mime_type));
+    }
+    write_tag(path, tag)
+}
@@ -0,0 +1,130 @@
+use std::collections::HashSet;
+
+use dioxus::prelude::*;
+
+use crate::{
+    app_context::{DatabaseContext, PlayerContext},
+    tag_editor::{CoverImage, TagField, read_common_values, write_tags},
+};
+
+/// Edits the tags of one or more files. Fields left untouched keep each file's own value.
+#[component]
+pub fn tag_editor_dialog(paths: Vec<String>, show: Signal<bool>) -> Element {
+    let db = use_context::<DatabaseContext>();
+    let player_context = use_context::<PlayerContext>();
+    let batch = paths.len() > 1;
+
+    let initial = use_hook({
+        let paths = paths.clone();
+        move || read_common_values(&paths)
+    });
+    let mut values = use_signal({
+        let initial = initial.clone();
+        move || initial.iter().map(|(field, value)| (*field, value.clone().unwrap_or_default())).collect::<Vec<_>>()
+    });
+    let mut edited = use_signal(HashSet::<TagField>::new);
+    let mut cover_path = use_signal(String::new);
+
+    let mut save = {
+        let paths = paths.clone();
+        move || {
+            let mut ctx = player_context.clone();
+            let changes: Vec<(TagField, String)> = values()
+                .into_iter()
+                .filter(|(field, _)| edited.peek().contains(field))
+                .collect();
+            let cover = match cover_path().trim() {
+                "" => None,
+                path => match CoverImage::load(path) {
+                    Ok(cover) => Some(cover),
+                    Err(e) => {
+                        ctx.notify(e);
+                        return;
+                    }
+                },
+            };
+            if changes.is_empty() && cover.is_none() {
+                show.set(false);
+                return;
+            }
+
+            let mut failed = 0;
+            for path in &paths {
+                let result = write_tags(path, &changes, cover.as_ref())
+                    .and_then(|_| db.get().add_song_by_path(path).map(|_| ()).map_err(|e| format!("{:?}", e)));
+                if let Err(e) = result {
+                    eprintln!("Failed to save tags of {}: {}", path, e);
+                    if paths.len() == 1 {
+                        ctx.notify(format!("Failed to save tags: {}", e));
+                    }
+                    failed += 1;
+                }
+            }
+            if paths.len() > 1 {
+                ctx.notify(match failed {
+                    0 => format!("Saved tags of {} songs", paths.len()),
+                    failed => format!("Saved tags of {} songs, {} failed", paths.len() - failed, failed),
+                });
+            }
+            *ctx.library_update_counter.write() += 1;
+            ctx.selected_songs.set(Vec::new());
+            show.set(false);
+        }
+    };
+
+    rsx! {
+        div {
+            class: "context-menu-overlay",
+            onclick: move |_| show.set(false),
+
+            div {
+                class: "context-menu settings-dialog",
+                onclick: move |evt: Event<MouseData>| evt.stop_propagation(),
+
+                h3 { class: "dialog-title",
+                    if batch { "EDIT {paths.len()} SONGS" } else { "EDIT TAGS" }
+                }
+                div { class: "settings-section",
+                    for (index, (field, value)) in values().into_iter().enumerate() {
+                        if !(batch && field.is_per_track()) {
+                            div { class: "settings-row",
+                                key: "{index}",
+                                span { "{field.label()}" }
+                                input {
+                                    r#type: "text",
+                                    value: "{value}",
+                                    placeholder: if batch && initial[index].1.is_none() && !edited().contains(&field) { "Multiple values" } else { "" },
+                                    oninput: move |evt: Event<FormData>| {
+                                        values.write()[index].1 = evt.value();
+                                        edited.write().insert(field);
+                                    },
+                                }
+                            }
+                        }
+                    }
+                    div { class: "settings-row",
+                        span { "Cover art" }
+                        input {
+                            r#type: "text",
+                            value: "{cover_path}",
+                            placeholder: "Path to a JPEG or PNG image",
+                            oninput: move |evt: Event<FormData>| cover_path.set(evt.value()),
+                        }
+                    }
+                }
+                div {
+                    class: "common-button",
+                    style: "display: flex; gap: 10px; margin-top: 10px;",
+                    button {
+                        onclick: move |_| save(),
+                        "Save"
+                    }
+                    button {
+                        onclick: move |_| show.set(false),
+                        "Cancel"
+                    }
+                }
+            }
+        }
+    }
+}
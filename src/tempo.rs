@@ -0,0 +1,324 @@
+use std::{collections::VecDeque, sync::{Arc, atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering}}, time::Duration};
+
+use rodio::{ChannelCount, SampleRate, Source, source::SeekError};
+
+pub const MIN_SPEED: f32 = 0.5;
+pub const MAX_SPEED: f32 = 3.0;
+
+const WINDOW_MS: u64 = 40;
+const SEARCH_MS: u64 = 10;
+const SEARCH_STRIDE: usize = 4; //correlation is only checked on every n-th frame to keep the search cheap
+
+/// Playback speed shared between the audio controller and every track it decodes.
+#[derive(Clone)]
+pub struct SpeedControl {
+    speed: Arc<AtomicU32>,
+    preserve_pitch: Arc<AtomicBool>,
+}
+
+impl Default for SpeedControl {
+    fn default() -> Self {
+        SpeedControl::new()
+    }
+}
+
+impl SpeedControl {
+    pub fn new() -> Self {
+        SpeedControl {
+            speed: Arc::new(AtomicU32::new(1.0f32.to_bits())),
+            preserve_pitch: Arc::new(AtomicBool::new(true)),
+        }
+    }
+
+    pub fn speed(&self) -> f32 {
+        f32::from_bits(self.speed.load(Ordering::Relaxed))
+    }
+
+    pub fn set_speed(&self, speed: f32) {
+        self.speed.store(speed.clamp(MIN_SPEED, MAX_SPEED).to_bits(), Ordering::Relaxed);
+    }
+
+    pub fn preserve_pitch(&self) -> bool {
+        self.preserve_pitch.load(Ordering::Relaxed)
+    }
+
+    pub fn set_preserve_pitch(&self, preserve_pitch: bool) {
+        self.preserve_pitch.store(preserve_pitch, Ordering::Relaxed);
+    }
+}
+
+/// Position within the track (not wall clock time), updated from the audio thread.
+#[derive(Clone)]
+pub struct PlaybackPosition(Arc<AtomicU64>);
+
+impl PlaybackPosition {
+    pub fn get(&self) -> Duration {
+        Duration::from_millis(self.0.load(Ordering::Relaxed))
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum StretchMode {
+    Passthrough,
+    Resample,
+    Wsola,
+}
+
+/// Changes the tempo of a decoded track, either by resampling (the pitch follows the speed)
+/// or with WSOLA time stretching (the pitch is kept).
+pub struct TempoSource<S: Source> {
+    input: S,
+    control: SpeedControl,
+    position: PlaybackPosition,
+    channels: usize,
+    sample_rate: SampleRate,
+    mode: StretchMode,
+    buffer: VecDeque<f32>,
+    buffer_start: u64,
+    input_done: bool,
+    read_pos: f64,
+    pending: VecDeque<f32>,
+    window: Vec<f32>,
+    overlap: Vec<f32>,
+    previous_segment: Option<u64>,
+}
+
+impl<S: Source> TempoSource<S> {
+    pub fn new(input: S, control: SpeedControl) -> (Self, PlaybackPosition) {
+        let position = PlaybackPosition(Arc::new(AtomicU64::new(0)));
+        let channels = input.channels().max(1) as usize;
+        let sample_rate = input.sample_rate().max(1);
+        let window_len = (sample_rate as u64 * WINDOW_MS / 1000) as usize & !1;
+        let window = (0..window_len)
+            .map(|i| 0.5 - 0.5 * (2.0 * std::f32::consts::PI * i as f32 / window_len as f32).cos())
+            .collect();
+        let source = TempoSource {
+            input,
+            control,
+            position: position.clone(),
+            channels,
+            sample_rate,
+            mode: StretchMode::Passthrough,
+            buffer: VecDeque::new(),
+            buffer_start: 0,
+            input_done: false,
+            read_pos: 0.0,
+            pending: VecDeque::new(),
+            window,
+            overlap: vec![0.0; window_len * channels],
+            previous_segment: None,
+        };
+        (source, position)
+    }
+
+    fn window_len(&self) -> usize {
+        self.window.len()
+    }
+
+    fn search_len(&self) -> u64 {
+        self.sample_rate as u64 * SEARCH_MS / 1000
+    }
+
+    fn buffered_frames(&self) -> u64 {
+        (self.buffer.len() / self.channels) as u64
+    }
+
+    /// Pulls frames from the input until `frame_end` (exclusive) is buffered or the input runs out.
+    fn ensure(&mut self, frame_end: u64) -> bool {
+        while self.buffer_start + self.buffered_frames() < frame_end && !self.input_done {
+            for _ in 0..self.channels {
+                match self.input.next() {
+                    Some(sample) => self.buffer.push_back(sample),
+                    None => {
+                        self.input_done = true;
+                        break;
+                    }
+                }
+            }
+        }
+        let whole_frames = self.buffer.len() / self.channels * self.channels;
+        self.buffer.truncate(whole_frames);
+        self.buffer_start + self.buffered_frames() >= frame_end
+    }
+
+    fn sample(&self, frame: u64, channel: usize) -> f32 {
+        if frame < self.buffer_start {
+            return 0.0;
+        }
+        let index = (frame - self.buffer_start) as usize * self.channels + channel;
+        self.buffer.get(index).copied().unwrap_or(0.0)
+    }
+
+    fn drop_before(&mut self, frame: u64) {
+        if frame <= self.buffer_start {
+            return;
+        }
+        let frames = (frame - self.buffer_start).min(self.buffered_frames());
+        self.buffer.drain(..frames as usize * self.channels);
+        self.buffer_start += frames;
+    }
+
+    fn set_mode(&mut self, mode: StretchMode) {
+        if self.mode == mode {
+            return;
+        }
+        self.mode = mode;
+        self.overlap.iter_mut().for_each(|s| *s = 0.0);
+        self.previous_segment = None;
+    }
+
+    fn fill_pending(&mut self) -> bool {
+        let speed = self.control.speed() as f64;
+        let mode = if (speed - 1.0).abs() < 0.001 {
+            StretchMode::Passthrough
+        } else if self.control.preserve_pitch() && self.window_len() > 0 {
+            StretchMode::Wsola
+        } else {
+            StretchMode::Resample
+        };
+        self.set_mode(mode);
+        let produced = match mode {
+            StretchMode::Passthrough => self.fill_passthrough(),
+            StretchMode::Resample => self.fill_resample(speed),
+            StretchMode::Wsola => self.fill_wsola(speed),
+        };
+        let position_ms = self.read_pos / self.sample_rate as f64 * 1000.0;
+        self.position.0.store(position_ms as u64, Ordering::Relaxed);
+        produced
+    }
+
+    fn fill_passthrough(&mut self) -> bool {
+        let frame = self.read_pos as u64;
+        if !self.ensure(frame + 1) {
+            return false;
+        }
+        for channel in 0..self.channels {
+            self.pending.push_back(self.sample(frame, channel));
+        }
+        self.read_pos = (frame + 1) as f64;
+        self.drop_before(frame + 1);
+        true
+    }
+
+    fn fill_resample(&mut self, speed: f64) -> bool {
+        let frame = self.read_pos as u64;
+        if !self.ensure(frame + 1) {
+            return false;
+        }
+        let has_next = self.ensure(frame + 2);
+        let frac = (self.read_pos - frame as f64) as f32;
+        for channel in 0..self.channels {
+            let current = self.sample(frame, channel);
+            let next = if has_next { self.sample(frame + 1, channel) } else { current };
+            self.pending.push_back(current + (next - current) * frac);
+        }
+        self.read_pos += speed;
+        self.drop_before(self.read_pos as u64);
+        true
+    }
+
+    fn fill_wsola(&mut self, speed: f64) -> bool {
+        let window_len = self.window_len() as u64;
+        let hop = window_len / 2;
+        let search = self.search_len();
+        let target = self.read_pos as u64;
+        self.ensure(target + search + window_len);
+        if self.buffer_start + self.buffered_frames() <= target {
+            return false;
+        }
+
+        let segment_start = match self.previous_segment {
+            Some(previous) => self.best_segment(previous + hop, target, search, hop),
+            None => target,
+        };
+
+        for i in 0..window_len as usize {
+            let gain = self.window[i];
+            for channel in 0..self.channels {
+                self.overlap[i * self.channels + channel] += self.sample(segment_start + i as u64, channel) * gain;
+            }
+        }
+        let hop_samples = hop as usize * self.channels;
+        self.pending.extend(self.overlap.drain(..hop_samples));
+        self.overlap.extend(std::iter::repeat_n(0.0, hop_samples));
+
+        self.previous_segment = Some(segment_start);
+        self.read_pos += hop as f64 * speed;
+        let keep_from = (self.read_pos as u64).saturating_sub(search).min(segment_start + hop);
+        self.drop_before(keep_from);
+        true
+    }
+
+    /// Finds the segment around `target` that lines up best with the natural continuation
+    /// of the previously copied segment, so the overlapping halves add up without phase jumps.
+    fn best_segment(&self, natural: u64, target: u64, search: u64, overlap_len: u64) -> u64 {
+        let first = target.saturating_sub(search).max(self.buffer_start);
+        let last = target + search;
+        let mut best = target.max(self.buffer_start);
+        let mut best_score = f32::MIN;
+        let mut candidate = first;
+        while candidate <= last {
+            let mut score = 0.0;
+            let mut i = 0;
+            while i < overlap_len {
+                let mut a = 0.0;
+                let mut b = 0.0;
+                for channel in 0..self.channels {
+                    a += self.sample(natural + i, channel);
+                    b += self.sample(candidate + i, channel);
+                }
+                score += a * b;
+                i += SEARCH_STRIDE as u64;
+            }
+            if score > best_score {
+                best_score = score;
+                best = candidate;
+            }
+            candidate += SEARCH_STRIDE as u64;
+        }
+        best
+    }
+}
+
+impl<S: Source> Iterator for TempoSource<S> {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        if self.pending.is_empty() && !self.fill_pending() {
+            return None;
+        }
+        self.pending.pop_front()
+    }
+}
+
+impl<S: Source> Source for TempoSource<S> {
+    fn current_span_len(&self) -> Option<usize> {
+        None
+    }
+
+    fn channels(&self) -> ChannelCount {
+        self.channels as ChannelCount
+    }
+
+    fn sample_rate(&self) -> SampleRate {
+        self.sample_rate
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        self.input.total_duration()
+    }
+
+    fn try_seek(&mut self, pos: Duration) -> Result<(), SeekError> {
+        self.input.try_seek(pos)?;
+        let frame = (pos.as_secs_f64() * self.sample_rate as f64) as u64;
+        self.buffer.clear();
+        self.buffer_start = frame;
+        self.input_done = false;
+        self.read_pos = frame as f64;
+        self.pending.clear();
+        self.overlap.iter_mut().for_each(|s| *s = 0.0);
+        self.previous_segment = None;
+        self.position.0.store(pos.as_millis() as u64, Ordering::Relaxed);
+        Ok(())
+    }
+}
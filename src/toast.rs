@@ -0,0 +1,40 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use dioxus::prelude::*;
+
+use crate::app_context::PlayerContext;
+
+pub const TOAST_DURATION_MS: u64 = 5000;
+
+static NEXT_TOAST_ID: AtomicU64 = AtomicU64::new(0);
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Toast {
+    pub id: u64,
+    pub message: String,
+}
+
+impl Toast {
+    pub fn new(message: String) -> Self {
+        Toast { id: NEXT_TOAST_ID.fetch_add(1, Ordering::Relaxed), message }
+    }
+}
+
+#[component]
+pub fn toast_list() -> Element {
+    let player_context = use_context::<PlayerContext>();
+    let mut toasts = player_context.toasts;
+
+    rsx! {
+        div { class: "toast-list",
+            for toast in toasts() {
+                div {
+                    key: "{toast.id}",
+                    class: "toast",
+                    onclick: move |_| toasts.write().retain(|t| t.id != toast.id),
+                    "{toast.message}"
+                }
+            }
+        }
+    }
+}